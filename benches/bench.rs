@@ -29,6 +29,22 @@ fn bench_next_id_single(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_next_ids_batch(c: &mut Criterion) {
+    let sf = Snowflake::builder()
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()
+        .unwrap();
+
+    let mut group = c.benchmark_group("next_ids");
+    group.throughput(Throughput::Elements(10_000));
+    group.bench_function("loop_next_id", |b| {
+        b.iter(|| (0..10_000).map(|_| sf.next_id()).collect::<Vec<_>>())
+    });
+    group.bench_function("next_ids", |b| b.iter(|| sf.next_ids(10_000)));
+    group.finish();
+}
+
 fn bench_next_id_concurrent(c: &mut Criterion) {
     let mut group = c.benchmark_group("next_id/concurrent");
     for num_threads in [2, 4, 8, 16] {
@@ -65,6 +81,40 @@ fn bench_next_id_concurrent(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_next_id_sharded_concurrent(c: &mut Criterion) {
+    let mut group = c.benchmark_group("next_id/sharded_concurrent");
+    for num_threads in [2, 4, 8, 16] {
+        group.throughput(Throughput::Elements(num_threads * 1000));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_threads),
+            &num_threads,
+            |b, &num_threads| {
+                let sf = Snowflake::builder()
+                    .machine_id(&|| Ok(1))
+                    .data_center_id(&|| Ok(1))
+                    .finalize_sharded(4)
+                    .unwrap();
+                b.iter(|| {
+                    let handles: Vec<_> = (0..num_threads)
+                        .map(|_| {
+                            let sf = sf.clone();
+                            std::thread::spawn(move || {
+                                for _ in 0..1000 {
+                                    let _ = sf.next_id();
+                                }
+                            })
+                        })
+                        .collect();
+                    for h in handles {
+                        h.join().unwrap();
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
 fn bench_decompose(c: &mut Criterion) {
     let sf = Snowflake::builder()
         .machine_id(&|| Ok(1))
@@ -152,7 +202,9 @@ criterion_group!(
     snowflake_perf,
     bench_new,
     bench_next_id_single,
+    bench_next_ids_batch,
     bench_next_id_concurrent,
+    bench_next_id_sharded_concurrent,
     bench_next_id_cas_strategy,
     bench_decompose,
     bench_encodings,
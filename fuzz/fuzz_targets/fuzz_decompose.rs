@@ -1,11 +1,23 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
-use snowflake_me::DecomposedSnowflake;
+use snowflake_me::{DecomposedSnowflake, Layout, SnowflakeId};
 
 fuzz_target!(|data: &[u8]| {
     if data.len() >= 8 {
         let id = u64::from_be_bytes(data[..8].try_into().unwrap());
         let id = id & ((1u64 << 63) - 1); // ensure 63 bits
         let _ = DecomposedSnowflake::decompose(id, 41, 12, 5, 5);
+
+        // Any valid layout must round-trip every ID within its width.
+        if let [t, s, dc, m, ..] = data[8..] {
+            if let Ok(layout) = Layout::try_new(t, s, dc, m) {
+                let id = SnowflakeId::new(id & layout.max_id());
+                let parts = layout.decompose(id);
+                let recomposed =
+                    layout.compose(parts.time, parts.sequence, parts.data_center_id, parts.machine_id);
+                assert_eq!(recomposed, Some(id));
+                assert_eq!(layout.from_packed_bytes(&layout.packed_bytes(id)).ok(), Some(id));
+            }
+        }
     }
 });
@@ -7,32 +7,157 @@
 // except according to those terms.
 
 use crate::Snowflake;
-use crate::clock::ClockDriftStrategy;
+use crate::clock::{ClockDriftStrategy, ClockMode, SequenceStart, WaitPolicy};
 use crate::error::{BoxDynError, Error};
+use crate::layout::Layout;
+use crate::persist::{Persistence, TimestampStore};
+use crate::preset::Preset;
+use crate::sharded::ShardedSnowflake;
 use crate::snowflake::SharedSnowflake;
+use crate::time::{TimeSource, TimeUnit};
+use crate::worker::{LeaseKeeper, WorkerIdRegistry, WorkerIdResolver};
 use core::sync::atomic::AtomicU64;
 
 extern crate alloc;
+use alloc::borrow::Cow;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
-#[cfg(feature = "std")]
-use crate::time::current_millis;
+/// Default start time: 2022-01-01 00:00:00 UTC in milliseconds since the Unix epoch.
+const DEFAULT_START_TIME: i64 = 1_640_995_200_000;
+
+/// Default write-ahead step for the timestamp high-water mark, in milliseconds.
+const DEFAULT_PERSIST_INTERVAL_MS: i64 = 1_000;
+
+/// Default interval between worker ID lease renewals, in milliseconds.
+const DEFAULT_LEASE_RENEW_INTERVAL_MS: i64 = 1_000;
 
 /// A builder for constructing the [`Snowflake`] generator.
 ///
-/// Use [`Snowflake::builder()`] to create an instance.
-pub struct Builder<'a> {
+/// Use [`Snowflake::builder()`] to create an instance. It holds the ID closures
+/// by reference, so they can be written inline; use [`Builder::owned`] for a
+/// builder that owns them and can be stored or sent to another thread.
+#[derive(Clone)]
+pub struct Builder<'a, C = BorrowedCallbacks<'a>> {
     start_time: Option<i64>,
-    machine_id: Option<&'a dyn Fn() -> Result<u16, BoxDynError>>,
-    data_center_id: Option<&'a dyn Fn() -> Result<u16, BoxDynError>>,
-    check_machine_id: Option<&'a dyn Fn(u16) -> bool>,
-    check_data_center_id: Option<&'a dyn Fn(u16) -> bool>,
+    callbacks: C,
     bit_len_time: u8,
     bit_len_sequence: u8,
     bit_len_data_center_id: u8,
     bit_len_machine_id: u8,
+    layout: Layout,
+    fields: Vec<(Cow<'a, str>, u64)>,
     clock_drift_strategy: ClockDriftStrategy,
     max_clock_drift_ms: Option<i64>,
+    max_borrow_ahead_ms: i64,
+    wait_policy: WaitPolicy,
+    max_wait_ms: Option<i64>,
+    sequence_start: SequenceStart,
+    time_source: Option<Arc<dyn TimeSource>>,
+    clock_mode: ClockMode,
+    time_unit: TimeUnit,
+    timestamp_store: Option<Arc<dyn TimestampStore>>,
+    persist_interval_ms: i64,
+    worker_id_resolver: Option<Arc<dyn WorkerIdResolver>>,
+    worker_id_registry: Option<Arc<dyn WorkerIdRegistry>>,
+    lease_renew_interval_ms: i64,
+}
+
+/// A [`Builder`] that owns its closures, so it is `Send + 'static`.
+///
+/// It can be kept in a configuration struct, built by a function that creates
+/// the closures, or sent to another thread before being finalized.
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use snowflake_me::{Builder, OwnedBuilder};
+///
+/// fn configure(worker: u16) -> OwnedBuilder {
+///     Builder::owned()
+///         .machine_id(move || Ok(worker))
+///         .data_center_id(|| Ok(1))
+/// }
+///
+/// let builder = configure(7);
+/// let sf = std::thread::spawn(move || builder.finalize()).join().unwrap()?;
+/// assert_eq!(sf.decompose(sf.next_id()?).machine_id, 7);
+/// # }
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+pub type OwnedBuilder = Builder<'static, OwnedCallbacks>;
+
+/// ID closures and checks of a [`Builder`] with the default, borrowed storage.
+#[derive(Clone, Copy, Default)]
+pub struct BorrowedCallbacks<'a> {
+    machine_id: Option<&'a dyn Fn() -> Result<u16, BoxDynError>>,
+    data_center_id: Option<&'a dyn Fn() -> Result<u16, BoxDynError>>,
+    check_machine_id: Option<&'a dyn Fn(u16) -> bool>,
+    check_data_center_id: Option<&'a dyn Fn(u16) -> bool>,
+}
+
+/// ID closures and checks of an [`OwnedBuilder`].
+#[derive(Clone, Default)]
+pub struct OwnedCallbacks {
+    machine_id: Option<Arc<dyn Fn() -> Result<u16, BoxDynError> + Send + Sync>>,
+    data_center_id: Option<Arc<dyn Fn() -> Result<u16, BoxDynError> + Send + Sync>>,
+    check_machine_id: Option<Arc<dyn Fn(u16) -> bool + Send + Sync>>,
+    check_data_center_id: Option<Arc<dyn Fn(u16) -> bool + Send + Sync>>,
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for super::BorrowedCallbacks<'_> {}
+    impl Sealed for super::OwnedCallbacks {}
+}
+
+/// How a [`Builder`] stores its ID closures: [`BorrowedCallbacks`] or [`OwnedCallbacks`].
+///
+/// This trait is sealed.
+pub trait Callbacks: sealed::Sealed {
+    #[doc(hidden)]
+    fn machine_id(&self) -> Option<&dyn Fn() -> Result<u16, BoxDynError>>;
+    #[doc(hidden)]
+    fn data_center_id(&self) -> Option<&dyn Fn() -> Result<u16, BoxDynError>>;
+    #[doc(hidden)]
+    fn check_machine_id(&self) -> Option<&dyn Fn(u16) -> bool>;
+    #[doc(hidden)]
+    fn check_data_center_id(&self) -> Option<&dyn Fn(u16) -> bool>;
+}
+
+impl Callbacks for BorrowedCallbacks<'_> {
+    fn machine_id(&self) -> Option<&dyn Fn() -> Result<u16, BoxDynError>> {
+        self.machine_id
+    }
+
+    fn data_center_id(&self) -> Option<&dyn Fn() -> Result<u16, BoxDynError>> {
+        self.data_center_id
+    }
+
+    fn check_machine_id(&self) -> Option<&dyn Fn(u16) -> bool> {
+        self.check_machine_id
+    }
+
+    fn check_data_center_id(&self) -> Option<&dyn Fn(u16) -> bool> {
+        self.check_data_center_id
+    }
+}
+
+impl Callbacks for OwnedCallbacks {
+    fn machine_id(&self) -> Option<&dyn Fn() -> Result<u16, BoxDynError>> {
+        self.machine_id.as_deref().map(|f| f as _)
+    }
+
+    fn data_center_id(&self) -> Option<&dyn Fn() -> Result<u16, BoxDynError>> {
+        self.data_center_id.as_deref().map(|f| f as _)
+    }
+
+    fn check_machine_id(&self) -> Option<&dyn Fn(u16) -> bool> {
+        self.check_machine_id.as_deref().map(|f| f as _)
+    }
+
+    fn check_data_center_id(&self) -> Option<&dyn Fn(u16) -> bool> {
+        self.check_data_center_id.as_deref().map(|f| f as _)
+    }
 }
 
 impl Default for Builder<'_> {
@@ -41,34 +166,40 @@ impl Default for Builder<'_> {
     }
 }
 
-impl<'a> Builder<'a> {
-    /// Construct a new builder with default configuration.
-    #[must_use]
-    pub fn new() -> Self {
+impl<'a, C: Default> Builder<'a, C> {
+    fn with_callbacks() -> Self {
         Self {
             start_time: None,
-            machine_id: None,
-            data_center_id: None,
-            check_machine_id: None,
-            check_data_center_id: None,
-            bit_len_time: 41,
-            bit_len_sequence: 12,
-            bit_len_data_center_id: 5,
-            bit_len_machine_id: 5,
+            callbacks: C::default(),
+            bit_len_time: Layout::DEFAULT.bit_len_time(),
+            bit_len_sequence: Layout::DEFAULT.bit_len_sequence(),
+            bit_len_data_center_id: Layout::DEFAULT.bit_len_data_center_id(),
+            bit_len_machine_id: Layout::DEFAULT.bit_len_machine_id(),
+            layout: Layout::DEFAULT,
+            fields: Vec::new(),
             clock_drift_strategy: ClockDriftStrategy::default(),
             max_clock_drift_ms: None,
+            max_borrow_ahead_ms: 0,
+            wait_policy: WaitPolicy::default(),
+            max_wait_ms: None,
+            sequence_start: SequenceStart::default(),
+            time_source: None,
+            clock_mode: ClockMode::default(),
+            time_unit: TimeUnit::default(),
+            timestamp_store: None,
+            persist_interval_ms: DEFAULT_PERSIST_INTERVAL_MS,
+            worker_id_resolver: None,
+            worker_id_registry: None,
+            lease_renew_interval_ms: DEFAULT_LEASE_RENEW_INTERVAL_MS,
         }
     }
+}
 
-    /// Set the start time in milliseconds since the Unix epoch.
-    ///
-    /// When the `std` feature is enabled, [`Builder::finalize`] fails with
-    /// [`Error::StartTimeAheadOfCurrentTime`](crate::Error::StartTimeAheadOfCurrentTime)
-    /// if this value is later than the current system time.
+impl<'a> Builder<'a> {
+    /// Construct a new builder with default configuration.
     #[must_use]
-    pub fn start_time(mut self, start_time: i64) -> Self {
-        self.start_time = Some(start_time);
-        self
+    pub fn new() -> Self {
+        Self::with_callbacks()
     }
 
     /// Set the machine ID.
@@ -76,7 +207,7 @@ impl<'a> Builder<'a> {
     /// If the provided closure returns an error, [`Builder::finalize`] will fail.
     #[must_use]
     pub fn machine_id(mut self, machine_id: &'a dyn Fn() -> Result<u16, BoxDynError>) -> Self {
-        self.machine_id = Some(machine_id);
+        self.callbacks.machine_id = Some(machine_id);
         self
     }
 
@@ -88,7 +219,7 @@ impl<'a> Builder<'a> {
         mut self,
         data_center_id: &'a dyn Fn() -> Result<u16, BoxDynError>,
     ) -> Self {
-        self.data_center_id = Some(data_center_id);
+        self.callbacks.data_center_id = Some(data_center_id);
         self
     }
 
@@ -97,7 +228,7 @@ impl<'a> Builder<'a> {
     /// If the function returns `false`, [`Builder::finalize`] will fail.
     #[must_use]
     pub fn check_machine_id(mut self, check_machine_id: &'a dyn Fn(u16) -> bool) -> Self {
-        self.check_machine_id = Some(check_machine_id);
+        self.callbacks.check_machine_id = Some(check_machine_id);
         self
     }
 
@@ -106,9 +237,116 @@ impl<'a> Builder<'a> {
     /// If the function returns `false`, [`Builder::finalize`] will fail.
     #[must_use]
     pub fn check_data_center_id(mut self, check_data_center_id: &'a dyn Fn(u16) -> bool) -> Self {
-        self.check_data_center_id = Some(check_data_center_id);
+        self.callbacks.check_data_center_id = Some(check_data_center_id);
         self
     }
+}
+
+impl Builder<'static, OwnedCallbacks> {
+    /// Construct a new [`OwnedBuilder`] with default configuration.
+    #[must_use]
+    pub fn owned() -> Self {
+        Self::with_callbacks()
+    }
+
+    /// Set the machine ID.
+    ///
+    /// If the provided closure returns an error, [`Builder::finalize`] will fail.
+    #[must_use]
+    pub fn machine_id(
+        mut self,
+        machine_id: impl Fn() -> Result<u16, BoxDynError> + Send + Sync + 'static,
+    ) -> Self {
+        self.callbacks.machine_id = Some(Arc::new(machine_id));
+        self
+    }
+
+    /// Set the data center ID.
+    ///
+    /// If the provided closure returns an error, [`Builder::finalize`] will fail.
+    #[must_use]
+    pub fn data_center_id(
+        mut self,
+        data_center_id: impl Fn() -> Result<u16, BoxDynError> + Send + Sync + 'static,
+    ) -> Self {
+        self.callbacks.data_center_id = Some(Arc::new(data_center_id));
+        self
+    }
+
+    /// Set a validation function for the machine ID.
+    ///
+    /// If the function returns `false`, [`Builder::finalize`] will fail.
+    #[must_use]
+    pub fn check_machine_id(
+        mut self,
+        check_machine_id: impl Fn(u16) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.callbacks.check_machine_id = Some(Arc::new(check_machine_id));
+        self
+    }
+
+    /// Set a validation function for the data center ID.
+    ///
+    /// If the function returns `false`, [`Builder::finalize`] will fail.
+    #[must_use]
+    pub fn check_data_center_id(
+        mut self,
+        check_data_center_id: impl Fn(u16) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.callbacks.check_data_center_id = Some(Arc::new(check_data_center_id));
+        self
+    }
+}
+
+impl<'a, C: Callbacks> Builder<'a, C> {
+    /// Set the start time in milliseconds since the Unix epoch.
+    ///
+    /// When the `std` feature is enabled, [`Builder::finalize`] fails with
+    /// [`Error::StartTimeAheadOfCurrentTime`](crate::Error::StartTimeAheadOfCurrentTime)
+    /// if this value is later than the current system time.
+    #[must_use]
+    pub fn start_time(mut self, start_time: i64) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    /// Set the bit length of every section, the custom fields and their packing order from a [`Layout`].
+    ///
+    /// Later calls to the `bit_len_*` setters override the corresponding section
+    /// and keep the layout's custom fields and field order.
+    #[must_use]
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.bit_len_time = layout.bit_len_time();
+        self.bit_len_sequence = layout.bit_len_sequence();
+        self.bit_len_data_center_id = layout.bit_len_data_center_id();
+        self.bit_len_machine_id = layout.bit_len_machine_id();
+        self.layout = layout;
+        self
+    }
+
+    /// Set the value of a custom field of the [`Layout`] for every generated ID.
+    ///
+    /// Fields without a value here must be supplied on each call to
+    /// [`Snowflake::next_id_with`]; the other generation methods then fail with
+    /// [`Error::MissingField`]. [`Builder::finalize`] fails with
+    /// [`Error::UnknownField`] or [`Error::FieldOverflow`] if `name` is not a
+    /// field of the layout or `value` does not fit in it.
+    #[must_use]
+    pub fn field(mut self, name: impl Into<Cow<'a, str>>, value: u64) -> Self {
+        self.fields.push((name.into(), value));
+        self
+    }
+
+    /// Configure the layout, start time and time unit of a well-known ID scheme.
+    ///
+    /// Equivalent to calling [`Builder::layout`], [`Builder::start_time`] and
+    /// [`Builder::time_unit`]; later calls to those setters override the preset.
+    #[must_use]
+    pub fn preset(self, preset: Preset) -> Self {
+        self.layout(preset.layout())
+            .start_time(preset.start_time())
+            .time_unit(preset.time_unit())
+    }
 
     /// Set the bit length of the timestamp section.
     #[must_use]
@@ -138,6 +376,18 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Set the length of one tick of the timestamp section.
+    ///
+    /// Defaults to [`TimeUnit::MILLISECOND`]. Coarser units make the time section
+    /// last longer (a 31-bit field of [`TimeUnit::SECOND`] ticks covers 68 years)
+    /// while the sequence section then counts IDs per tick instead of per millisecond.
+    /// The start time and [`Builder::max_clock_drift_ms`] stay in milliseconds.
+    #[must_use]
+    pub fn time_unit(mut self, time_unit: TimeUnit) -> Self {
+        self.time_unit = time_unit;
+        self
+    }
+
     /// Set the strategy for handling backward clock drift.
     ///
     /// Defaults to [`ClockDriftStrategy::Wait`].
@@ -151,64 +401,231 @@ impl<'a> Builder<'a> {
     ///
     /// When using [`ClockDriftStrategy::Wait`], if the drift exceeds this limit,
     /// [`Error::ClockDriftExceeded`] is returned instead of waiting indefinitely.
-    /// Only effective with the `Wait` strategy.
+    /// With [`ClockDriftStrategy::Hybrid`] it bounds how far the timestamp may run
+    /// ahead of the clock, and it bounds the IDs accepted by [`Snowflake::observe`].
+    /// Has no effect with the other strategies.
     #[must_use]
     pub fn max_clock_drift_ms(mut self, ms: i64) -> Self {
         self.max_clock_drift_ms = Some(ms);
         self
     }
 
+    /// Let bursts borrow future ticks, up to `ms` milliseconds ahead of the clock.
+    ///
+    /// When the sequence of the current tick runs out, the generator moves on to
+    /// the next tick right away instead of waiting for it, as long as its
+    /// timestamp stays within `ms` of the clock. Once the lead reaches the cap it
+    /// waits, according to the [`WaitPolicy`], until the clock has caught up enough
+    /// to borrow again. A backward clock jump within the cap is absorbed the same
+    /// way; larger ones are handled by the [`ClockDriftStrategy`]. The current lead
+    /// is reported by the `snowflake_borrow_ahead_ms` gauge with the `metrics`
+    /// feature.
+    ///
+    /// Disabled (`0`) by default. Has no effect with [`ClockDriftStrategy::Hybrid`],
+    /// which always borrows and is bounded by [`Builder::max_clock_drift_ms`].
+    #[must_use]
+    pub fn max_borrow_ahead_ms(mut self, ms: i64) -> Self {
+        self.max_borrow_ahead_ms = ms;
+        self
+    }
+
+    /// Set how the generator waits for the clock to reach the next tick.
+    ///
+    /// Applies to sequence exhaustion and to [`ClockDriftStrategy::Wait`].
+    /// Defaults to [`WaitPolicy::Spin`].
+    #[must_use]
+    pub fn wait_policy(mut self, policy: WaitPolicy) -> Self {
+        self.wait_policy = policy;
+        self
+    }
+
+    /// Set the maximum time a single call may wait for the clock, in milliseconds.
+    ///
+    /// Measured with the generator's time source across all waits of one call.
+    /// When exceeded, [`Error::WaitTimeout`] is returned instead of blocking
+    /// further. By default there is no deadline.
+    #[must_use]
+    pub fn max_wait_ms(mut self, ms: i64) -> Self {
+        self.max_wait_ms = Some(ms);
+        self
+    }
+
+    /// Set where the sequence starts on each new tick.
+    ///
+    /// Defaults to [`SequenceStart::Zero`]. The other policies make IDs harder
+    /// to enumerate at the cost of up to half of each tick's sequence range.
+    #[must_use]
+    pub fn sequence_start(mut self, start: SequenceStart) -> Self {
+        self.sequence_start = start;
+        self
+    }
+
+    /// Set the clock the generator reads the current time from.
+    ///
+    /// Each generator owns its time source, so several generators with different
+    /// clocks can coexist in one process. With the `std` feature the default is
+    /// [`SystemClock`](crate::SystemClock); without it a time source is required
+    /// and [`Builder::finalize`] fails with
+    /// `Error::MissingTimeSource` if none is set.
+    #[must_use]
+    pub fn time_source<T: TimeSource + 'static>(mut self, time_source: T) -> Self {
+        self.time_source = Some(Arc::new(time_source));
+        self
+    }
+
+    /// Set how the generator's clock tracks the wall clock.
+    ///
+    /// Defaults to [`ClockMode::Wall`]. The monotonic modes read the configured
+    /// time source once in [`Builder::finalize`] and then advance with
+    /// `std::time::Instant`, so backward clock steps never reach the
+    /// [`ClockDriftStrategy`].
+    #[must_use]
+    pub fn clock_mode(mut self, mode: ClockMode) -> Self {
+        self.clock_mode = mode;
+        self
+    }
+
+    /// Persist the last issued timestamp so a restart never reissues IDs.
+    ///
+    /// [`Builder::finalize`] loads the stored high-water mark, and the generator
+    /// will not issue IDs from ticks that start before it. If the clock is behind
+    /// the mark after a restart, the [`ClockDriftStrategy`] decides whether
+    /// `next_id` waits or fails. Fails with
+    /// [`Error::TimestampStoreFailed`] if the store cannot be read or written.
+    #[must_use]
+    pub fn timestamp_store<T: TimestampStore + 'static>(mut self, store: T) -> Self {
+        self.timestamp_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Set how far ahead the timestamp high-water mark is written, in milliseconds.
+    ///
+    /// The store is written at most once per interval, so larger values keep the
+    /// hot path faster but make a restarted generator wait longer. Defaults to
+    /// 1000ms. Only effective with [`Builder::timestamp_store`].
+    #[must_use]
+    pub fn persist_interval_ms(mut self, ms: i64) -> Self {
+        self.persist_interval_ms = ms;
+        self
+    }
+
+    /// Resolve the machine ID and data center ID at [`Builder::finalize`].
+    ///
+    /// Used for whichever of the two IDs has no closure set, in place of the
+    /// default `ip-fallback` derivation; e.g.
+    /// [`StatefulSetOrdinal`](crate::worker::StatefulSetOrdinal) on Kubernetes,
+    /// or a configured `IpFallback` to pick the interface and network.
+    #[must_use]
+    pub fn worker_id_resolver<R: WorkerIdResolver + 'static>(mut self, resolver: R) -> Self {
+        self.worker_id_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Lease the machine ID and data center ID from a registry at [`Builder::finalize`].
+    ///
+    /// Takes precedence over [`Builder::worker_id_resolver`]. Cannot be combined
+    /// with the `machine_id` or `data_center_id` closures, which could issue IDs
+    /// outside the lease ([`Error::RegistryWithWorkerIdClosure`]); pin the data
+    /// center ID on the registry instead. The generator renews the lease while it issues IDs, fails with
+    /// [`Error::LeaseLost`] once a renewal fails, and releases the lease when the
+    /// last clone is dropped. See
+    /// [`FileWorkerRegistry`](crate::worker::FileWorkerRegistry) for processes on one host.
+    #[must_use]
+    pub fn worker_id_registry<R: WorkerIdRegistry + 'static>(mut self, registry: R) -> Self {
+        self.worker_id_registry = Some(Arc::new(registry));
+        self
+    }
+
+    /// Set how often the worker ID lease is renewed, in milliseconds.
+    ///
+    /// The lease is renewed by the first ID issued after the interval, so it must
+    /// be well below the lease's expiry. Defaults to 1000ms. Only effective with
+    /// [`Builder::worker_id_registry`].
+    #[must_use]
+    pub fn lease_renew_interval_ms(mut self, ms: i64) -> Self {
+        self.lease_renew_interval_ms = ms;
+        self
+    }
+
     /// Finish building and create a [`Snowflake`] instance.
     ///
     /// Returns an error if validation fails or any configured closure returns an error.
     pub fn finalize(self) -> Result<Snowflake, Error> {
-        if self.bit_len_time
-            + self.bit_len_sequence
-            + self.bit_len_data_center_id
-            + self.bit_len_machine_id
-            != 63
+        let layout = self.layout.try_with_bit_lens(
+            self.bit_len_time,
+            self.bit_len_sequence,
+            self.bit_len_data_center_id,
+            self.bit_len_machine_id,
+        )?;
+        let fields: Vec<(&str, u64)> = self
+            .fields
+            .iter()
+            .map(|(name, value)| (name.as_ref(), *value))
+            .collect();
+        let (field_bits, unset_fields) = layout.set_fields(0, layout.all_fields(), &fields)?;
+        if let SequenceStart::Step(step) = self.sequence_start
+            && step % 2 == 0
         {
-            return Err(Error::InvalidBitLength(
-                self.bit_len_time,
-                self.bit_len_sequence,
-                self.bit_len_data_center_id,
-                self.bit_len_machine_id,
-            ));
+            return Err(Error::InvalidSequenceStep(step));
         }
 
-        let start_time = if let Some(start_time) = self.start_time {
+        let time_source: Arc<dyn TimeSource> = match self.time_source {
+            Some(time_source) => time_source,
             #[cfg(feature = "std")]
-            if start_time > current_millis() {
-                return Err(Error::StartTimeAheadOfCurrentTime(start_time));
+            None => Arc::new(crate::time::SystemClock),
+            #[cfg(not(feature = "std"))]
+            None => return Err(Error::MissingTimeSource),
+        };
+        let time_source: Arc<dyn TimeSource> = match self.clock_mode {
+            ClockMode::Wall => time_source,
+            #[cfg(feature = "std")]
+            ClockMode::Monotonic => {
+                Arc::new(crate::time::MonotonicClock::anchored(time_source, None))
             }
-            start_time
-        } else {
-            // Default start time: 2022-01-01 00:00:00 UTC in milliseconds since the Unix epoch.
-            1_640_995_200_000
+            #[cfg(feature = "std")]
+            ClockMode::Slew { max_ppm } => Arc::new(crate::time::MonotonicClock::anchored(
+                time_source,
+                Some(max_ppm),
+            )),
         };
 
-        #[cfg(feature = "ip-fallback")]
-        let ip_derived_ids = get_ids_from_ip();
+        let start_time = self.start_time.unwrap_or(DEFAULT_START_TIME);
+        #[cfg(feature = "std")]
+        if self.start_time.is_some() && start_time > time_source.current_millis() {
+            return Err(Error::StartTimeAheadOfCurrentTime(start_time));
+        }
 
-        let machine_id_mask = (1 << self.bit_len_machine_id) - 1;
-        let machine_id = if let Some(machine_id_fn) = self.machine_id {
-            machine_id_fn().map_err(Error::MachineIdFailed)?
-        } else {
-            #[cfg(feature = "ip-fallback")]
-            {
-                if let Some((_, machine_id)) = ip_derived_ids {
-                    machine_id & machine_id_mask
-                } else {
-                    // For compatibility, leave the NoPrivateIP error on hold for now
-                    return Err(Error::NoPrivateIP);
-                }
+        let unresolved =
+            self.callbacks.machine_id().is_none() || self.callbacks.data_center_id().is_none();
+        let lease = match &self.worker_id_registry {
+            Some(_) if self.callbacks.machine_id().is_some() => {
+                return Err(Error::RegistryWithWorkerIdClosure);
+            }
+            Some(_) if self.callbacks.data_center_id().is_some() => {
+                return Err(Error::RegistryWithWorkerIdClosure);
             }
-            #[cfg(not(feature = "ip-fallback"))]
-            {
-                return Err(Error::MachineIdFailed(
-                    "Machine ID not provided and `ip-fallback` feature is disabled".into(),
-                ));
+            Some(registry) => Some(registry.acquire(&layout)?),
+            None => None,
+        };
+        let resolved = match (&lease, &self.worker_id_resolver) {
+            (Some(lease), _) => Some(lease.worker_id()),
+            (None, Some(resolver)) if unresolved => Some(resolver.resolve(&layout)?),
+            #[cfg(feature = "ip-fallback")]
+            (None, None) if unresolved => {
+                Some(crate::worker::IpFallback::default().resolve(&layout)?)
             }
+            _ => None,
+        };
+
+        let machine_id_mask = layout.max_machine_id() as u16;
+        let machine_id = if let Some(machine_id_fn) = self.callbacks.machine_id() {
+            machine_id_fn().map_err(Error::MachineIdFailed)?
+        } else if let Some(resolved) = resolved {
+            resolved.machine_id
+        } else {
+            return Err(Error::MachineIdFailed(
+                "Machine ID not provided and `ip-fallback` feature is disabled".into(),
+            ));
         };
 
         if machine_id > machine_id_mask {
@@ -217,30 +634,21 @@ impl<'a> Builder<'a> {
             ));
         }
 
-        if let Some(check_machine_id) = self.check_machine_id
+        if let Some(check_machine_id) = self.callbacks.check_machine_id()
             && !check_machine_id(machine_id)
         {
             return Err(Error::CheckMachineIdFailed);
         }
 
-        let data_center_id_mask = (1 << self.bit_len_data_center_id) - 1;
-        let data_center_id = if let Some(data_center_id_fn) = self.data_center_id {
+        let data_center_id_mask = layout.max_data_center_id() as u16;
+        let data_center_id = if let Some(data_center_id_fn) = self.callbacks.data_center_id() {
             data_center_id_fn().map_err(Error::DataCenterIdFailed)?
+        } else if let Some(resolved) = resolved {
+            resolved.data_center_id
         } else {
-            #[cfg(feature = "ip-fallback")]
-            {
-                if let Some((data_center_id, _)) = ip_derived_ids {
-                    data_center_id & data_center_id_mask
-                } else {
-                    return Err(Error::NoPrivateIP);
-                }
-            }
-            #[cfg(not(feature = "ip-fallback"))]
-            {
-                return Err(Error::DataCenterIdFailed(
-                    "Data Center ID not provided and `ip-fallback` feature is disabled".into(),
-                ));
-            }
+            return Err(Error::DataCenterIdFailed(
+                "Data Center ID not provided and `ip-fallback` feature is disabled".into(),
+            ));
         };
 
         if data_center_id > data_center_id_mask {
@@ -249,23 +657,62 @@ impl<'a> Builder<'a> {
             ));
         }
 
-        if let Some(check_data_center_id) = self.check_data_center_id
+        if let Some(check_data_center_id) = self.callbacks.check_data_center_id()
             && !check_data_center_id(data_center_id)
         {
             return Err(Error::CheckDataCenterIdFailed);
         }
 
+        let mut state = 0;
+        let persistence = match self.timestamp_store {
+            Some(store) => {
+                let high_water_mark = store.load().map_err(Error::TimestampStoreFailed)?;
+                if let Some(mark) = high_water_mark
+                    && mark > start_time
+                {
+                    // The first tick that starts at or after the mark; mark the tick
+                    // before it as exhausted so generation resumes from there.
+                    let unit = self.time_unit.as_millis() as i64;
+                    let first_tick = (mark - start_time + unit - 1) / unit;
+                    state = ((first_tick as u64 - 1) << layout.bit_len_sequence())
+                        | layout.max_sequence();
+                }
+                Some(Arc::new(Persistence::new(
+                    store,
+                    high_water_mark.unwrap_or(i64::MIN),
+                    self.persist_interval_ms,
+                )))
+            }
+            None => None,
+        };
+
+        let lease = lease.map(|lease| {
+            Arc::new(LeaseKeeper::new(
+                lease,
+                time_source.current_millis(),
+                self.lease_renew_interval_ms,
+            ))
+        });
+
         let shared = Arc::new(SharedSnowflake {
-            state: AtomicU64::new(0),
+            state: AtomicU64::new(state),
             start_time,
+            time_unit: self.time_unit,
             machine_id,
             data_center_id,
-            bit_len_time: self.bit_len_time,
-            bit_len_sequence: self.bit_len_sequence,
-            bit_len_data_center_id: self.bit_len_data_center_id,
-            bit_len_machine_id: self.bit_len_machine_id,
+            layout,
+            field_bits,
+            unset_fields,
+            max_sequence: layout.max_sequence(),
             clock_drift_strategy: self.clock_drift_strategy,
             max_clock_drift_ms: self.max_clock_drift_ms,
+            max_borrow_ahead: self.max_borrow_ahead_ms.max(0) as u64 / self.time_unit.as_millis(),
+            wait_policy: self.wait_policy,
+            max_wait_ms: self.max_wait_ms,
+            sequence_start: self.sequence_start,
+            time_source,
+            persistence,
+            lease,
         });
 
         #[cfg(feature = "tracing")]
@@ -278,66 +725,15 @@ impl<'a> Builder<'a> {
         );
         Ok(Snowflake::new_inner(shared))
     }
-}
 
-/// Get the data center ID and machine ID from the private IP address (v4 or v6).
-/// Returns a tuple (data_center_id, machine_id).
-#[cfg(feature = "ip-fallback")]
-fn get_ids_from_ip() -> Option<(u16, u16)> {
-    if let Some(ipv4) = private_ipv4() {
-        let octets = ipv4.octets();
-        // IPv4: Use bytes 3 and 4
-        let data_center_id = u16::from(octets[2]);
-        let machine_id = u16::from(octets[3]);
-        return Some((data_center_id, machine_id));
-    }
-
-    if let Some(ipv6) = private_ipv6() {
-        let segments = ipv6.segments();
-        //IPv6: Use the last two 16-bit segments
-        let data_center_id = segments[6];
-        let machine_id = segments[7];
-        return Some((data_center_id, machine_id));
+    /// Finish building and create a [`ShardedSnowflake`] with `2^lane_bits` lanes.
+    ///
+    /// The top `lane_bits` bits of the sequence section select the lane, so each
+    /// lane issues `2^(bit_len_sequence - lane_bits)` IDs per tick.
+    ///
+    /// Returns [`Error::InvalidLaneBits`] if `lane_bits` is over 10 or leaves no
+    /// sequence bits, and otherwise the same errors as [`Builder::finalize`].
+    pub fn finalize_sharded(self, lane_bits: u8) -> Result<ShardedSnowflake, Error> {
+        ShardedSnowflake::new(&self.finalize()?, lane_bits)
     }
-
-    None
-}
-
-#[cfg(feature = "ip-fallback")]
-fn private_ipv4() -> Option<std::net::Ipv4Addr> {
-    pnet_datalink::interfaces()
-        .iter()
-        .filter(|iface| iface.is_up() && !iface.is_loopback() && !iface.ips.is_empty())
-        .flat_map(|iface| iface.ips.iter())
-        .find_map(|network| match network.ip() {
-            std::net::IpAddr::V4(ipv4) if is_private_ipv4(&ipv4) => Some(ipv4),
-            _ => None,
-        })
-}
-
-#[cfg(feature = "ip-fallback")]
-fn is_private_ipv4(ip: &std::net::Ipv4Addr) -> bool {
-    let octets = ip.octets();
-    matches!(octets[0], 10)
-        || (octets[0] == 172 && (16..=31).contains(&octets[1]))
-        || (octets[0] == 192 && octets[1] == 168)
-}
-
-#[cfg(feature = "ip-fallback")]
-fn private_ipv6() -> Option<std::net::Ipv6Addr> {
-    pnet_datalink::interfaces()
-        .iter()
-        .filter(|iface| iface.is_up() && !iface.is_loopback() && !iface.ips.is_empty())
-        .flat_map(|iface| iface.ips.iter())
-        .find_map(|network| match network.ip() {
-            std::net::IpAddr::V6(ipv6) if is_private_ipv6(&ipv6) => Some(ipv6),
-            _ => None,
-        })
-}
-
-#[cfg(feature = "ip-fallback")]
-fn is_private_ipv6(ip: &std::net::Ipv6Addr) -> bool {
-    // fc00::/7 (Unique Local Address)
-    // fe80::/10 (Link-Local Address)
-    (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80
 }
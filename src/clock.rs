@@ -6,6 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::entropy::EntropySource;
+
+extern crate alloc;
+use alloc::sync::Arc;
+
 /// Strategy for handling backward clock drift (e.g., due to NTP adjustments).
 ///
 /// When the system clock moves backward, the generator must decide how to
@@ -13,7 +18,9 @@
 /// between monotonicity guarantees and availability.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ClockDriftStrategy {
-    /// Busy-wait until the clock catches up. Guarantees strict monotonicity.
+    /// Wait until the clock catches up. Guarantees strict monotonicity.
+    ///
+    /// How the generator waits is set by [`WaitPolicy`] (busy-spin by default).
     ///
     /// This is the default strategy. If `max_clock_drift_ms` is configured and
     /// the drift exceeds that limit, `Error::ClockDriftExceeded` is returned
@@ -31,4 +38,208 @@ pub enum ClockDriftStrategy {
     /// but the time-to-ID mapping becomes approximate. Useful when
     /// availability is more important than exact timestamp accuracy.
     LastTimestamp,
+    /// Hybrid logical clock: the time section is `max(physical, last issued)`.
+    ///
+    /// The generator never waits. When the clock moves backward it keeps issuing
+    /// IDs from the last timestamp, and when a tick's sequence runs out it borrows
+    /// the next logical tick, so the timestamp may run ahead of the clock until it
+    /// catches up. Combined with [`Snowflake::observe`](crate::Snowflake::observe),
+    /// IDs issued after seeing a remote ID are larger than it. If
+    /// `max_clock_drift_ms` is configured, running further ahead of the clock than
+    /// that returns `Error::ClockDriftExceeded`.
+    Hybrid,
+}
+
+/// How the generator's clock relates to the wall clock.
+///
+/// Applied on top of the configured [`TimeSource`](crate::TimeSource) by
+/// [`Builder::clock_mode`](crate::Builder::clock_mode). Where [`ClockDriftStrategy`]
+/// decides what to do once the clock has gone backwards, the monotonic modes
+/// keep it from going backwards in the first place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockMode {
+    /// Read the time source directly. This is the default.
+    #[default]
+    Wall,
+    /// Read the wall clock once at [`Builder::finalize`](crate::Builder::finalize),
+    /// then advance with `std::time::Instant`.
+    ///
+    /// NTP steps and manual clock changes never cause backward drift, but the
+    /// generator's notion of time slowly diverges from the wall clock over long uptimes.
+    #[cfg(feature = "std")]
+    Monotonic,
+    /// Like [`ClockMode::Monotonic`], but gradually re-synchronize with the wall clock.
+    ///
+    /// The correction rate is capped at `max_ppm` parts per million of elapsed
+    /// time (e.g., `500` corrects at most 0.5ms per second), so time never
+    /// steps and never runs backwards. `max_ppm` is clamped to `500_000`.
+    #[cfg(feature = "std")]
+    Slew {
+        /// Maximum correction rate in parts per million.
+        max_ppm: u32,
+    },
+}
+
+/// How the generator waits for the clock to reach the next tick.
+///
+/// Used both when the sequence of the current tick is exhausted and when
+/// [`ClockDriftStrategy::Wait`] waits for a clock that moved backward. Set it with
+/// [`Builder::wait_policy`](crate::Builder::wait_policy); an overall deadline can be
+/// added with [`Builder::max_wait_ms`](crate::Builder::max_wait_ms).
+#[derive(Clone, Default)]
+pub enum WaitPolicy {
+    /// Busy-spin until the clock advances. This is the default.
+    ///
+    /// Lowest latency, but burns a full core while waiting.
+    #[default]
+    Spin,
+    /// Spin for `spins` clock reads, then call `std::thread::yield_now` between reads.
+    #[cfg(feature = "std")]
+    SpinYield {
+        /// Number of clock reads to spin for before yielding.
+        spins: u32,
+    },
+    /// Spin for `spins` clock reads, then sleep for `sleep` between reads.
+    ///
+    /// Friendliest to oversubscribed hosts, at the cost of overshooting the
+    /// next tick by up to `sleep` plus the scheduler's wake-up latency.
+    #[cfg(feature = "std")]
+    SpinSleep {
+        /// Number of clock reads to spin for before sleeping.
+        spins: u32,
+        /// How long to sleep between clock reads once spinning is over.
+        sleep: std::time::Duration,
+    },
+    /// Call the closure between clock reads.
+    ///
+    /// The closure receives the number of clock reads made so far in the current
+    /// wait, starting at `0`.
+    Custom(Arc<dyn Fn(u32) + Send + Sync>),
+}
+
+impl WaitPolicy {
+    /// Build a [`WaitPolicy::Custom`] from a closure.
+    #[must_use]
+    pub fn custom(wait: impl Fn(u32) + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(wait))
+    }
+
+    /// Back off once between two clock reads.
+    pub(crate) fn wait(&self, attempt: u32) {
+        match self {
+            Self::Spin => core::hint::spin_loop(),
+            #[cfg(feature = "std")]
+            Self::SpinYield { spins } => {
+                if attempt < *spins {
+                    core::hint::spin_loop();
+                } else {
+                    std::thread::yield_now();
+                }
+            }
+            #[cfg(feature = "std")]
+            Self::SpinSleep { spins, sleep } => {
+                if attempt < *spins {
+                    core::hint::spin_loop();
+                } else {
+                    std::thread::sleep(*sleep);
+                }
+            }
+            Self::Custom(wait) => wait(attempt),
+        }
+    }
+}
+
+impl core::fmt::Debug for WaitPolicy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Spin => f.write_str("Spin"),
+            #[cfg(feature = "std")]
+            Self::SpinYield { spins } => f.debug_struct("SpinYield").field("spins", spins).finish(),
+            #[cfg(feature = "std")]
+            Self::SpinSleep { spins, sleep } => f
+                .debug_struct("SpinSleep")
+                .field("spins", spins)
+                .field("sleep", sleep)
+                .finish(),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Where the sequence starts on each new tick.
+///
+/// By default every tick starts at sequence `0`, so IDs are easy to enumerate
+/// and a lightly loaded service emits IDs whose low bits are almost always zero.
+/// The other policies start each tick somewhere in the lower half of the
+/// sequence range, which keeps at least half of it available and IDs from one
+/// generator strictly increasing. Set it with
+/// [`Builder::sequence_start`](crate::Builder::sequence_start).
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use snowflake_me::{SequenceStart, Snowflake};
+///
+/// let sf = Snowflake::builder()
+///     .machine_id(&|| Ok(1))
+///     .data_center_id(&|| Ok(1))
+///     .sequence_start(SequenceStart::random())
+///     .finalize()?;
+/// let id = sf.next_id()?;
+/// assert!(sf.decompose(id).sequence < 1 << 11);
+/// # }
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub enum SequenceStart {
+    /// Start every tick at sequence `0`. This is the default.
+    #[default]
+    Zero,
+    /// Start every tick at a random sequence in the lower half of the range.
+    Random(Arc<dyn EntropySource>),
+    /// Start tick `t` at `t * step`, wrapped to the lower half of the range.
+    ///
+    /// The step must be odd, so consecutive ticks cycle through every start in
+    /// the lower half without needing an RNG; otherwise
+    /// [`Builder::finalize`](crate::Builder::finalize) returns
+    /// [`Error::InvalidSequenceStep`](crate::Error::InvalidSequenceStep).
+    Step(u64),
+}
+
+impl SequenceStart {
+    /// Random starts from a SplitMix64 generator seeded from the standard
+    /// library's hasher seed. Fast, but not cryptographically secure.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn random() -> Self {
+        Self::random_with(crate::entropy::SplitMix64::seeded(
+            &crate::time::SystemClock,
+        ))
+    }
+
+    /// Random starts drawn from the given source, e.g. a CSPRNG or, without
+    /// `std`, a hardware RNG.
+    #[must_use]
+    pub fn random_with(entropy: impl EntropySource + 'static) -> Self {
+        Self::Random(Arc::new(entropy))
+    }
+
+    /// The first sequence of tick `time`, given the largest sequence number.
+    pub(crate) fn first(&self, time: u64, max_sequence: u64) -> u64 {
+        let lower_half = max_sequence >> 1;
+        match self {
+            Self::Zero => 0,
+            Self::Random(entropy) => entropy.next_u64() & lower_half,
+            Self::Step(step) => time.wrapping_mul(*step) & lower_half,
+        }
+    }
+}
+
+impl core::fmt::Debug for SequenceStart {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Zero => f.write_str("Zero"),
+            Self::Random(_) => f.write_str("Random(..)"),
+            Self::Step(step) => f.debug_tuple("Step").field(step).finish(),
+        }
+    }
 }
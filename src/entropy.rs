@@ -0,0 +1,64 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::time::TimeSource;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+extern crate alloc;
+use alloc::sync::Arc;
+
+/// A source of random bits, such as the suffix of a
+/// [`SnowflakeId128`](crate::SnowflakeId128) or a randomized
+/// [`SequenceStart`](crate::SequenceStart).
+///
+/// The built-in source is a fast SplitMix64 generator seeded once per generator
+/// (from the standard library's hasher seed with `std`, from the time source
+/// otherwise). It is not cryptographically secure; plug in a CSPRNG if the bits
+/// must be unpredictable. In `no_std` builds this is also how a hardware RNG is
+/// supplied.
+pub trait EntropySource: Send + Sync {
+    /// Returns 64 fresh random bits.
+    fn next_u64(&self) -> u64;
+}
+
+impl<T: EntropySource + ?Sized> EntropySource for Arc<T> {
+    fn next_u64(&self) -> u64 {
+        (**self).next_u64()
+    }
+}
+
+/// Lock-free SplitMix64 generator, the built-in [`EntropySource`].
+pub(crate) struct SplitMix64(AtomicU64);
+
+impl SplitMix64 {
+    /// Seed from the standard library's hasher seed (with `std`) and the given clock.
+    pub(crate) fn seeded(time_source: &dyn TimeSource) -> Self {
+        #[cfg(feature = "std")]
+        let seed = {
+            use std::hash::{BuildHasher, Hasher};
+            std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish()
+        };
+        #[cfg(not(feature = "std"))]
+        let seed = 0;
+        Self(AtomicU64::new(seed ^ time_source.current_millis() as u64))
+    }
+}
+
+impl EntropySource for SplitMix64 {
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .0
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
@@ -52,10 +52,34 @@ pub enum Error {
     /// No private IPv4 or IPv6 address was found on any network interface.
     ///
     /// Only raised when the `ip-fallback` feature is enabled and no `machine_id` or
-    /// `data_center_id` is explicitly provided.
+    /// `data_center_id` is explicitly provided, or by an
+    /// [`IpFallback`](crate::worker::IpFallback) whose interface and CIDR filters
+    /// match no address.
     #[error("could not find any private IPv4 or IPv6 address")]
     NoPrivateIP,
 
+    /// The worker ID derived from an IP address does not fit in the data center ID
+    /// and machine ID sections, so bits of the address would be lost.
+    ///
+    /// See [`IpDerivation`](crate::worker::IpDerivation).
+    #[cfg(feature = "ip-fallback")]
+    #[error(
+        "IP address {addr} needs {needed} bits for the worker ID, but only {available} are available"
+    )]
+    IpAddressOverflow {
+        /// The address the worker ID was derived from.
+        addr: core::net::IpAddr,
+        /// The number of bits the derivation needs.
+        needed: u8,
+        /// The number of bits of the section(s) it fills.
+        available: u8,
+    },
+
+    /// A string is not a valid [`IpCidr`](crate::worker::IpCidr).
+    #[cfg(feature = "ip-fallback")]
+    #[error("invalid CIDR `{0}`")]
+    InvalidCidr(String),
+
     /// Failed to parse a string as a [`SnowflakeId`](crate::SnowflakeId).
     #[error("failed to parse SnowflakeId: {0}")]
     ParseIdFailed(String),
@@ -66,16 +90,18 @@ pub enum Error {
     /// and the current time is earlier than the last recorded timestamp.
     #[error("clock drifted backward: last_time={last_time}, current_time={current_time}")]
     ClockDrift {
-        /// The last recorded timestamp in the state.
+        /// The last recorded timestamp in the state, in ticks since the start time.
         last_time: u64,
-        /// The current (earlier) timestamp from the system clock.
+        /// The current (earlier) timestamp from the clock, in ticks since the start time.
         current_time: u64,
     },
 
     /// Clock drift exceeded the configured maximum allowed drift.
     ///
     /// Raised when the [`ClockDriftStrategy`](crate::ClockDriftStrategy) is `Wait`
-    /// and the drift exceeds [`Builder::max_clock_drift_ms`](crate::Builder::max_clock_drift_ms).
+    /// and the drift exceeds [`Builder::max_clock_drift_ms`](crate::Builder::max_clock_drift_ms),
+    /// when a `Hybrid` clock would run further ahead of the clock than that, and by
+    /// [`Snowflake::observe`](crate::Snowflake::observe) for IDs from too far in the future.
     #[error("clock drift {drift_ms}ms exceeded maximum allowed {max_ms}ms")]
     ClockDriftExceeded {
         /// The actual drift in milliseconds.
@@ -84,12 +110,146 @@ pub enum Error {
         max_ms: i64,
     },
 
-    /// The sum of all bit lengths does not equal 63.
+    /// Waiting for the clock took longer than the configured deadline.
     ///
-    /// The four configurable sections (time, sequence, data center ID, machine ID)
-    /// must sum to exactly 63 bits to fit within a `u64` with the sign bit unset.
+    /// Raised when a call has to wait (sequence exhaustion or
+    /// [`ClockDriftStrategy::Wait`](crate::ClockDriftStrategy::Wait)) for longer than
+    /// [`Builder::max_wait_ms`](crate::Builder::max_wait_ms).
+    #[error("waited longer than the maximum allowed {max_ms}ms for the clock to advance")]
+    WaitTimeout {
+        /// The configured maximum wait in milliseconds.
+        max_ms: i64,
+    },
+
+    /// [`Snowflake::try_next_id`](crate::Snowflake::try_next_id) would have had to
+    /// wait for the clock.
+    ///
+    /// Raised when the sequence of the current tick is exhausted, or the clock is
+    /// behind the last issued timestamp under [`ClockDriftStrategy::Wait`](crate::ClockDriftStrategy::Wait)
+    /// (or exhausted under `LastTimestamp`).
+    #[error("would block for {wait_ms}ms waiting for the clock to advance")]
+    WouldBlock {
+        /// How long the caller would have had to wait, in milliseconds.
+        wait_ms: u64,
+    },
+
+    /// The bit lengths do not form a valid [`Layout`](crate::Layout).
+    ///
+    /// The four configurable sections (time, sequence, data center ID, machine ID),
+    /// together with any custom fields, must sum to at most 63 bits to fit within a
+    /// `u64` with the sign bit unset, and the data center ID and machine ID sections
+    /// hold at most 16 bits each.
     #[error(
-        "invalid bit length configuration: time({0}) + sequence({1}) + data_center({2}) + machine({3}) must be 63"
+        "invalid bit length configuration: time({0}) + sequence({1}) + data_center({2}) + machine({3}) + custom fields must be at most 63, with data_center and machine at most 16"
     )]
     InvalidBitLength(u8, u8, u8, u8),
+
+    /// The bit lengths do not form a valid [`Layout128`](crate::Layout128).
+    ///
+    /// The time section must be non-empty, time and sequence must sum to at most
+    /// 63 bits, the node section holds at most 16 bits, and all four sections
+    /// must fit in 128 bits.
+    #[error(
+        "invalid 128-bit layout: time({0}) must be non-empty, time + sequence({1}) must be at most 63, node({2}) at most 16, and time + sequence + node + random({3}) at most 128"
+    )]
+    InvalidLayout128(u8, u8, u8, u8),
+
+    /// A [`Layout`](crate::Layout) field order does not list every field exactly once.
+    #[error("invalid field order: every field must appear exactly once")]
+    InvalidFieldOrder,
+
+    /// The custom field definitions of a [`Layout`](crate::Layout) are invalid.
+    ///
+    /// Raised by [`Layout::try_with_fields`](crate::Layout::try_with_fields) with the reason.
+    #[error("{0}")]
+    InvalidField(&'static str),
+
+    /// A custom field value was given for a name the [`Layout`](crate::Layout) does not define.
+    #[error("unknown field `{0}`")]
+    UnknownField(String),
+
+    /// A custom field value does not fit in the field's bit length.
+    #[error("value {value} of field `{name}` is greater than the max allowed value {max}")]
+    FieldOverflow {
+        /// The name of the field.
+        name: String,
+        /// The rejected value.
+        value: u64,
+        /// The largest value the field can hold.
+        max: u64,
+    },
+
+    /// No value was given for a custom field, neither on the
+    /// [`Builder`](crate::Builder::field) nor in the call.
+    #[error("no value for field `{0}`")]
+    MissingField(String),
+
+    /// A [`SequenceStart::Step`](crate::SequenceStart::Step) step is even.
+    #[error("sequence start step `{0}` must be odd")]
+    InvalidSequenceStep(u64),
+
+    /// The lane bit length of a [`ShardedSnowflake`](crate::ShardedSnowflake) is invalid.
+    ///
+    /// Lanes take the top bits of the sequence section, so at least one sequence
+    /// bit must remain, and there are at most 10 lane bits (1024 lanes).
+    #[error("lane bits `{0}` must be at most 10 and less than the sequence bit length")]
+    InvalidLaneBits(u8),
+
+    /// A [`StatefulSetOrdinal`](crate::worker::StatefulSetOrdinal) could not read
+    /// the pod ordinal.
+    #[cfg(feature = "std")]
+    #[error("could not read a StatefulSet ordinal from `{from}`: {reason}")]
+    OrdinalUnavailable {
+        /// The environment variable (or `hostname`) the ordinal was read from.
+        from: String,
+        /// Why no ordinal could be read.
+        reason: String,
+    },
+
+    /// A pod ordinal does not fit in the data center ID and machine ID sections.
+    #[error("ordinal {ordinal} is greater than the max allowed value {max}")]
+    OrdinalOutOfRange {
+        /// The pod ordinal.
+        ordinal: u64,
+        /// The largest ordinal the layout can hold.
+        max: u64,
+    },
+
+    /// A [`WorkerIdRegistry`](crate::WorkerIdRegistry) has no free worker ID left.
+    #[error("all {capacity} worker IDs are taken")]
+    NoFreeWorkerId {
+        /// The number of worker IDs the registry can hand out for the layout.
+        capacity: u64,
+    },
+
+    /// A [`WorkerIdRegistry`](crate::WorkerIdRegistry) failed to acquire a worker ID,
+    /// or a [`WorkerNodeTable`](crate::worker::WorkerNodeTable) failed to insert a row.
+    #[error("worker ID registry failed: {0}")]
+    WorkerIdRegistryFailed(#[source] BoxDynError),
+
+    /// A [`WorkerIdRegistry`](crate::WorkerIdRegistry) was combined with a
+    /// `machine_id` or `data_center_id` closure, which could issue IDs outside
+    /// the leased worker ID.
+    ///
+    /// Pin the data center ID on the registry instead.
+    #[error("worker_id_registry cannot be combined with machine_id or data_center_id closures")]
+    RegistryWithWorkerIdClosure,
+
+    /// The generator's [`WorkerLease`](crate::WorkerLease) could not be renewed,
+    /// so it no longer issues IDs.
+    #[error("worker ID lease lost")]
+    LeaseLost,
+
+    /// The configured [`TimestampStore`](crate::persist::TimestampStore) failed to
+    /// load or persist the timestamp high-water mark.
+    #[error("timestamp store failed: {0}")]
+    TimestampStoreFailed(#[source] BoxDynError),
+
+    /// No [`TimeSource`](crate::TimeSource) was configured.
+    ///
+    /// Only raised when the `std` feature is disabled, since there is no system
+    /// clock to fall back to. Set one with [`Builder::time_source`](crate::Builder::time_source).
+    #[cfg(not(feature = "std"))]
+    #[error("no time source configured")]
+    MissingTimeSource,
 }
@@ -24,13 +24,13 @@ pub struct SnowflakeId(u64);
 impl SnowflakeId {
     /// Create a new `SnowflakeId` from a raw `u64` value.
     #[must_use]
-    pub fn new(raw: u64) -> Self {
+    pub const fn new(raw: u64) -> Self {
         Self(raw)
     }
 
     /// Returns the underlying `u64` value.
     #[must_use]
-    pub fn as_u64(&self) -> u64 {
+    pub const fn as_u64(&self) -> u64 {
         self.0
     }
 
@@ -49,22 +49,17 @@ impl SnowflakeId {
     /// Returns the base32 encoded string using a custom alphabet.
     #[must_use]
     pub fn base32(&self) -> String {
-        const ENCODE_BASE32_MAP: &str = "ybndrfg8ejkmcpqxot1uwisza345h769";
         let mut id = self.0;
         if id < 32 {
-            return ENCODE_BASE32_MAP
-                .chars()
-                .nth(id as usize)
-                .unwrap()
-                .to_string();
+            return char::from(BASE32_ALPHABET[id as usize]).to_string();
         }
 
         let mut b = Vec::new();
         while id >= 32 {
-            b.push(ENCODE_BASE32_MAP.chars().nth((id % 32) as usize).unwrap());
+            b.push(char::from(BASE32_ALPHABET[(id % 32) as usize]));
             id /= 32;
         }
-        b.push(ENCODE_BASE32_MAP.chars().nth(id as usize).unwrap());
+        b.push(char::from(BASE32_ALPHABET[id as usize]));
 
         b.reverse();
         b.into_iter().collect()
@@ -73,14 +68,13 @@ impl SnowflakeId {
     /// Returns the base36 encoded string (digits + lowercase letters).
     #[must_use]
     pub fn base36(&self) -> String {
-        const CHARSET: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
         let mut id = self.0;
         if id == 0 {
             return "0".to_string();
         }
         let mut buf = Vec::new();
         while id > 0 {
-            buf.push(CHARSET[(id % 36) as usize]);
+            buf.push(BASE36_ALPHABET[(id % 36) as usize]);
             id /= 36;
         }
         buf.reverse();
@@ -90,23 +84,17 @@ impl SnowflakeId {
     /// Returns the base58 encoded string.
     #[must_use]
     pub fn base58(&self) -> String {
-        const ENCODE_BASE58_MAP: &str =
-            "123456789abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ";
         let mut id = self.0;
         if id < 58 {
-            return ENCODE_BASE58_MAP
-                .chars()
-                .nth(id as usize)
-                .unwrap()
-                .to_string();
+            return char::from(BASE58_ALPHABET[id as usize]).to_string();
         }
 
         let mut b = Vec::new();
         while id >= 58 {
-            b.push(ENCODE_BASE58_MAP.chars().nth((id % 58) as usize).unwrap());
+            b.push(char::from(BASE58_ALPHABET[(id % 58) as usize]));
             id /= 58;
         }
-        b.push(ENCODE_BASE58_MAP.chars().nth(id as usize).unwrap());
+        b.push(char::from(BASE58_ALPHABET[id as usize]));
 
         b.reverse();
         b.into_iter().collect()
@@ -143,6 +131,294 @@ impl SnowflakeId {
     }
 }
 
+// Shared by the 64-bit and 128-bit encoders, so that both encode the same way.
+const BASE32_ALPHABET: &[u8] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+const BASE36_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const BASE58_ALPHABET: &[u8] = b"123456789abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// Encode `id` in the base given by the length of `alphabet`, most significant digit first.
+fn encode_radix(mut id: u128, alphabet: &[u8]) -> String {
+    let radix = alphabet.len() as u128;
+    let mut buf = Vec::new();
+    loop {
+        buf.push(alphabet[(id % radix) as usize]);
+        id /= radix;
+        if id == 0 {
+            break;
+        }
+    }
+    buf.reverse();
+    String::from_utf8(buf).expect("alphabets are ASCII")
+}
+
+/// A 128-bit Snowflake ID generated by [`Snowflake128`](crate::Snowflake128).
+///
+/// Mirrors [`SnowflakeId`], with 16-byte encodings and conversions to and from
+/// UUID byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SnowflakeId128(u128);
+
+impl SnowflakeId128 {
+    /// Create a new `SnowflakeId128` from a raw `u128` value.
+    #[must_use]
+    pub const fn new(raw: u128) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the underlying `u128` value.
+    #[must_use]
+    pub const fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// Returns the lowercase hexadecimal string representation.
+    #[must_use]
+    pub fn hex(&self) -> String {
+        format!("{:x}", self.0)
+    }
+
+    /// Returns the binary string representation.
+    #[must_use]
+    pub fn base2(&self) -> String {
+        format!("{:b}", self.0)
+    }
+
+    /// Returns the base32 encoded string using the same alphabet as [`SnowflakeId::base32`].
+    #[must_use]
+    pub fn base32(&self) -> String {
+        encode_radix(self.0, BASE32_ALPHABET)
+    }
+
+    /// Returns the base36 encoded string (digits + lowercase letters).
+    #[must_use]
+    pub fn base36(&self) -> String {
+        encode_radix(self.0, BASE36_ALPHABET)
+    }
+
+    /// Returns the base58 encoded string.
+    #[must_use]
+    pub fn base58(&self) -> String {
+        encode_radix(self.0, BASE58_ALPHABET)
+    }
+
+    /// Returns the base64 encoded string of the raw 16-byte ID.
+    #[must_use]
+    pub fn base64(&self) -> String {
+        general_purpose::STANDARD.encode(self.0.to_be_bytes())
+    }
+
+    /// Returns the decimal string representation.
+    #[must_use]
+    pub fn string(&self) -> String {
+        self.0.to_string()
+    }
+
+    /// Returns the decimal string representation as bytes.
+    #[must_use]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.to_string().into_bytes()
+    }
+
+    /// Returns the raw 16-byte big-endian representation.
+    #[must_use]
+    pub const fn int_bytes(&self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Returns the ID as the 16 bytes of a UUID.
+    ///
+    /// UUIDs are stored most significant byte first, so this is the big-endian
+    /// representation and sorts like the ID. The bytes are taken verbatim: no
+    /// UUID version or variant bits are set.
+    #[must_use]
+    pub const fn to_uuid_bytes(&self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Create an ID from the 16 bytes of a UUID, see [`SnowflakeId128::to_uuid_bytes`].
+    #[must_use]
+    pub const fn from_uuid_bytes(bytes: [u8; 16]) -> Self {
+        Self(u128::from_be_bytes(bytes))
+    }
+
+    /// Returns the hyphenated UUID string of the ID, e.g.
+    /// `0192f4c6-3a10-0001-0002-5c1f9e6d3b2a`.
+    #[must_use]
+    pub fn uuid(&self) -> String {
+        let hex = format!("{:032x}", self.0);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl fmt::Display for SnowflakeId128 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u128> for SnowflakeId128 {
+    fn from(raw: u128) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<SnowflakeId128> for u128 {
+    fn from(id: SnowflakeId128) -> u128 {
+        id.0
+    }
+}
+
+impl FromStr for SnowflakeId128 {
+    type Err = Error;
+
+    /// Parses a decimal ID, a `0x`-prefixed hexadecimal ID, or a hyphenated UUID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            u128::from_str_radix(hex, 16)
+        } else if s.len() == 36 && s.contains('-') {
+            let groups: Vec<&str> = s.split('-').collect();
+            if groups.iter().map(|group| group.len()).ne([8, 4, 4, 4, 12]) {
+                return Err(Error::ParseIdFailed(format!("invalid UUID: {s}")));
+            }
+            u128::from_str_radix(&groups.concat(), 16)
+        } else {
+            s.parse::<u128>()
+        };
+        parsed
+            .map(SnowflakeId128)
+            .map_err(|e| Error::ParseIdFailed(e.to_string()))
+    }
+}
+
+impl TryFrom<&str> for SnowflakeId128 {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A contiguous block of IDs reserved with [`Snowflake::reserve`](crate::Snowflake::reserve).
+///
+/// All IDs in a block share the same timestamp and differ only in consecutive
+/// sequence numbers. The block is a few words in size and yields its IDs in
+/// increasing order without touching the generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdBlock {
+    /// The ID with a sequence number of zero.
+    pub(crate) base: u64,
+    /// Sequence number of the first ID in the block.
+    pub(crate) first_sequence: u64,
+    /// Number of IDs in the block.
+    pub(crate) len: u64,
+    /// Bit offset of the sequence section.
+    pub(crate) sequence_shift: u8,
+}
+
+impl IdBlock {
+    pub(crate) fn empty() -> Self {
+        Self {
+            base: 0,
+            first_sequence: 0,
+            len: 0,
+            sequence_shift: 0,
+        }
+    }
+
+    /// Returns the first ID in the block, or `None` if the block is empty.
+    #[must_use]
+    pub fn first(&self) -> Option<SnowflakeId> {
+        self.get(0)
+    }
+
+    /// Returns the last ID in the block, or `None` if the block is empty.
+    #[must_use]
+    pub fn last(&self) -> Option<SnowflakeId> {
+        self.len
+            .checked_sub(1)
+            .and_then(|index| self.get(index as usize))
+    }
+
+    /// Returns the ID at `index`, or `None` if it is out of range.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<SnowflakeId> {
+        let index = index as u64;
+        (index < self.len).then(|| {
+            SnowflakeId(self.base | ((self.first_sequence + index) << self.sequence_shift))
+        })
+    }
+
+    /// Returns the number of IDs in the block.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if the block holds no IDs.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns an iterator over the IDs in the block.
+    #[must_use]
+    pub fn iter(&self) -> IdBlockIter {
+        IdBlockIter {
+            block: *self,
+            next: 0,
+        }
+    }
+}
+
+impl IntoIterator for IdBlock {
+    type Item = SnowflakeId;
+    type IntoIter = IdBlockIter;
+
+    fn into_iter(self) -> IdBlockIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for &IdBlock {
+    type Item = SnowflakeId;
+    type IntoIter = IdBlockIter;
+
+    fn into_iter(self) -> IdBlockIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the IDs of an [`IdBlock`].
+#[derive(Debug, Clone)]
+pub struct IdBlockIter {
+    block: IdBlock,
+    next: usize,
+}
+
+impl Iterator for IdBlockIter {
+    type Item = SnowflakeId;
+
+    fn next(&mut self) -> Option<SnowflakeId> {
+        let id = self.block.get(self.next)?;
+        self.next += 1;
+        Some(id)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.block.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for IdBlockIter {}
+
 // --- Standard trait implementations ---
 
 impl fmt::Display for SnowflakeId {
@@ -262,6 +538,21 @@ impl<'de> serde::Deserialize<'de> for SnowflakeId {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for SnowflakeId128 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u128(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SnowflakeId128 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = u128::deserialize(deserializer)?;
+        Ok(SnowflakeId128(raw))
+    }
+}
+
 /// Wrapper that serializes [`SnowflakeId`] as a decimal string.
 ///
 /// Useful for JSON where `u64` may lose precision in JavaScript.
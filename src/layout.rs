@@ -0,0 +1,1057 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Bit allocation of a Snowflake ID.
+//!
+//! A [`Layout`] describes how the usable bits of an ID (at most 63) are split
+//! between the time, sequence, data center ID and machine ID sections and any
+//! named custom fields, and in which order they are packed. It is a plain `Copy`
+//! value that can be built in a `const` context and shared between the service
+//! producing IDs ([`Builder::layout`](crate::Builder::layout)) and the services
+//! consuming them ([`Layout::decompose`]).
+
+use crate::error::Error;
+use crate::id::SnowflakeId;
+use crate::snowflake::DecomposedSnowflake;
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Maximum bit length of the data center ID and machine ID sections, which hold `u16` values.
+const MAX_ID_BITS: u8 = 16;
+
+/// Maximum width of an ID, leaving the sign bit of an `i64` unset.
+const MAX_WIDTH: u32 = 63;
+
+/// Maximum number of custom fields in a [`Layout`].
+pub const MAX_CUSTOM_FIELDS: usize = 8;
+
+/// Maximum length of a custom field name, in bytes.
+pub const MAX_FIELD_NAME_LEN: usize = 23;
+
+/// Number of built-in sections: time, data center ID, machine ID and sequence.
+const BUILTIN_FIELDS: usize = 4;
+
+/// Maximum number of sections in a [`Layout`].
+const MAX_FIELDS: usize = BUILTIN_FIELDS + MAX_CUSTOM_FIELDS;
+
+// Section codes stored in `Layout::order`; custom field `i` is `CUSTOM + i`.
+const TIME: u8 = 0;
+const DATA_CENTER_ID: u8 = 1;
+const MACHINE_ID: u8 = 2;
+const SEQUENCE: u8 = 3;
+const CUSTOM: u8 = BUILTIN_FIELDS as u8;
+/// Marks an unused slot of `Layout::order`.
+const UNUSED: u8 = u8::MAX;
+
+/// The name of a custom field, stored inline so that a [`Layout`] stays `Copy`
+/// and can be built in a `const` context.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldName {
+    len: u8,
+    bytes: [u8; MAX_FIELD_NAME_LEN],
+}
+
+impl FieldName {
+    const EMPTY: FieldName = FieldName {
+        len: 0,
+        bytes: [0; MAX_FIELD_NAME_LEN],
+    };
+
+    /// Create a field name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is empty or longer than [`MAX_FIELD_NAME_LEN`] bytes.
+    #[must_use]
+    pub const fn new(name: &str) -> Self {
+        match Self::try_new(name) {
+            Some(name) => name,
+            None => panic!("invalid layout: field names must be 1 to 23 bytes long"),
+        }
+    }
+
+    /// Create a field name, or `None` if `name` is empty or longer than
+    /// [`MAX_FIELD_NAME_LEN`] bytes.
+    #[must_use]
+    pub const fn try_new(name: &str) -> Option<Self> {
+        let source = name.as_bytes();
+        if source.is_empty() || source.len() > MAX_FIELD_NAME_LEN {
+            return None;
+        }
+        let mut bytes = [0; MAX_FIELD_NAME_LEN];
+        let mut i = 0;
+        while i < source.len() {
+            bytes[i] = source[i];
+            i += 1;
+        }
+        Some(Self {
+            len: source.len() as u8,
+            bytes,
+        })
+    }
+
+    /// The name as a string slice.
+    #[must_use]
+    pub const fn as_str(&self) -> &str {
+        // The bytes are always a complete copy of a `&str`.
+        match core::str::from_utf8(self.bytes.split_at(self.len as usize).0) {
+            Ok(name) => name,
+            Err(_) => "",
+        }
+    }
+
+    const fn is(&self, name: &str) -> bool {
+        let other = name.as_bytes();
+        if other.len() != self.len as usize {
+            return false;
+        }
+        let mut i = 0;
+        while i < other.len() {
+            if self.bytes[i] != other[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+}
+
+impl core::fmt::Debug for FieldName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl core::fmt::Display for FieldName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FieldName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FieldName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        FieldName::try_new(&name)
+            .ok_or_else(|| serde::de::Error::custom("field names must be 1 to 23 bytes long"))
+    }
+}
+
+/// A section of an ID, used to describe the packing order of a [`Layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Field {
+    /// Elapsed ticks since the start time.
+    Time,
+    /// The data center ID.
+    DataCenterId,
+    /// The machine ID.
+    MachineId,
+    /// The per-tick sequence number.
+    Sequence,
+    /// A named custom field, see [`Layout::with_fields`].
+    Custom(FieldName),
+}
+
+impl Field {
+    /// The custom field called `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is empty or longer than [`MAX_FIELD_NAME_LEN`] bytes.
+    #[must_use]
+    pub const fn custom(name: &str) -> Self {
+        Self::Custom(FieldName::new(name))
+    }
+}
+
+/// A named custom section of a [`Layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CustomField {
+    name: FieldName,
+    bit_len: u8,
+    shift: u8,
+    mask: u64,
+}
+
+impl CustomField {
+    const EMPTY: CustomField = CustomField {
+        name: FieldName::EMPTY,
+        bit_len: 0,
+        shift: 0,
+        mask: 0,
+    };
+}
+
+/// Bit allocation of a Snowflake ID, with precomputed shifts and masks.
+///
+/// By default, from the most significant usable bit down, an ID packs
+/// `time | custom fields | data_center_id | machine_id | sequence`;
+/// [`Layout::with_order`] arranges the sections in any other order.
+///
+/// The sections take up the low [`width`](Layout::width) bits of the ID, which
+/// is 63 in the conventional layout. Narrower layouts keep the remaining high
+/// bits zero, for example 53 bits for IDs that stay exact as JavaScript numbers:
+///
+/// ```rust
+/// use snowflake_me::{Layout, SnowflakeId};
+///
+/// const JS_SAFE: Layout = Layout::new(41, 6, 0, 6);
+///
+/// assert_eq!(JS_SAFE.width(), 53);
+/// assert_eq!(JS_SAFE.max_id(), (1 << 53) - 1); // Number.MAX_SAFE_INTEGER
+/// assert_eq!(JS_SAFE.packed_bytes(SnowflakeId::new(1)), [0, 0, 0, 0, 0, 0, 1]);
+/// ```
+///
+/// ```rust
+/// use snowflake_me::Layout;
+///
+/// const LAYOUT: Layout = Layout::new(41, 12, 5, 5);
+///
+/// let id = LAYOUT.compose(1_000, 7, 3, 2).unwrap();
+/// let parts = LAYOUT.decompose(id);
+/// assert_eq!((parts.time, parts.sequence), (1_000, 7));
+/// assert_eq!((parts.data_center_id, parts.machine_id), (3, 2));
+/// assert_eq!(LAYOUT.max_sequence(), 4_095);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "RawLayout", into = "RawLayout")
+)]
+pub struct Layout {
+    bit_len_time: u8,
+    bit_len_sequence: u8,
+    bit_len_data_center_id: u8,
+    bit_len_machine_id: u8,
+    custom: [CustomField; MAX_CUSTOM_FIELDS],
+    custom_len: u8,
+    /// Section codes, most significant first, padded with `UNUSED`.
+    order: [u8; MAX_FIELDS],
+    time_shift: u8,
+    data_center_id_shift: u8,
+    machine_id_shift: u8,
+    sequence_shift: u8,
+    time_mask: u64,
+    sequence_mask: u64,
+    data_center_id_mask: u64,
+    machine_id_mask: u64,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Layout {
+    /// The default layout: 41 bits of time, 12 of sequence, 5 of data center ID and 5 of machine ID.
+    pub const DEFAULT: Layout = Layout::new(41, 12, 5, 5);
+
+    /// Create a layout from the bit length of each section.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout is invalid; see [`Layout::try_new`]. In a `const`
+    /// context this is a compile-time error.
+    #[must_use]
+    pub const fn new(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+    ) -> Self {
+        Self::with_fields(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            &[],
+        )
+    }
+
+    /// Create a layout from the bit length of each section, checking that it is valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidBitLength`] if the bit lengths sum to more than 63, or if
+    /// the data center ID or machine ID section is wider than 16 bits.
+    pub const fn try_new(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+    ) -> Result<Self, Error> {
+        Self::try_with_fields(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            &[],
+        )
+    }
+
+    /// Create a layout with named custom fields, given as `(name, bit length)` pairs.
+    ///
+    /// The custom fields are packed right below the time section, in the given
+    /// order; [`Layout::with_order`] places them elsewhere. Their values are set
+    /// per generator with [`Builder::field`](crate::Builder::field) or per call
+    /// with [`Snowflake::next_id_with`](crate::Snowflake::next_id_with).
+    ///
+    /// ```rust
+    /// use snowflake_me::Layout;
+    ///
+    /// // 41 + 4 + 6 + 4 + 8 = 63 bits.
+    /// const LAYOUT: Layout =
+    ///     Layout::with_fields(41, 8, 0, 4, &[("entity_type", 4), ("shard", 6)]);
+    ///
+    /// assert_eq!(LAYOUT.max_field("shard"), Some(63));
+    /// assert_eq!(LAYOUT.field_shift("entity_type"), Some(18));
+    /// assert_eq!(LAYOUT.field_shift("shard"), Some(12));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout is invalid; see [`Layout::try_with_fields`]. In a
+    /// `const` context this is a compile-time error.
+    #[must_use]
+    pub const fn with_fields(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+        fields: &[(&str, u8)],
+    ) -> Self {
+        if let Err(reason) = check_fields(fields) {
+            panic!("{}", reason);
+        }
+        assert!(
+            Self::is_valid(
+                bit_len_time,
+                bit_len_sequence,
+                bit_len_data_center_id,
+                bit_len_machine_id,
+                custom_bits(fields)
+            ),
+            "invalid layout: bit lengths must sum to at most 63, with at most 16 bits each for data center and machine ID"
+        );
+        Self::new_unchecked(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            fields,
+        )
+    }
+
+    /// Create a layout with named custom fields, checking that it is valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidField`] if there are more than [`MAX_CUSTOM_FIELDS`]
+    /// custom fields, or a custom field is zero bits wide or does not have a unique
+    /// name of 1 to [`MAX_FIELD_NAME_LEN`] bytes.
+    ///
+    /// Returns [`Error::InvalidBitLength`] if all sections together are wider than
+    /// 63 bits, or if the data center ID or machine ID section is wider than 16 bits.
+    pub const fn try_with_fields(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+        fields: &[(&str, u8)],
+    ) -> Result<Self, Error> {
+        if let Err(reason) = check_fields(fields) {
+            return Err(Error::InvalidField(reason));
+        }
+        if !Self::is_valid(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            custom_bits(fields),
+        ) {
+            return Err(Error::InvalidBitLength(
+                bit_len_time,
+                bit_len_sequence,
+                bit_len_data_center_id,
+                bit_len_machine_id,
+            ));
+        }
+        Ok(Self::new_unchecked(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            fields,
+        ))
+    }
+
+    /// Change the bit lengths of the built-in sections, keeping the custom fields and the order.
+    pub(crate) const fn try_with_bit_lens(
+        self,
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+    ) -> Result<Self, Error> {
+        if !Self::is_valid(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            self.custom_bits(),
+        ) {
+            return Err(Error::InvalidBitLength(
+                bit_len_time,
+                bit_len_sequence,
+                bit_len_data_center_id,
+                bit_len_machine_id,
+            ));
+        }
+        let mut layout = self;
+        layout.bit_len_time = bit_len_time;
+        layout.bit_len_sequence = bit_len_sequence;
+        layout.bit_len_data_center_id = bit_len_data_center_id;
+        layout.bit_len_machine_id = bit_len_machine_id;
+        layout.time_mask = mask(bit_len_time);
+        layout.sequence_mask = mask(bit_len_sequence);
+        layout.data_center_id_mask = mask(bit_len_data_center_id);
+        layout.machine_id_mask = mask(bit_len_machine_id);
+        Ok(layout.with_order_codes(self.order))
+    }
+
+    /// Rearrange the sections, listed most significant first.
+    ///
+    /// For example, placing [`Field::Sequence`] above the worker sections makes
+    /// the IDs of one tick interleave across workers. IDs from one generator
+    /// only increase over time while [`Field::Time`] comes first.
+    ///
+    /// ```rust
+    /// use snowflake_me::layout::{Field, Layout};
+    ///
+    /// const LAYOUT: Layout = Layout::new(41, 12, 5, 5).with_order(&[
+    ///     Field::Time,
+    ///     Field::Sequence,
+    ///     Field::DataCenterId,
+    ///     Field::MachineId,
+    /// ]);
+    ///
+    /// assert_eq!(LAYOUT.sequence_shift(), 10);
+    /// let id = LAYOUT.compose(1, 2, 3, 4).unwrap();
+    /// assert_eq!(id.as_u64(), (1 << 22) | (2 << 10) | (3 << 5) | 4);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` does not list every section exactly once; see
+    /// [`Layout::try_with_order`]. In a `const` context this is a compile-time error.
+    #[must_use]
+    pub const fn with_order(self, order: &[Field]) -> Self {
+        match self.order_codes(order) {
+            Some(codes) => self.with_order_codes(codes),
+            None => panic!("invalid layout: the field order must list every field exactly once"),
+        }
+    }
+
+    /// Rearrange the sections, listed most significant first, checking the order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidFieldOrder`] if `order` does not list every section,
+    /// including each custom field, exactly once.
+    pub const fn try_with_order(self, order: &[Field]) -> Result<Self, Error> {
+        match self.order_codes(order) {
+            Some(codes) => Ok(self.with_order_codes(codes)),
+            None => Err(Error::InvalidFieldOrder),
+        }
+    }
+
+    /// Translate `order` to section codes, or `None` if it does not list every section exactly once.
+    const fn order_codes(&self, order: &[Field]) -> Option<[u8; MAX_FIELDS]> {
+        if order.len() != BUILTIN_FIELDS + self.custom_len as usize {
+            return None;
+        }
+        let mut codes = [UNUSED; MAX_FIELDS];
+        let mut seen = [false; MAX_FIELDS];
+        let mut i = 0;
+        while i < order.len() {
+            let code = match order[i] {
+                Field::Time => TIME,
+                Field::DataCenterId => DATA_CENTER_ID,
+                Field::MachineId => MACHINE_ID,
+                Field::Sequence => SEQUENCE,
+                Field::Custom(name) => match self.find_field(name.as_str()) {
+                    Some(index) => CUSTOM + index as u8,
+                    None => return None,
+                },
+            };
+            if seen[code as usize] {
+                return None;
+            }
+            seen[code as usize] = true;
+            codes[i] = code;
+            i += 1;
+        }
+        Some(codes)
+    }
+
+    /// Store `order` and recompute every shift from it.
+    const fn with_order_codes(self, order: [u8; MAX_FIELDS]) -> Self {
+        let mut layout = self;
+        layout.order = order;
+        let mut shift = 0;
+        let mut i = MAX_FIELDS;
+        while i > 0 {
+            i -= 1;
+            match order[i] {
+                UNUSED => {}
+                TIME => {
+                    layout.time_shift = shift;
+                    shift += layout.bit_len_time;
+                }
+                DATA_CENTER_ID => {
+                    layout.data_center_id_shift = shift;
+                    shift += layout.bit_len_data_center_id;
+                }
+                MACHINE_ID => {
+                    layout.machine_id_shift = shift;
+                    shift += layout.bit_len_machine_id;
+                }
+                SEQUENCE => {
+                    layout.sequence_shift = shift;
+                    shift += layout.bit_len_sequence;
+                }
+                code => {
+                    let field = &mut layout.custom[(code - CUSTOM) as usize];
+                    field.shift = shift;
+                    shift += field.bit_len;
+                }
+            }
+        }
+        layout
+    }
+
+    const fn is_valid(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+        custom_bits: u32,
+    ) -> bool {
+        bit_len_time as u32
+            + bit_len_sequence as u32
+            + bit_len_data_center_id as u32
+            + bit_len_machine_id as u32
+            + custom_bits
+            <= MAX_WIDTH
+            && bit_len_data_center_id <= MAX_ID_BITS
+            && bit_len_machine_id <= MAX_ID_BITS
+    }
+
+    const fn new_unchecked(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_data_center_id: u8,
+        bit_len_machine_id: u8,
+        fields: &[(&str, u8)],
+    ) -> Self {
+        let mut custom = [CustomField::EMPTY; MAX_CUSTOM_FIELDS];
+        let mut i = 0;
+        while i < fields.len() {
+            custom[i] = CustomField {
+                name: FieldName::new(fields[i].0),
+                bit_len: fields[i].1,
+                shift: 0,
+                mask: mask(fields[i].1),
+            };
+            i += 1;
+        }
+        Self {
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+            custom,
+            custom_len: fields.len() as u8,
+            order: [UNUSED; MAX_FIELDS],
+            time_shift: 0,
+            data_center_id_shift: 0,
+            machine_id_shift: 0,
+            sequence_shift: 0,
+            time_mask: mask(bit_len_time),
+            sequence_mask: mask(bit_len_sequence),
+            data_center_id_mask: mask(bit_len_data_center_id),
+            machine_id_mask: mask(bit_len_machine_id),
+        }
+        .with_order_codes(default_order(fields.len()))
+    }
+
+    /// The packing order of the sections, most significant first.
+    pub fn order(&self) -> impl Iterator<Item = Field> + '_ {
+        self.order
+            .iter()
+            .take_while(|&&code| code != UNUSED)
+            .map(|&code| match code {
+                TIME => Field::Time,
+                DATA_CENTER_ID => Field::DataCenterId,
+                MACHINE_ID => Field::MachineId,
+                SEQUENCE => Field::Sequence,
+                code => Field::Custom(self.custom[(code - CUSTOM) as usize].name),
+            })
+    }
+
+    /// The custom fields as `(name, bit length)` pairs, in definition order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, u8)> + '_ {
+        self.custom_fields()
+            .iter()
+            .map(|field| (field.name.as_str(), field.bit_len))
+    }
+
+    /// Position of the lowest bit of the custom field `name`.
+    #[must_use]
+    pub const fn field_shift(&self, name: &str) -> Option<u8> {
+        match self.find_field(name) {
+            Some(index) => Some(self.custom[index].shift),
+            None => None,
+        }
+    }
+
+    /// Largest representable value of the custom field `name`.
+    #[must_use]
+    pub const fn max_field(&self, name: &str) -> Option<u64> {
+        match self.find_field(name) {
+            Some(index) => Some(self.custom[index].mask),
+            None => None,
+        }
+    }
+
+    /// Total bit length of an ID: the sum of all sections, at most 63.
+    ///
+    /// The bits above it are always zero.
+    #[must_use]
+    pub const fn width(&self) -> u8 {
+        (self.bit_len_time as u32
+            + self.bit_len_sequence as u32
+            + self.bit_len_data_center_id as u32
+            + self.bit_len_machine_id as u32
+            + self.custom_bits()) as u8
+    }
+
+    /// Number of bytes needed to hold an ID, see [`Layout::packed_bytes`].
+    #[must_use]
+    pub const fn byte_len(&self) -> usize {
+        self.width().div_ceil(8) as usize
+    }
+
+    /// Largest ID of this layout.
+    #[must_use]
+    pub const fn max_id(&self) -> u64 {
+        mask(self.width())
+    }
+
+    /// Whether `id` fits in the layout's width.
+    #[must_use]
+    pub const fn contains(&self, id: SnowflakeId) -> bool {
+        id.as_u64() <= self.max_id()
+    }
+
+    /// The big-endian bytes of an ID, trimmed to [`Layout::byte_len`] bytes.
+    ///
+    /// A 53-bit layout packs IDs in 7 bytes and a 32-bit one in 4, where
+    /// [`SnowflakeId::int_bytes`] always takes 8.
+    #[must_use]
+    pub fn packed_bytes(&self, id: SnowflakeId) -> Vec<u8> {
+        id.int_bytes()[8 - self.byte_len()..].to_vec()
+    }
+
+    /// Read an ID from the bytes written by [`Layout::packed_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ParseIdFailed`] if `bytes` is not [`Layout::byte_len`]
+    /// bytes long or the ID is wider than the layout.
+    pub fn from_packed_bytes(&self, bytes: &[u8]) -> Result<SnowflakeId, Error> {
+        if bytes.len() != self.byte_len() {
+            return Err(Error::ParseIdFailed(alloc::format!(
+                "expected {} bytes, got {}",
+                self.byte_len(),
+                bytes.len()
+            )));
+        }
+        let id = SnowflakeId::new(bytes.iter().fold(0, |id, &b| (id << 8) | u64::from(b)));
+        if !self.contains(id) {
+            return Err(Error::ParseIdFailed(alloc::format!(
+                "{id} is wider than {} bits",
+                self.width()
+            )));
+        }
+        Ok(id)
+    }
+
+    /// Bit length of the time section.
+    #[must_use]
+    pub const fn bit_len_time(&self) -> u8 {
+        self.bit_len_time
+    }
+
+    /// Bit length of the sequence section.
+    #[must_use]
+    pub const fn bit_len_sequence(&self) -> u8 {
+        self.bit_len_sequence
+    }
+
+    /// Bit length of the data center ID section.
+    #[must_use]
+    pub const fn bit_len_data_center_id(&self) -> u8 {
+        self.bit_len_data_center_id
+    }
+
+    /// Bit length of the machine ID section.
+    #[must_use]
+    pub const fn bit_len_machine_id(&self) -> u8 {
+        self.bit_len_machine_id
+    }
+
+    /// Position of the lowest bit of the time section.
+    #[must_use]
+    pub const fn time_shift(&self) -> u8 {
+        self.time_shift
+    }
+
+    /// Position of the lowest bit of the sequence section.
+    #[must_use]
+    pub const fn sequence_shift(&self) -> u8 {
+        self.sequence_shift
+    }
+
+    /// Position of the lowest bit of the data center ID section.
+    #[must_use]
+    pub const fn data_center_id_shift(&self) -> u8 {
+        self.data_center_id_shift
+    }
+
+    /// Position of the lowest bit of the machine ID section.
+    #[must_use]
+    pub const fn machine_id_shift(&self) -> u8 {
+        self.machine_id_shift
+    }
+
+    /// Largest representable time, in ticks since the start time.
+    #[must_use]
+    pub const fn max_time(&self) -> u64 {
+        self.time_mask
+    }
+
+    /// Largest representable sequence number.
+    #[must_use]
+    pub const fn max_sequence(&self) -> u64 {
+        self.sequence_mask
+    }
+
+    /// Largest representable data center ID.
+    #[must_use]
+    pub const fn max_data_center_id(&self) -> u64 {
+        self.data_center_id_mask
+    }
+
+    /// Largest representable machine ID.
+    #[must_use]
+    pub const fn max_machine_id(&self) -> u64 {
+        self.machine_id_mask
+    }
+
+    /// Pack the components of an ID, leaving any custom fields zero.
+    ///
+    /// Returns `None` if any component does not fit in its section.
+    #[must_use]
+    pub const fn compose(
+        &self,
+        time: u64,
+        sequence: u64,
+        data_center_id: u64,
+        machine_id: u64,
+    ) -> Option<SnowflakeId> {
+        if time > self.time_mask
+            || sequence > self.sequence_mask
+            || data_center_id > self.data_center_id_mask
+            || machine_id > self.machine_id_mask
+        {
+            return None;
+        }
+        Some(SnowflakeId::new(self.compose_unchecked(
+            time,
+            sequence,
+            data_center_id,
+            machine_id,
+        )))
+    }
+
+    /// Pack components that are known to fit in their sections.
+    pub(crate) const fn compose_unchecked(
+        &self,
+        time: u64,
+        sequence: u64,
+        data_center_id: u64,
+        machine_id: u64,
+    ) -> u64 {
+        (time << self.time_shift)
+            | (data_center_id << self.data_center_id_shift)
+            | (machine_id << self.machine_id_shift)
+            | (sequence << self.sequence_shift)
+    }
+
+    /// Decompose an ID into its components.
+    ///
+    /// The returned [`DecomposedSnowflake`] assumes millisecond ticks; use
+    /// [`DecomposedSnowflake::with_time_unit`] for other units.
+    #[must_use]
+    pub fn decompose(&self, id: SnowflakeId) -> DecomposedSnowflake {
+        DecomposedSnowflake::decompose_with_layout(id.as_u64(), self)
+    }
+
+    /// Extract the custom field `name` of an ID.
+    #[must_use]
+    pub const fn field_of(&self, id: SnowflakeId, name: &str) -> Option<u64> {
+        match self.find_field(name) {
+            Some(index) => {
+                let field = &self.custom[index];
+                Some((id.as_u64() >> field.shift) & field.mask)
+            }
+            None => None,
+        }
+    }
+
+    /// Extract the time section of an ID.
+    pub(crate) const fn time_of(&self, id: u64) -> u64 {
+        (id >> self.time_shift) & self.time_mask
+    }
+
+    /// Extract the sequence section of an ID.
+    pub(crate) const fn sequence_of(&self, id: u64) -> u64 {
+        (id >> self.sequence_shift) & self.sequence_mask
+    }
+
+    /// Extract the data center ID section of an ID.
+    pub(crate) const fn data_center_id_of(&self, id: u64) -> u64 {
+        (id >> self.data_center_id_shift) & self.data_center_id_mask
+    }
+
+    /// Extract the machine ID section of an ID.
+    pub(crate) const fn machine_id_of(&self, id: u64) -> u64 {
+        (id >> self.machine_id_shift) & self.machine_id_mask
+    }
+
+    /// Every custom field of an ID, as `(name, value)` pairs in definition order.
+    pub(crate) fn fields_of(&self, id: u64) -> impl Iterator<Item = (FieldName, u64)> + '_ {
+        self.custom_fields()
+            .iter()
+            .map(move |field| (field.name, (id >> field.shift) & field.mask))
+    }
+
+    /// Bit set with one flag per custom field, all set.
+    pub(crate) const fn all_fields(&self) -> u16 {
+        (1 << self.custom_len) - 1
+    }
+
+    /// Write custom field `values` into `bits`, clearing their flags in `unset`.
+    ///
+    /// Fails if a name is not a custom field of this layout or a value does not fit.
+    pub(crate) fn set_fields(
+        &self,
+        mut bits: u64,
+        mut unset: u16,
+        values: &[(&str, u64)],
+    ) -> Result<(u64, u16), Error> {
+        for &(name, value) in values {
+            let index = self
+                .find_field(name)
+                .ok_or_else(|| Error::UnknownField(String::from(name)))?;
+            let field = &self.custom[index];
+            if value > field.mask {
+                return Err(Error::FieldOverflow {
+                    name: String::from(name),
+                    value,
+                    max: field.mask,
+                });
+            }
+            bits = (bits & !(field.mask << field.shift)) | (value << field.shift);
+            unset &= !(1 << index);
+        }
+        Ok((bits, unset))
+    }
+
+    /// Fail with [`Error::MissingField`] naming the first flag set in `unset`.
+    pub(crate) fn check_unset(&self, unset: u16) -> Result<(), Error> {
+        if unset == 0 {
+            return Ok(());
+        }
+        let name = self.custom[unset.trailing_zeros() as usize].name;
+        Err(Error::MissingField(String::from(name.as_str())))
+    }
+
+    /// Total bit length of the custom fields.
+    const fn custom_bits(&self) -> u32 {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < self.custom_len as usize {
+            bits += self.custom[i].bit_len as u32;
+            i += 1;
+        }
+        bits
+    }
+
+    fn custom_fields(&self) -> &[CustomField] {
+        &self.custom[..self.custom_len as usize]
+    }
+
+    const fn find_field(&self, name: &str) -> Option<usize> {
+        let mut i = 0;
+        while i < self.custom_len as usize {
+            if self.custom[i].name.is(name) {
+                return Some(i);
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+/// The default section order for `custom_len` custom fields:
+/// time, the custom fields, data center ID, machine ID, sequence.
+const fn default_order(custom_len: usize) -> [u8; MAX_FIELDS] {
+    let mut order = [UNUSED; MAX_FIELDS];
+    order[0] = TIME;
+    let mut i = 0;
+    while i < custom_len {
+        order[1 + i] = CUSTOM + i as u8;
+        i += 1;
+    }
+    order[1 + custom_len] = DATA_CENTER_ID;
+    order[2 + custom_len] = MACHINE_ID;
+    order[3 + custom_len] = SEQUENCE;
+    order
+}
+
+/// Check custom field definitions, returning why they are invalid.
+const fn check_fields(fields: &[(&str, u8)]) -> Result<(), &'static str> {
+    if fields.len() > MAX_CUSTOM_FIELDS {
+        return Err("invalid layout: at most 8 custom fields are supported");
+    }
+    let mut i = 0;
+    while i < fields.len() {
+        let (name, bit_len) = fields[i];
+        let Some(field_name) = FieldName::try_new(name) else {
+            return Err("invalid layout: field names must be 1 to 23 bytes long");
+        };
+        if bit_len == 0 {
+            return Err("invalid layout: custom fields must be at least 1 bit wide");
+        }
+        let mut j = i + 1;
+        while j < fields.len() {
+            if field_name.is(fields[j].0) {
+                return Err("invalid layout: custom field names must be unique");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Total bit length of custom field definitions.
+const fn custom_bits(fields: &[(&str, u8)]) -> u32 {
+    let mut bits = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        bits += fields[i].1 as u32;
+        i += 1;
+    }
+    bits
+}
+
+/// All-ones mask of the given bit length.
+const fn mask(bits: u8) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    }
+}
+
+/// Serialized form of a [`Layout`]: the bit lengths, the custom fields and,
+/// unless it is the default one, the field order. Validated on deserialization.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawLayout {
+    bit_len_time: u8,
+    bit_len_sequence: u8,
+    bit_len_data_center_id: u8,
+    bit_len_machine_id: u8,
+    #[serde(default, skip_serializing_if = "alloc::vec::Vec::is_empty")]
+    fields: alloc::vec::Vec<(FieldName, u8)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<alloc::vec::Vec<Field>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Layout> for RawLayout {
+    fn from(layout: Layout) -> Self {
+        let is_default_order = layout.order == default_order(layout.custom_len as usize);
+        Self {
+            bit_len_time: layout.bit_len_time,
+            bit_len_sequence: layout.bit_len_sequence,
+            bit_len_data_center_id: layout.bit_len_data_center_id,
+            bit_len_machine_id: layout.bit_len_machine_id,
+            fields: layout
+                .custom_fields()
+                .iter()
+                .map(|field| (field.name, field.bit_len))
+                .collect(),
+            order: (!is_default_order).then(|| layout.order().collect()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawLayout> for Layout {
+    type Error = Error;
+
+    fn try_from(raw: RawLayout) -> Result<Self, Error> {
+        let fields: alloc::vec::Vec<(&str, u8)> = raw
+            .fields
+            .iter()
+            .map(|(name, bit_len)| (name.as_str(), *bit_len))
+            .collect();
+        let layout = Layout::try_with_fields(
+            raw.bit_len_time,
+            raw.bit_len_sequence,
+            raw.bit_len_data_center_id,
+            raw.bit_len_machine_id,
+            &fields,
+        )?;
+        match raw.order {
+            Some(order) => layout.try_with_order(&order),
+            None => Ok(layout),
+        }
+    }
+}
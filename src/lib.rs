@@ -15,9 +15,10 @@
 //! - **Lock-Free Concurrency**: Uses `AtomicU64` and CAS operations to manage internal state, eliminating `Mutex` lock overhead.
 //! - **High Performance**: The lock-free design makes ID generation extremely fast, performing exceptionally well under high concurrency.
 //! - **Highly Customizable**: The `Builder` pattern allows you to flexibly configure the start time, machine ID, data center ID, and the bit lengths of each component.
-//! - **Smart IP Fallback**: With the `ip-fallback` feature enabled, if `machine_id` or `data_center_id` are not provided, the system automatically derives them from local network interfaces.
+//! - **Smart IP Fallback**: With the `ip-fallback` feature enabled, if `machine_id` or `data_center_id` are not provided, the system automatically derives them from local network interfaces. `worker::IpFallback` selects the interface by name, restricts the address to CIDRs and derives the IDs from its octets, low bits or a hash, failing instead of losing bits.
 //!     - **Supports both IPv4 and IPv6**: It prioritizes private IPv4 addresses and falls back to private IPv6 addresses.
 //!     - **Conflict-Free**: To ensure uniqueness, `machine_id` and `data_center_id` are derived from distinct parts of the IP address.
+//! - **Pluggable Clocks**: Each generator reads time through its own [`TimeSource`], so generators with different clocks can coexist in one process.
 //! - **`no_std` Support**: Works in `no_std` + `alloc` environments with a user-provided time source.
 //!
 //! ## Architecture
@@ -33,11 +34,15 @@
 //! ```
 //!
 //! - **Time** (41 bits): Milliseconds since the configured start time. Default epoch is 2022-01-01.
+//!   The tick length can be changed with [`Builder::time_unit`] (e.g., 10ms or 1s ticks).
 //! - **Data Center ID** (5 bits): Identifies the data center (0–31).
 //! - **Machine ID** (5 bits): Identifies the machine within the data center (0–31).
-//! - **Sequence** (12 bits): Per-millisecond counter (0–4095).
+//! - **Sequence** (12 bits): Per-tick counter (0–4095).
 //!
-//! The bit lengths are fully configurable via [`Builder`], as long as they sum to 63.
+//! The bit lengths are fully configurable via [`Builder`], as long as they sum to at most 63;
+//! narrower IDs keep their high bits zero, e.g. 53-bit IDs that are exact as JavaScript numbers.
+//! A [`Layout`] captures them as a single `const` value that can be shared between the
+//! services producing and consuming IDs.
 //!
 //! ## Performance
 //!
@@ -58,11 +63,15 @@
 //! | Feature | Dependencies | Default | Description |
 //! |---------|-------------|---------|-------------|
 //! | `std` | jiff, thiserror/std | Yes | Standard library support (time via jiff) |
-//! | `ip-fallback` | std, pnet_datalink | No | Auto-derive IDs from local IP address |
+//! | `ip-fallback` | std, pnet_datalink | No | Auto-derive IDs from local IP address (the default octet derivation fails unless octets 3/4 fit their sections) |
 //! | `serde` | serde | No | Serde serialization for `SnowflakeId` and `DecomposedSnowflake` |
 //! | `tracing` | tracing | No | Structured logging at key points |
 //! | `metrics` | metrics | No | Runtime counters and gauges |
 //! | `use-strong-cas` | — | No | Use `compare_exchange` instead of `compare_exchange_weak` |
+//! | `testkit` | — | No | `ManualClock` for deterministic tests |
+//! | `tokio` | std, tokio | No | `next_id_async` / `reserve_async` that sleep instead of spinning |
+//! | `redis` | std | No | `RedisWorkerRegistry` leasing worker IDs from a Redis-compatible server |
+//! | `sqlite` | std, rusqlite | No | `SqliteWorkerNodeTable` for `DisposableWorkerId` (bundles SQLite) |
 //! | `full` | all of the above | No | Enable all optional features |
 //!
 //! ## Quick Start
@@ -81,11 +90,18 @@
 //! ```rust
 //! use snowflake_me::Snowflake;
 //!
-//! // Create a generator with the default configuration.
-//! // Note: This requires the `ip-fallback` feature to auto-detect machine and data center IDs.
+//! // Derive the machine and data center IDs from a hash of the local private IP.
+//! // Note: This requires the `ip-fallback` feature. `Snowflake::new()` uses the
+//! // third and fourth octets instead, and fails with `Error::IpAddressOverflow`
+//! // unless they fit the 5-bit sections (e.g. not on 192.168.1.100).
 //! # #[cfg(feature = "ip-fallback")]
 //! # {
-//! let sf = Snowflake::new().unwrap();
+//! use snowflake_me::worker::{IpDerivation, IpFallback};
+//!
+//! let sf = Snowflake::builder()
+//!     .worker_id_resolver(IpFallback::new().derivation(IpDerivation::Hash))
+//!     .finalize()
+//!     .unwrap();
 //! let next_id = sf.next_id().unwrap();
 //! println!("Generated ID: {}", next_id);
 //! # }
@@ -174,6 +190,7 @@
 //! can handle it using one of three strategies:
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! use snowflake_me::{Snowflake, ClockDriftStrategy};
 //!
 //! let sf = Snowflake::builder()
@@ -183,20 +200,29 @@
 //!     .max_clock_drift_ms(5000)  // fail if drift > 5 seconds
 //!     .finalize()
 //!     .unwrap();
+//! # }
 //! ```
 //!
 //! See [`ClockDriftStrategy`] for details on each strategy.
 //!
 //! ## `no_std` Usage
 //!
-//! In `no_std` environments, disable default features and provide a time source:
+//! In `no_std` environments, disable default features and provide a [`TimeSource`]:
 //!
 //! ```rust,ignore
-//! // In your timer interrupt or main loop:
-//! snowflake_me::set_time_source(current_millis);
+//! use snowflake_me::{Snowflake, TimeSource};
+//!
+//! struct Rtc;
+//!
+//! impl TimeSource for Rtc {
+//!     fn current_millis(&self) -> i64 {
+//!         read_rtc_millis()
+//!     }
+//! }
 //!
 //! // Then create the generator (start_time must be a raw i64 in milliseconds):
 //! let sf = Snowflake::builder()
+//!     .time_source(Rtc)
 //!     .start_time(1_640_995_200_000) // 2022-01-01 UTC
 //!     .machine_id(&|| Ok(1))
 //!     .data_center_id(&|| Ok(1))
@@ -213,23 +239,49 @@
 pub struct ReadmeDoctests;
 
 mod builder;
-/// Clock drift handling strategies for backward clock detection.
+/// Clock drift handling strategies for backward clock detection, and the wait
+/// and sequence-start policies.
 pub mod clock;
+mod entropy;
 mod error;
-/// The [`SnowflakeId`] newtype with encoding methods and trait implementations.
+/// The [`SnowflakeId`] newtype with encoding methods and trait implementations, and [`IdBlock`].
 pub mod id;
+/// The [`Layout`] type describing the bit allocation and field order of an ID.
+pub mod layout;
+/// Persisting the last issued timestamp across restarts.
+pub mod persist;
+/// Built-in [`Preset`]s for well-known ID schemes.
+pub mod preset;
+mod sharded;
 mod snowflake;
-pub(crate) mod time;
+mod snowflake128;
+/// Pluggable [`TimeSource`] trait and the built-in clocks.
+pub mod time;
+/// Resolving the worker ID from the environment (e.g. a Kubernetes StatefulSet
+/// ordinal) or leasing it from a registry.
+pub mod worker;
 
 #[cfg(test)]
 mod tests;
 
-pub use builder::Builder;
-pub use clock::ClockDriftStrategy;
+pub use builder::{BorrowedCallbacks, Builder, Callbacks, OwnedBuilder, OwnedCallbacks};
+pub use clock::{ClockDriftStrategy, ClockMode, SequenceStart, WaitPolicy};
+pub use entropy::EntropySource;
 pub use error::Error;
-pub use id::SnowflakeId;
 #[cfg(feature = "serde")]
 pub use id::SnowflakeIdString;
+pub use id::{IdBlock, SnowflakeId, SnowflakeId128};
+pub use layout::{Field, FieldName, Layout};
+#[cfg(feature = "std")]
+pub use persist::FileTimestampStore;
+pub use persist::TimestampStore;
+pub use preset::Preset;
+pub use sharded::ShardedSnowflake;
 pub use snowflake::{DecomposedSnowflake, Snowflake};
-#[cfg(not(feature = "std"))]
-pub use time::set_time_source;
+pub use snowflake128::{Builder128, DecomposedSnowflake128, Layout128, Snowflake128};
+#[cfg(feature = "testkit")]
+pub use time::ManualClock;
+#[cfg(feature = "std")]
+pub use time::{MonotonicClock, SystemClock};
+pub use time::{TimeSource, TimeUnit};
+pub use worker::{WorkerId, WorkerIdRegistry, WorkerIdResolver, WorkerLease};
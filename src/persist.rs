@@ -0,0 +1,157 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Persistence of the last issued timestamp across restarts.
+//!
+//! A generator configured with [`Builder::timestamp_store`](crate::Builder::timestamp_store)
+//! records a high-water mark: a wall-clock time (milliseconds since the Unix epoch)
+//! that no issued ID has reached yet. The mark is written ahead in coarse steps
+//! ([`Builder::persist_interval_ms`](crate::Builder::persist_interval_ms)), so the
+//! store is only touched once per interval. On startup the mark is reloaded and
+//! the generator will not issue IDs from earlier ticks, even if the host clock
+//! went backwards while it was down.
+
+use crate::error::{BoxDynError, Error};
+use core::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+extern crate alloc;
+use alloc::sync::Arc;
+
+/// Durable storage for the timestamp high-water mark.
+///
+/// [`store`](TimestampStore::store) calls are serialized by the generator and
+/// always pass increasing values; implementations only need to make each write
+/// durable before returning.
+pub trait TimestampStore: Send + Sync {
+    /// Load the persisted high-water mark in milliseconds since the Unix epoch.
+    ///
+    /// Returns `Ok(None)` if nothing has been stored yet.
+    fn load(&self) -> Result<Option<i64>, BoxDynError>;
+
+    /// Durably record a new high-water mark in milliseconds since the Unix epoch.
+    fn store(&self, millis: i64) -> Result<(), BoxDynError>;
+}
+
+impl<T: TimestampStore + ?Sized> TimestampStore for Arc<T> {
+    fn load(&self) -> Result<Option<i64>, BoxDynError> {
+        (**self).load()
+    }
+
+    fn store(&self, millis: i64) -> Result<(), BoxDynError> {
+        (**self).store(millis)
+    }
+}
+
+/// A [`TimestampStore`] backed by a small text file.
+///
+/// Each write goes to a sibling temporary file which is synced and then renamed
+/// over the target, so a crash never leaves a truncated mark behind.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct FileTimestampStore {
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl FileTimestampStore {
+    /// Create a store that keeps the high-water mark in the file at `path`.
+    ///
+    /// The file is created on the first write.
+    #[must_use]
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the backing file.
+    #[must_use]
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "std")]
+impl TimestampStore for FileTimestampStore {
+    fn load(&self) -> Result<Option<i64>, BoxDynError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(contents.trim().parse()?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn store(&self, millis: i64) -> Result<(), BoxDynError> {
+        use std::io::Write;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        writeln!(file, "{millis}")?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Write-ahead state for a generator with a [`TimestampStore`].
+pub(crate) struct Persistence {
+    store: Arc<dyn TimestampStore>,
+    /// Wall-clock time (ms) that no issued tick may start at or after.
+    reserved_until: AtomicI64,
+    /// Set while a thread is writing to the store.
+    writing: AtomicBool,
+    /// How far ahead of the current tick each write reserves, in milliseconds.
+    interval_ms: i64,
+}
+
+impl Persistence {
+    pub(crate) fn new(
+        store: Arc<dyn TimestampStore>,
+        reserved_until: i64,
+        interval_ms: i64,
+    ) -> Self {
+        Self {
+            store,
+            reserved_until: AtomicI64::new(reserved_until),
+            writing: AtomicBool::new(false),
+            interval_ms: interval_ms.max(1),
+        }
+    }
+
+    /// Make sure a tick starting at `tick_start` (ms since the Unix epoch) is covered
+    /// by the persisted high-water mark, writing a new mark if needed.
+    pub(crate) fn ensure(&self, tick_start: i64) -> Result<(), Error> {
+        loop {
+            if tick_start < self.reserved_until.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            if self
+                .writing
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                let result = if tick_start < self.reserved_until.load(Ordering::Acquire) {
+                    Ok(())
+                } else {
+                    let next = tick_start.saturating_add(self.interval_ms);
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        high_water_mark = next,
+                        "persisting timestamp high-water mark"
+                    );
+                    self.store
+                        .store(next)
+                        .map(|()| self.reserved_until.store(next, Ordering::Release))
+                        .map_err(Error::TimestampStoreFailed)
+                };
+                self.writing.store(false, Ordering::Release);
+                return result;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
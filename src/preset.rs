@@ -0,0 +1,140 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Layouts, epochs and tick lengths of well-known Snowflake-style ID schemes.
+//!
+//! A [`Preset`] configures a [`Builder`] with [`Builder::preset`] to generate
+//! compatible IDs, and decomposes IDs produced by those systems with
+//! [`Preset::decompose`].
+
+use crate::id::SnowflakeId;
+use crate::layout::{Field, Layout};
+use crate::snowflake::DecomposedSnowflake;
+use crate::time::TimeUnit;
+
+/// The bit layout, epoch and tick length of an ID scheme.
+///
+/// ```rust
+/// use snowflake_me::{Preset, SnowflakeId};
+///
+/// // Example ID from the Discord API reference.
+/// let id = SnowflakeId::new(175_928_847_299_117_063);
+/// let parts = Preset::DISCORD.decompose(id);
+/// assert_eq!(Preset::DISCORD.timestamp_millis(id), 1_462_015_105_796);
+/// assert_eq!((parts.data_center_id, parts.machine_id, parts.sequence), (1, 0, 7));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+    layout: Layout,
+    start_time: i64,
+    time_unit: TimeUnit,
+}
+
+impl Preset {
+    /// Twitter Snowflake: 41 bits of milliseconds, 5 bits of data center ID,
+    /// 5 bits of worker ID (the machine ID) and 12 bits of sequence.
+    ///
+    /// Epoch: 2010-11-04T01:42:54.657Z.
+    pub const TWITTER: Preset = Preset::new(
+        Layout::new(41, 12, 5, 5),
+        1_288_834_974_657,
+        TimeUnit::MILLISECOND,
+    );
+
+    /// Sonyflake: 39 bits of 10ms ticks, 8 bits of sequence, then 16 bits of machine ID.
+    ///
+    /// Unlike the other presets the sequence sits above the machine ID. There is
+    /// no data center ID section. Epoch: 2014-09-01T00:00:00Z, Sonyflake's default.
+    pub const SONYFLAKE: Preset = Preset::new(
+        Layout::new(39, 8, 0, 16).with_order(&[
+            Field::Time,
+            Field::Sequence,
+            Field::MachineId,
+            Field::DataCenterId,
+        ]),
+        1_409_529_600_000,
+        TimeUnit::CENTISECOND,
+    );
+
+    /// Discord: milliseconds, 5 bits of internal worker ID (the data center ID),
+    /// 5 bits of internal process ID (the machine ID) and 12 bits of increment.
+    ///
+    /// Discord's timestamp is 42 bits wide; only the low 41 are kept, which covers
+    /// every ID issued before 2084. Epoch: 2015-01-01T00:00:00Z.
+    pub const DISCORD: Preset = Preset::new(
+        Layout::new(41, 12, 5, 5),
+        1_420_070_400_000,
+        TimeUnit::MILLISECOND,
+    );
+
+    /// Instagram: milliseconds, 13 bits of logical shard ID (the machine ID) and
+    /// 10 bits of sequence.
+    ///
+    /// Instagram's timestamp is 41 bits wide; only the low 40 are kept, which
+    /// covers every ID issued before 2046. There is no data center ID section.
+    /// Epoch: 1314220021721 (2011-08-24T21:07:01.721Z), as in Instagram's ID function.
+    pub const INSTAGRAM: Preset = Preset::new(
+        Layout::new(40, 10, 0, 13),
+        1_314_220_021_721,
+        TimeUnit::MILLISECOND,
+    );
+
+    /// Baidu UidGenerator: 28 bits of seconds, 22 bits of worker ID and 13 bits of sequence.
+    ///
+    /// The worker ID is split into the data center ID (upper 6 bits) and the machine
+    /// ID (lower 16 bits), so `data_center_id << 16 | machine_id` is the worker ID.
+    /// Epoch: 2016-05-20T00:00:00+08:00, UidGenerator's default `epochStr` in China
+    /// Standard Time. The 28-bit time section ran out in late 2024 with this epoch;
+    /// set a later [`Builder::start_time`](crate::Builder::start_time) after applying
+    /// the preset to generate new IDs.
+    pub const BAIDU_UID: Preset = Preset::new(
+        Layout::new(28, 13, 6, 16),
+        1_463_673_600_000,
+        TimeUnit::SECOND,
+    );
+
+    /// Create a preset from a layout, an epoch in milliseconds since the Unix epoch, and a tick length.
+    #[must_use]
+    pub const fn new(layout: Layout, start_time: i64, time_unit: TimeUnit) -> Self {
+        Self {
+            layout,
+            start_time,
+            time_unit,
+        }
+    }
+
+    /// The bit layout.
+    #[must_use]
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The epoch, in milliseconds since the Unix epoch.
+    #[must_use]
+    pub const fn start_time(&self) -> i64 {
+        self.start_time
+    }
+
+    /// The length of one tick of the time section.
+    #[must_use]
+    pub const fn time_unit(&self) -> TimeUnit {
+        self.time_unit
+    }
+
+    /// Decompose an ID produced under this scheme.
+    #[must_use]
+    pub fn decompose(&self, id: SnowflakeId) -> DecomposedSnowflake {
+        self.layout.decompose(id).with_time_unit(self.time_unit)
+    }
+
+    /// The time an ID was generated, in milliseconds since the Unix epoch.
+    #[must_use]
+    pub fn timestamp_millis(&self, id: SnowflakeId) -> i64 {
+        self.start_time + self.decompose(id).elapsed_millis() as i64
+    }
+}
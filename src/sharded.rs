@@ -0,0 +1,176 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::error::Error;
+use crate::id::{IdBlock, SnowflakeId};
+use crate::snowflake::{DecomposedSnowflake, Snowflake};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Maximum number of lane bits, i.e. at most 1024 lanes.
+const MAX_LANE_BITS: u8 = 10;
+
+/// Source of lane indices: one per thread with `std`, one per call otherwise.
+static NEXT_LANE: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static THREAD_LANE: usize = NEXT_LANE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A generator split into independent lanes to avoid CAS contention between threads.
+///
+/// The top bits of the sequence section select a lane, and each lane has its
+/// own cache-line-aligned state, so threads on different lanes never contend.
+/// With the `std` feature every thread sticks to one lane, assigned round-robin
+/// on first use; without it, lanes are assigned round-robin per call. IDs stay
+/// unique, and are ordered by time across lanes but not within a tick.
+///
+/// Each lane issues `2^(bit_len_sequence - lane_bits)` IDs per tick, so the
+/// total capacity is unchanged when all lanes are busy.
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use snowflake_me::Snowflake;
+///
+/// // 4 lanes of 1024 IDs per millisecond each.
+/// let sf = Snowflake::builder()
+///     .machine_id(&|| Ok(1))
+///     .data_center_id(&|| Ok(1))
+///     .finalize_sharded(2)?;
+///
+/// let id = sf.next_id()?;
+/// assert_eq!(sf.decompose(id).machine_id, 1);
+/// assert!(sf.lane_of(id) < sf.lanes());
+/// # }
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+///
+/// Instances can be safely cloned and shared across threads (cloning is a cheap `Arc` increment).
+#[derive(Clone)]
+pub struct ShardedSnowflake(Arc<[Snowflake]>);
+
+impl ShardedSnowflake {
+    /// Split `base` into `2^lane_bits` lanes.
+    pub(crate) fn new(base: &Snowflake, lane_bits: u8) -> Result<Self, Error> {
+        let shared = &base.0;
+        if lane_bits > MAX_LANE_BITS || lane_bits >= shared.layout.bit_len_sequence() {
+            return Err(Error::InvalidLaneBits(lane_bits));
+        }
+        Ok(Self(
+            (0..1u64 << lane_bits)
+                .map(|lane| Snowflake::new_inner(Arc::new(shared.lane(lane, lane_bits))))
+                .collect(),
+        ))
+    }
+
+    /// The lane used by the calling thread.
+    fn current(&self) -> &Snowflake {
+        #[cfg(feature = "std")]
+        let index = THREAD_LANE.with(|lane| *lane);
+        #[cfg(not(feature = "std"))]
+        let index = NEXT_LANE.fetch_add(1, Ordering::Relaxed);
+        &self.0[index % self.0.len()]
+    }
+
+    /// Generate the next unique ID from the calling thread's lane.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    pub fn next_id(&self) -> Result<SnowflakeId, Error> {
+        self.current().next_id()
+    }
+
+    /// Generate the next unique ID from the calling thread's lane without ever
+    /// waiting for the clock.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::try_next_id`].
+    pub fn try_next_id(&self) -> Result<SnowflakeId, Error> {
+        self.current().try_next_id()
+    }
+
+    /// Generate the next unique ID, sleeping instead of spinning when it has to wait.
+    ///
+    /// Requires the `tokio` feature; see [`Snowflake::next_id_async`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    #[cfg(feature = "tokio")]
+    pub async fn next_id_async(&self) -> Result<SnowflakeId, Error> {
+        self.current().next_id_async().await
+    }
+
+    /// Reserve a block of up to `count` IDs from the calling thread's lane.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::reserve`].
+    pub fn reserve(&self, count: usize) -> Result<IdBlock, Error> {
+        self.current().reserve(count)
+    }
+
+    /// Generate multiple unique IDs from the calling thread's lane.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_ids`].
+    pub fn next_ids(&self, count: usize) -> Result<Vec<SnowflakeId>, Error> {
+        self.current().next_ids(count)
+    }
+
+    /// Record an ID received from another node in every lane.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::observe`].
+    pub fn observe(&self, id: SnowflakeId) -> Result<(), Error> {
+        self.0.iter().try_for_each(|lane| lane.observe(id))
+    }
+
+    /// Decompose an ID into its constituent parts using the generator's configuration.
+    ///
+    /// The lane is part of the [`sequence`](DecomposedSnowflake::sequence); see
+    /// [`ShardedSnowflake::lane_of`].
+    #[must_use]
+    pub fn decompose(&self, id: SnowflakeId) -> DecomposedSnowflake {
+        self.0[0].decompose(id)
+    }
+
+    /// The number of lanes.
+    #[must_use]
+    pub fn lanes(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The lane with the given index, to drive it directly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`ShardedSnowflake::lanes`].
+    #[must_use]
+    pub fn lane(&self, index: usize) -> &Snowflake {
+        &self.0[index]
+    }
+
+    /// The index of the lane that issued an ID.
+    #[must_use]
+    pub fn lane_of(&self, id: SnowflakeId) -> usize {
+        let shared = &self.0[0].0;
+        let sequence_bits = shared.max_sequence.count_ones();
+        let sequence =
+            (id.as_u64() >> shared.layout.sequence_shift()) & shared.layout.max_sequence();
+        (sequence >> sequence_bits) as usize
+    }
+}
@@ -6,11 +6,17 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use base64::Engine;
+use base64::engine::general_purpose;
+
 use crate::builder::Builder;
-use crate::clock::ClockDriftStrategy;
+use crate::clock::{ClockDriftStrategy, SequenceStart, WaitPolicy};
 use crate::error::*;
-use crate::id::SnowflakeId;
-use crate::time;
+use crate::id::{IdBlock, SnowflakeId};
+use crate::layout::{FieldName, Layout};
+use crate::persist::Persistence;
+use crate::time::{TimeSource, TimeUnit};
+use crate::worker::LeaseKeeper;
 use core::sync::atomic::{AtomicU64, Ordering};
 
 extern crate alloc;
@@ -30,93 +36,152 @@ pub(crate) struct SharedSnowflake {
     // Cold path — read-only after init
     /// Start timestamp in milliseconds since Unix epoch.
     pub(crate) start_time: i64,
+    /// Length of one tick of the timestamp section.
+    pub(crate) time_unit: TimeUnit,
     /// Data center ID.
     pub(crate) data_center_id: u16,
     /// Machine ID.
     pub(crate) machine_id: u16,
-    /// Bit length of the timestamp section.
-    pub(crate) bit_len_time: u8,
-    /// Bit length of the sequence number section.
-    pub(crate) bit_len_sequence: u8,
-    /// Bit length of the data center ID section.
-    pub(crate) bit_len_data_center_id: u8,
-    /// Bit length of the machine ID section.
-    pub(crate) bit_len_machine_id: u8,
+    /// Bit allocation of generated IDs.
+    pub(crate) layout: Layout,
+    /// Custom field values set on the builder, in place.
+    pub(crate) field_bits: u64,
+    /// One flag per custom field without a value on the builder.
+    pub(crate) unset_fields: u16,
+    /// Largest sequence number this generator issues; below the layout's maximum
+    /// for the lanes of a [`ShardedSnowflake`](crate::ShardedSnowflake).
+    pub(crate) max_sequence: u64,
     /// Strategy for handling backward clock drift.
     pub(crate) clock_drift_strategy: ClockDriftStrategy,
     /// Maximum allowed clock drift in milliseconds (for `Wait` strategy).
     pub(crate) max_clock_drift_ms: Option<i64>,
+    /// How many ticks the timestamp may borrow ahead of the clock (0 to never borrow).
+    pub(crate) max_borrow_ahead: u64,
+    /// How to wait for the clock to reach the next tick.
+    pub(crate) wait_policy: WaitPolicy,
+    /// Maximum time a single call may wait for the clock, in milliseconds.
+    pub(crate) max_wait_ms: Option<i64>,
+    /// Where the sequence starts on each new tick.
+    pub(crate) sequence_start: SequenceStart,
+    /// Clock used to read the current time.
+    pub(crate) time_source: Arc<dyn TimeSource>,
+    /// Timestamp high-water mark persistence, if configured; shared by the lanes
+    /// of a [`ShardedSnowflake`](crate::ShardedSnowflake).
+    pub(crate) persistence: Option<Arc<Persistence>>,
+    /// Lease on the worker ID, if it came from a registry; shared by the lanes of
+    /// a [`ShardedSnowflake`](crate::ShardedSnowflake).
+    pub(crate) lease: Option<Arc<LeaseKeeper>>,
 }
 
-/// A high-performance, distributed, unique ID generator.
-///
-/// Instances can be safely cloned and shared across threads (cloning is a cheap `Arc` increment).
-pub struct Snowflake(pub(crate) Arc<SharedSnowflake>);
+impl SharedSnowflake {
+    /// Ticks elapsed since the configured start time.
+    fn current_elapsed_time(&self) -> i64 {
+        (self.time_source.current_millis() - self.start_time)
+            .div_euclid(self.time_unit.as_millis() as i64)
+    }
 
-impl Snowflake {
-    /// Create a new `Snowflake` generator with default configuration.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if `machine_id` or `data_center_id` cannot be resolved
-    /// (e.g., when the `ip-fallback` feature is disabled and no IDs are provided).
-    pub fn new() -> Result<Self, Error> {
-        Builder::new().finalize()
+    /// Wall-clock time (ms since the Unix epoch) at which the tick after `last_time` starts.
+    fn next_tick_start(&self, last_time: u64) -> i64 {
+        self.start_time + (last_time as i64 + 1).saturating_mul(self.time_unit.as_millis() as i64)
     }
 
-    /// Create a new [`Builder`] to configure a `Snowflake` generator.
-    #[must_use]
-    pub fn builder<'a>() -> Builder<'a> {
-        Builder::new()
+    /// Fail with [`Error::WaitTimeout`] if a wait that began at `wait_started`
+    /// (ms since the Unix epoch) has reached the configured deadline.
+    fn check_wait_deadline(&self, wait_started: i64, now: i64) -> Result<(), Error> {
+        match self.max_wait_ms {
+            Some(max_ms) if now - wait_started >= max_ms => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(max_wait_ms = max_ms, "timed out waiting for the clock");
+                Err(Error::WaitTimeout { max_ms })
+            }
+            _ => Ok(()),
+        }
     }
 
-    pub(crate) fn new_inner(shared: Arc<SharedSnowflake>) -> Self {
-        Self(shared)
+    /// Wait, according to the wait policy, until the clock has moved past tick `last_time`.
+    fn til_next_tick(&self, last_time: u64, wait_started: i64) -> Result<(), Error> {
+        let next_tick = self.next_tick_start(last_time);
+        let mut attempt = 0u32;
+        loop {
+            let now = self.time_source.current_millis();
+            if now >= next_tick {
+                return Ok(());
+            }
+            self.check_wait_deadline(wait_started, now)?;
+            self.wait_policy.wait(attempt);
+            attempt = attempt.saturating_add(1);
+        }
     }
 
-    /// Generate the next unique ID.
-    ///
-    /// This method is lock-free and thread-safe, using CAS operations for high concurrency.
-    ///
-    /// # Errors
-    ///
-    /// Returns [`Error::OverTimeLimit`] if the timestamp exceeds the maximum value
-    /// representable by the configured time bit length.
-    ///
-    /// Returns [`Error::ClockDrift`] when the clock drift strategy is [`ClockDriftStrategy::Error`]
-    /// and backward clock drift is detected.
+    /// Sleep until the clock has moved past tick `last_time`.
+    #[cfg(feature = "tokio")]
+    async fn sleep_til_next_tick(&self, last_time: u64, wait_started: i64) -> Result<(), Error> {
+        let now = self.time_source.current_millis();
+        let remaining = self.next_tick_start(last_time) - now;
+        if remaining > 0 {
+            self.check_wait_deadline(wait_started, now)?;
+            let remaining = match self.max_wait_ms {
+                Some(max_ms) => remaining.min(wait_started + max_ms - now),
+                None => remaining,
+            };
+            tokio::time::sleep(std::time::Duration::from_millis(remaining as u64)).await;
+        }
+        Ok(())
+    }
+
+    /// Claim up to `max` consecutive sequence numbers, returning `(time, first_sequence, count)`.
     ///
-    /// Returns [`Error::ClockDriftExceeded`] when the clock drift strategy is [`ClockDriftStrategy::Wait`]
-    /// and the drift exceeds `max_clock_drift_ms`.
-    pub fn next_id(&self) -> Result<SnowflakeId, Error> {
-        let sequence_mask = (1u64 << self.0.bit_len_sequence) - 1;
-        let time_shift = self.0.bit_len_sequence;
-        let time_max = (1u64 << self.0.bit_len_time) - 1;
+    /// Waits according to the wait policy whenever the generator has to wait for the clock.
+    pub(crate) fn claim(&self, max: u64) -> Result<(u64, u64, u64), Error> {
+        let mut wait_started = None;
+        loop {
+            match self.poll_claim(max)? {
+                Claim::Ready(time, sequence, count) => return Ok((time, sequence, count)),
+                Claim::Pending(last_time) => {
+                    let started =
+                        *wait_started.get_or_insert_with(|| self.time_source.current_millis());
+                    self.til_next_tick(last_time, started)?;
+                }
+            }
+        }
+    }
 
-        #[cfg(feature = "tracing")]
-        tracing::trace!("generating next snowflake id");
+    /// Try to claim up to `max` consecutive sequence numbers without waiting.
+    ///
+    /// This is the lock-free core shared by every generation method. It only loops
+    /// on CAS contention; whenever the clock has to move on first it returns
+    /// [`Claim::Pending`] and leaves the waiting to the caller.
+    pub(crate) fn poll_claim(&self, max: u64) -> Result<Claim, Error> {
+        let sequence_mask = self.max_sequence;
+        let time_shift = self.layout.bit_len_sequence();
+        let time_max = self.layout.max_time();
 
         loop {
-            let current_state = self.0.state.load(Ordering::Relaxed);
+            let current_state = self.state.load(Ordering::Relaxed);
             let last_time = current_state >> time_shift;
 
-            let elapsed_time = current_elapsed_time(self.0.start_time) as u64;
+            let elapsed_time = self.current_elapsed_time() as u64;
+            self.check_lease(elapsed_time)?;
 
-            // Clock drift detection: elapsed_time < last_time means clock went backward
-            if elapsed_time < last_time {
+            // Clock drift detection: elapsed_time < last_time means clock went backward.
+            // A hybrid logical clock, or borrowed ticks within the cap, running ahead
+            // of the physical one is not drift.
+            let hybrid = self.clock_drift_strategy == ClockDriftStrategy::Hybrid;
+            let borrowed = last_time.saturating_sub(elapsed_time) <= self.max_borrow_ahead;
+            let (next_time, next_sequence) = if elapsed_time < last_time && !hybrid && !borrowed {
                 #[cfg(feature = "metrics")]
                 metrics::counter!("snowflake_clock_drift_events_total").increment(1);
                 #[cfg(feature = "tracing")]
                 tracing::warn!(
                     last_time,
                     current_time = elapsed_time,
-                    strategy = ?self.0.clock_drift_strategy,
+                    strategy = ?self.clock_drift_strategy,
                     "clock drift detected"
                 );
-                match self.0.clock_drift_strategy {
+                match self.clock_drift_strategy {
                     ClockDriftStrategy::Wait => {
-                        if let Some(max_drift) = self.0.max_clock_drift_ms {
-                            let drift = last_time - elapsed_time;
+                        if let Some(max_drift) = self.max_clock_drift_ms {
+                            let drift = self.time_unit.ticks_to_millis(last_time - elapsed_time);
                             if drift > max_drift as u64 {
                                 return Err(Error::ClockDriftExceeded {
                                     drift_ms: drift,
@@ -124,8 +189,7 @@ impl Snowflake {
                                 });
                             }
                         }
-                        til_next_millis(self.0.start_time + last_time as i64);
-                        continue;
+                        return Ok(Claim::Pending(last_time));
                     }
                     ClockDriftStrategy::Error => {
                         return Err(Error::ClockDrift {
@@ -136,63 +200,52 @@ impl Snowflake {
                     ClockDriftStrategy::LastTimestamp => {
                         let sequence = (current_state & sequence_mask) + 1;
                         if sequence > sequence_mask {
-                            til_next_millis(self.0.start_time + last_time as i64);
-                            continue;
+                            return Ok(Claim::Pending(last_time));
                         }
-                        let new_state = (last_time << time_shift) | sequence;
-                        let cas_ok = if cfg!(feature = "use-strong-cas") {
-                            self.0
-                                .state
-                                .compare_exchange(
-                                    current_state,
-                                    new_state,
-                                    Ordering::AcqRel,
-                                    Ordering::Relaxed,
-                                )
-                                .is_ok()
-                        } else {
-                            self.0
-                                .state
-                                .compare_exchange_weak(
-                                    current_state,
-                                    new_state,
-                                    Ordering::AcqRel,
-                                    Ordering::Relaxed,
-                                )
-                                .is_ok()
-                        };
-                        if cas_ok {
-                            let id = (last_time
-                                << (self.0.bit_len_data_center_id
-                                    + self.0.bit_len_machine_id
-                                    + self.0.bit_len_sequence))
-                                | (u64::from(self.0.data_center_id)
-                                    << (self.0.bit_len_machine_id + self.0.bit_len_sequence))
-                                | (u64::from(self.0.machine_id) << self.0.bit_len_sequence)
-                                | sequence;
-                            return Ok(SnowflakeId::new(id));
-                        }
-                        continue;
+                        (last_time, sequence)
                     }
+                    ClockDriftStrategy::Hybrid => unreachable!("hybrid clocks never drift"),
                 }
-            }
-
-            let (next_time, next_sequence) = if elapsed_time == last_time {
-                // In the same millisecond, the serial number is incremented
+            } else if elapsed_time <= last_time {
+                // In the same tick, the serial number is incremented
                 let sequence = (current_state & sequence_mask) + 1;
                 if sequence > sequence_mask {
-                    // The serial number has run out, busy waiting until the next millisecond
-                    #[cfg(feature = "metrics")]
-                    metrics::counter!("snowflake_sequence_exhaustion_total").increment(1);
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("sequence exhausted, waiting for next millisecond");
-                    til_next_millis(self.0.start_time + last_time as i64);
-                    continue; // Restart the loop to get a new timestamp
+                    let next_time = last_time + 1;
+                    if hybrid {
+                        // Borrow the next logical tick instead of waiting for it
+                        self.check_logical_drift(next_time, elapsed_time)?;
+                    } else if next_time - elapsed_time <= self.max_borrow_ahead {
+                        // Borrow the next tick while the lead stays within the cap
+                        #[cfg(feature = "tracing")]
+                        tracing::trace!(
+                            lead_ms = self.time_unit.ticks_to_millis(next_time - elapsed_time),
+                            "sequence exhausted, borrowing next tick"
+                        );
+                    } else {
+                        // The serial number has run out, busy waiting until the clock
+                        // is close enough to borrow (or reach) the next tick
+                        #[cfg(feature = "metrics")]
+                        metrics::counter!("snowflake_sequence_exhaustion_total").increment(1);
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(
+                            lead_ms = self.time_unit.ticks_to_millis(last_time - elapsed_time),
+                            "sequence exhausted, waiting for next tick"
+                        );
+                        return Ok(Claim::Pending(last_time - self.max_borrow_ahead));
+                    }
+                    (
+                        next_time,
+                        self.sequence_start.first(next_time, sequence_mask),
+                    )
+                } else {
+                    (last_time, sequence)
                 }
-                (last_time, sequence)
             } else {
-                // new milliseconds, the serial number resets to 0
-                (elapsed_time, 0)
+                // new tick, the serial number resets to the configured start
+                (
+                    elapsed_time,
+                    self.sequence_start.first(elapsed_time, sequence_mask),
+                )
             };
 
             if next_time > time_max {
@@ -201,15 +254,18 @@ impl Snowflake {
                 return Err(Error::OverTimeLimit);
             }
 
+            // Claim as much of the requested range as the current tick still holds
+            let count = max.min(sequence_mask - next_sequence + 1);
+            let last_sequence = next_sequence + count - 1;
+
             // Pack the new time and serial number into a new state
-            let new_state = (next_time << time_shift) | next_sequence;
+            let new_state = (next_time << time_shift) | last_sequence;
 
             // Use CAS (Compare-And-Swap) to update status atomically
             // compare_exchange_weak performs better at high concurrency because it allows spurious failures,
             // which is safe in retry loops. compare_exchange is stronger but slightly slower.
             let cas_ok = if cfg!(feature = "use-strong-cas") {
-                self.0
-                    .state
+                self.state
                     .compare_exchange(
                         current_state,
                         new_state,
@@ -218,8 +274,7 @@ impl Snowflake {
                     )
                     .is_ok()
             } else {
-                self.0
-                    .state
+                self.state
                     .compare_exchange_weak(
                         current_state,
                         new_state,
@@ -229,36 +284,350 @@ impl Snowflake {
                     .is_ok()
             };
             if cas_ok {
-                let id = (next_time
-                    << (self.0.bit_len_data_center_id
-                        + self.0.bit_len_machine_id
-                        + self.0.bit_len_sequence))
-                    | (u64::from(self.0.data_center_id)
-                        << (self.0.bit_len_machine_id + self.0.bit_len_sequence))
-                    | (u64::from(self.0.machine_id) << self.0.bit_len_sequence)
-                    | next_sequence;
+                self.ensure_persisted(next_time)?;
                 #[cfg(feature = "metrics")]
                 {
-                    metrics::counter!("snowflake_ids_generated_total").increment(1);
+                    metrics::counter!("snowflake_ids_generated_total").increment(count);
                     metrics::gauge!("snowflake_sequence_utilization")
-                        .set(next_sequence as f64 / sequence_mask as f64);
+                        .set(last_sequence as f64 / sequence_mask as f64);
+                    if self.max_borrow_ahead > 0 {
+                        metrics::gauge!("snowflake_borrow_ahead_ms").set(
+                            self.time_unit
+                                .ticks_to_millis(next_time.saturating_sub(elapsed_time))
+                                as f64,
+                        );
+                    }
                 }
-                #[cfg(feature = "tracing")]
-                tracing::trace!(
-                    time = next_time,
-                    sequence = next_sequence,
-                    "snowflake id generated"
-                );
-                return Ok(SnowflakeId::new(id));
+                return Ok(Claim::Ready(next_time, next_sequence, count));
             }
             // CAS failure means that another thread has modified its state and the loop will be retried
         }
     }
 
+    /// Fail with [`Error::ClockDriftExceeded`] if logical tick `time` is further
+    /// ahead of the physical tick `elapsed_time` than `max_clock_drift_ms`.
+    fn check_logical_drift(&self, time: u64, elapsed_time: u64) -> Result<(), Error> {
+        if let Some(max_drift) = self.max_clock_drift_ms
+            && time > elapsed_time
+        {
+            let drift = self.time_unit.ticks_to_millis(time - elapsed_time);
+            if drift > max_drift as u64 {
+                return Err(Error::ClockDriftExceeded {
+                    drift_ms: drift,
+                    max_ms: max_drift,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Advance the state past tick `time`, so that every later claim is in a later tick.
+    pub(crate) fn observe(&self, time: u64) -> Result<(), Error> {
+        if time > self.layout.max_time() {
+            return Err(Error::OverTimeLimit);
+        }
+        self.check_logical_drift(time, self.current_elapsed_time().max(0) as u64)?;
+        // The state orders like (time, sequence); an exhausted sequence forces a new tick.
+        let observed = (time << self.layout.bit_len_sequence()) | self.layout.max_sequence();
+        self.state.fetch_max(observed, Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// A generator with the same configuration and state that issues only the
+    /// sequence numbers whose top `lane_bits` bits are `lane`.
+    pub(crate) fn lane(&self, lane: u64, lane_bits: u8) -> Self {
+        let sequence_bits = self.layout.bit_len_sequence() - lane_bits;
+        Self {
+            state: AtomicU64::new(self.state.load(Ordering::Acquire)),
+            start_time: self.start_time,
+            time_unit: self.time_unit,
+            data_center_id: self.data_center_id,
+            machine_id: self.machine_id,
+            layout: self.layout,
+            field_bits: self.field_bits | (lane << sequence_bits) << self.layout.sequence_shift(),
+            unset_fields: self.unset_fields,
+            max_sequence: (1 << sequence_bits) - 1,
+            clock_drift_strategy: self.clock_drift_strategy,
+            max_clock_drift_ms: self.max_clock_drift_ms,
+            max_borrow_ahead: self.max_borrow_ahead,
+            wait_policy: self.wait_policy.clone(),
+            max_wait_ms: self.max_wait_ms,
+            sequence_start: self.sequence_start.clone(),
+            time_source: Arc::clone(&self.time_source),
+            persistence: self.persistence.clone(),
+            lease: self.lease.clone(),
+        }
+    }
+
+    /// Pack the components of an ID.
+    pub(crate) fn compose(&self, time: u64, sequence: u64) -> u64 {
+        self.compose_with(time, sequence, self.field_bits)
+    }
+
+    /// Pack the components of an ID with the given custom field bits.
+    fn compose_with(&self, time: u64, sequence: u64, field_bits: u64) -> u64 {
+        self.layout.compose_unchecked(
+            time,
+            sequence,
+            u64::from(self.data_center_id),
+            u64::from(self.machine_id),
+        ) | field_bits
+    }
+
+    /// Fail if a custom field has no value from the builder.
+    fn check_fields(&self) -> Result<(), Error> {
+        self.layout.check_unset(self.unset_fields)
+    }
+
+    /// Fail if the worker ID lease is lost, renewing it at tick `time` if due.
+    fn check_lease(&self, time: u64) -> Result<(), Error> {
+        match &self.lease {
+            Some(lease) => lease.check(
+                self.start_time + (time as i64).saturating_mul(self.time_unit.as_millis() as i64),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Make sure tick `time` is covered by the persisted high-water mark.
+    fn ensure_persisted(&self, time: u64) -> Result<(), Error> {
+        match &self.persistence {
+            Some(persistence) => persistence.ensure(
+                self.start_time + (time as i64).saturating_mul(self.time_unit.as_millis() as i64),
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Outcome of [`SharedSnowflake::poll_claim`].
+pub(crate) enum Claim {
+    /// Claimed `(time, first_sequence, count)`.
+    Ready(u64, u64, u64),
+    /// Nothing can be claimed until the clock moves past the given tick.
+    Pending(u64),
+}
+
+/// A high-performance, distributed, unique ID generator.
+///
+/// Instances can be safely cloned and shared across threads (cloning is a cheap `Arc` increment).
+pub struct Snowflake(pub(crate) Arc<SharedSnowflake>);
+
+impl Snowflake {
+    /// Create a new `Snowflake` generator with default configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `machine_id` or `data_center_id` cannot be resolved
+    /// (e.g., when the `ip-fallback` feature is disabled and no IDs are provided).
+    /// With `ip-fallback`, fails with `Error::IpAddressOverflow` unless the third
+    /// and fourth octets of the local address fit the 5-bit sections; see
+    /// `worker::IpFallback` for other derivations.
+    pub fn new() -> Result<Self, Error> {
+        Builder::new().finalize()
+    }
+
+    /// Create a new [`Builder`] to configure a `Snowflake` generator.
+    #[must_use]
+    pub fn builder<'a>() -> Builder<'a> {
+        Builder::new()
+    }
+
+    pub(crate) fn new_inner(shared: Arc<SharedSnowflake>) -> Self {
+        Self(shared)
+    }
+
+    /// Generate the next unique ID.
+    ///
+    /// This method is lock-free and thread-safe, using CAS operations for high concurrency.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::OverTimeLimit`] if the timestamp exceeds the maximum value
+    /// representable by the configured time bit length.
+    ///
+    /// Returns [`Error::ClockDrift`] when the clock drift strategy is [`ClockDriftStrategy::Error`]
+    /// and backward clock drift is detected.
+    ///
+    /// Returns [`Error::ClockDriftExceeded`] when the clock drift strategy is [`ClockDriftStrategy::Wait`]
+    /// and the drift exceeds `max_clock_drift_ms`.
+    ///
+    /// Returns [`Error::WaitTimeout`] when waiting for the clock takes longer than
+    /// [`Builder::max_wait_ms`].
+    ///
+    /// Returns [`Error::MissingField`] if a custom field of the [`Layout`] has no
+    /// value from [`Builder::field`]; use [`Snowflake::next_id_with`] instead.
+    ///
+    /// Returns [`Error::LeaseLost`] once the worker ID lease from
+    /// [`Builder::worker_id_registry`] could not be renewed.
+    pub fn next_id(&self) -> Result<SnowflakeId, Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("generating next snowflake id");
+
+        self.0.check_fields()?;
+        let (time, sequence, _) = self.0.claim(1)?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(time, sequence, "snowflake id generated");
+        Ok(SnowflakeId::new(self.0.compose(time, sequence)))
+    }
+
+    /// Generate the next unique ID with per-call values for custom fields of the [`Layout`].
+    ///
+    /// Values given here override those set with [`Builder::field`], and every
+    /// field without a builder value must be given.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "std")] {
+    /// use snowflake_me::{Layout, Snowflake};
+    ///
+    /// const LAYOUT: Layout =
+    ///     Layout::with_fields(41, 8, 0, 4, &[("entity_type", 4), ("shard", 6)]);
+    ///
+    /// let sf = Snowflake::builder()
+    ///     .layout(LAYOUT)
+    ///     .machine_id(&|| Ok(1))
+    ///     .data_center_id(&|| Ok(0))
+    ///     .field("entity_type", 3)
+    ///     .finalize()?;
+    /// let id = sf.next_id_with(&[("shard", 17)])?;
+    ///
+    /// let parts = sf.decompose(id);
+    /// assert_eq!(parts.field("entity_type"), Some(3));
+    /// assert_eq!(parts.field("shard"), Some(17));
+    /// # }
+    /// # Ok::<(), snowflake_me::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnknownField`] if a name is not a custom field of the layout,
+    /// [`Error::FieldOverflow`] if a value does not fit in its field, and
+    /// [`Error::MissingField`] if a field has no value. Otherwise the same as
+    /// [`Snowflake::next_id`].
+    pub fn next_id_with(&self, fields: &[(&str, u64)]) -> Result<SnowflakeId, Error> {
+        let (field_bits, unset) =
+            self.0
+                .layout
+                .set_fields(self.0.field_bits, self.0.unset_fields, fields)?;
+        self.0.layout.check_unset(unset)?;
+        let (time, sequence, _) = self.0.claim(1)?;
+        Ok(SnowflakeId::new(
+            self.0.compose_with(time, sequence, field_bits),
+        ))
+    }
+
+    /// Generate the next unique ID without ever waiting for the clock.
+    ///
+    /// Where [`Snowflake::next_id`] would wait (sequence exhaustion, or backward
+    /// drift under [`ClockDriftStrategy::Wait`]), this returns [`Error::WouldBlock`]
+    /// with the time left until the next tick, so the caller can retry later or
+    /// shed load.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::WouldBlock`] instead of waiting; otherwise the same as
+    /// [`Snowflake::next_id`], except that [`Error::WaitTimeout`] never occurs.
+    pub fn try_next_id(&self) -> Result<SnowflakeId, Error> {
+        self.0.check_fields()?;
+        loop {
+            match self.0.poll_claim(1)? {
+                Claim::Ready(time, sequence, _) => {
+                    return Ok(SnowflakeId::new(self.0.compose(time, sequence)));
+                }
+                Claim::Pending(last_time) => {
+                    let wait_ms =
+                        self.0.next_tick_start(last_time) - self.0.time_source.current_millis();
+                    // Zero means the clock has reached the next tick since the poll; retry.
+                    if wait_ms > 0 {
+                        #[cfg(feature = "metrics")]
+                        metrics::counter!("snowflake_would_block_total").increment(1);
+                        return Err(Error::WouldBlock {
+                            wait_ms: wait_ms as u64,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reserve a contiguous block of up to `count` IDs with a single CAS.
+    ///
+    /// The block is taken from the sequence space remaining in the current tick,
+    /// so it may hold fewer than `count` IDs (but at least one when `count > 0`).
+    /// Iterating the returned [`IdBlock`] does not touch the generator again.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    pub fn reserve(&self, count: usize) -> Result<IdBlock, Error> {
+        self.0.check_fields()?;
+        if count == 0 {
+            return Ok(IdBlock::empty());
+        }
+        let (time, sequence, claimed) = self.0.claim(count as u64)?;
+        Ok(self.block(time, sequence, claimed))
+    }
+
+    fn block(&self, time: u64, sequence: u64, claimed: u64) -> IdBlock {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(time, sequence, claimed, "snowflake id block reserved");
+        IdBlock {
+            base: self.0.compose(time, 0),
+            first_sequence: sequence,
+            len: claimed,
+            sequence_shift: self.0.layout.sequence_shift(),
+        }
+    }
+
+    /// Generate the next unique ID, sleeping instead of spinning when it has to wait.
+    ///
+    /// Whenever [`Snowflake::next_id`] would busy-wait (sequence exhaustion, or
+    /// backward drift under [`ClockDriftStrategy::Wait`]), this awaits a
+    /// `tokio::time::sleep` until the next tick, leaving the worker thread free.
+    /// The configured [`WaitPolicy`] is not used, but [`Builder::max_wait_ms`] is.
+    /// Requires the `tokio` feature and a Tokio runtime with the time driver enabled.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    #[cfg(feature = "tokio")]
+    pub async fn next_id_async(&self) -> Result<SnowflakeId, Error> {
+        let block = self.reserve_async(1).await?;
+        Ok(block.first().expect("reserved block holds at least one ID"))
+    }
+
+    /// Reserve a block of up to `count` IDs, sleeping instead of spinning when it has to wait.
+    ///
+    /// The async counterpart of [`Snowflake::reserve`]. Requires the `tokio` feature.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    #[cfg(feature = "tokio")]
+    pub async fn reserve_async(&self, count: usize) -> Result<IdBlock, Error> {
+        self.0.check_fields()?;
+        if count == 0 {
+            return Ok(IdBlock::empty());
+        }
+        let mut wait_started = None;
+        loop {
+            match self.0.poll_claim(count as u64)? {
+                Claim::Ready(time, sequence, claimed) => {
+                    return Ok(self.block(time, sequence, claimed));
+                }
+                Claim::Pending(last_time) => {
+                    let started =
+                        *wait_started.get_or_insert_with(|| self.0.time_source.current_millis());
+                    self.0.sleep_til_next_tick(last_time, started).await?;
+                }
+            }
+        }
+    }
+
     /// Generate multiple unique IDs in a single call.
     ///
-    /// More efficient than calling `next_id()` in a loop because
-    /// it amortizes overhead across the batch.
+    /// IDs are taken in blocks via [`Snowflake::reserve`], so each tick costs a
+    /// single CAS and clock read rather than one per ID.
     ///
     /// # Errors
     ///
@@ -266,22 +635,55 @@ impl Snowflake {
     /// (e.g., [`Error::OverTimeLimit`] or clock drift errors).
     pub fn next_ids(&self, count: usize) -> Result<Vec<SnowflakeId>, Error> {
         let mut ids = Vec::with_capacity(count);
-        for _ in 0..count {
-            ids.push(self.next_id()?);
+        while ids.len() < count {
+            ids.extend(self.reserve(count - ids.len())?);
         }
         Ok(ids)
     }
 
+    /// Record an ID received from another node, so that every ID issued afterwards is larger.
+    ///
+    /// The generator moves on to a later tick than the observed ID's timestamp,
+    /// read with this generator's [`Layout`]. This gives causal ordering across
+    /// nodes when time is the most significant section, as in the default layout.
+    /// It is meant for [`ClockDriftStrategy::Hybrid`], which keeps issuing IDs
+    /// from the observed timestamp; the other strategies treat an observed ID
+    /// from the future like a clock that moved backward.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "std")] {
+    /// use snowflake_me::{ClockDriftStrategy, Snowflake};
+    ///
+    /// let local = Snowflake::builder()
+    ///     .machine_id(&|| Ok(1))
+    ///     .data_center_id(&|| Ok(1))
+    ///     .clock_drift_strategy(ClockDriftStrategy::Hybrid)
+    ///     .finalize()?;
+    /// // An ID from a node whose clock is one second ahead.
+    /// let remote = local.next_id()?.as_u64() + (1_000 << 22);
+    ///
+    /// local.observe(remote.into())?;
+    /// assert!(local.next_id()?.as_u64() > remote);
+    /// # }
+    /// # Ok::<(), snowflake_me::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ClockDriftExceeded`] if the observed timestamp is further
+    /// ahead of the clock than [`Builder::max_clock_drift_ms`]; the ID is then
+    /// ignored. Returns [`Error::OverTimeLimit`] if the timestamp does not fit the
+    /// layout.
+    pub fn observe(&self, id: SnowflakeId) -> Result<(), Error> {
+        let time = (id.as_u64() >> self.0.layout.time_shift()) & self.0.layout.max_time();
+        self.0.observe(time)
+    }
+
     /// Decompose a Snowflake ID into its constituent parts using the generator's configuration.
     #[must_use]
     pub fn decompose(&self, id: SnowflakeId) -> DecomposedSnowflake {
-        DecomposedSnowflake::decompose(
-            id.as_u64(),
-            self.0.bit_len_time,
-            self.0.bit_len_sequence,
-            self.0.bit_len_data_center_id,
-            self.0.bit_len_machine_id,
-        )
+        DecomposedSnowflake::decompose_with_layout(id.as_u64(), &self.0.layout)
+            .with_time_unit(self.0.time_unit)
     }
 }
 
@@ -294,18 +696,6 @@ impl Clone for Snowflake {
 /// Nanoseconds per millisecond, used to convert elapsed milliseconds to nanoseconds.
 const NANOS_PER_MILLI: i64 = 1_000_000;
 
-fn current_elapsed_time(start_time: i64) -> i64 {
-    time::current_millis() - start_time
-}
-
-fn til_next_millis(last_timestamp: i64) {
-    let mut now = time::current_millis();
-    while now <= last_timestamp {
-        core::hint::spin_loop();
-        now = time::current_millis();
-    }
-}
-
 /// All components of a decomposed Snowflake ID.
 ///
 /// Created by calling [`Snowflake::decompose`] or [`DecomposedSnowflake::decompose`].
@@ -313,22 +703,43 @@ fn til_next_millis(last_timestamp: i64) {
 pub struct DecomposedSnowflake {
     /// The Snowflake ID.
     pub id: SnowflakeId,
-    /// Elapsed milliseconds since the configured start time.
+    /// Elapsed ticks since the configured start time (milliseconds unless a
+    /// different [`TimeUnit`] was configured).
     pub time: u64,
-    /// Sequence number within the same millisecond.
+    /// Sequence number within the same tick.
     pub sequence: u64,
     /// Data center ID.
     pub data_center_id: u64,
     /// Machine ID.
     pub machine_id: u64,
+    /// Length of one tick of [`time`](Self::time).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_unit: TimeUnit,
+    /// Custom fields of the [`Layout`] as `(name, value)` pairs, in definition order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub fields: Vec<(FieldName, u64)>,
+    /// Total bit length of the layout the ID was decomposed with, see [`Layout::width`].
+    #[cfg_attr(feature = "serde", serde(default = "default_width"))]
+    pub width: u8,
+}
+
+#[cfg(feature = "serde")]
+fn default_width() -> u8 {
+    Layout::DEFAULT.width()
 }
 
 impl DecomposedSnowflake {
     /// Decompose a Snowflake ID into its components based on the provided bit lengths.
     ///
+    /// Note the argument order (time, sequence, data center, machine) differs from
+    /// the packing order; prefer [`DecomposedSnowflake::decompose_with_layout`].
+    ///
     /// # Panics
     ///
-    /// Panics if the total bit length does not equal 63.
+    /// Panics if the bit lengths do not form a valid [`Layout`].
     #[must_use]
     pub fn decompose(
         id: u64,
@@ -337,31 +748,51 @@ impl DecomposedSnowflake {
         bit_len_data_center_id: u8,
         bit_len_machine_id: u8,
     ) -> Self {
-        let total_bits = bit_len_time as u32
-            + bit_len_sequence as u32
-            + bit_len_data_center_id as u32
-            + bit_len_machine_id as u32;
-        assert_eq!(total_bits, 63, "Total bit length must be 63");
-
-        // Compute the bit-shift offset for each ID section.
-        let sequence_shift = 0;
-        let machine_id_shift = sequence_shift + bit_len_sequence;
-        let data_center_id_shift = machine_id_shift + bit_len_machine_id;
-        let time_shift = data_center_id_shift + bit_len_data_center_id;
-
-        let sequence_mask = (1u64 << bit_len_sequence) - 1;
-        let machine_id_mask = (1u64 << bit_len_machine_id) - 1;
-        let data_center_id_mask = (1u64 << bit_len_data_center_id) - 1;
+        let layout = Layout::new(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+        );
+        Self::decompose_with_layout(id, &layout)
+    }
 
+    /// Decompose a Snowflake ID into its components according to `layout`.
+    ///
+    /// Assumes millisecond ticks; see [`DecomposedSnowflake::with_time_unit`].
+    #[must_use]
+    pub fn decompose_with_layout(id: u64, layout: &Layout) -> Self {
         Self {
             id: SnowflakeId::new(id),
-            time: id >> time_shift,
-            data_center_id: (id >> data_center_id_shift) & data_center_id_mask,
-            machine_id: (id >> machine_id_shift) & machine_id_mask,
-            sequence: (id >> sequence_shift) & sequence_mask,
+            time: layout.time_of(id),
+            data_center_id: layout.data_center_id_of(id),
+            machine_id: layout.machine_id_of(id),
+            sequence: layout.sequence_of(id),
+            time_unit: TimeUnit::MILLISECOND,
+            fields: layout.fields_of(id).collect(),
+            width: layout.width(),
         }
     }
 
+    /// The value of the custom field `name`, if the layout defines it.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<u64> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.as_str() == name)
+            .map(|&(_, value)| value)
+    }
+
+    /// Interpret [`time`](Self::time) in the given unit instead of milliseconds.
+    ///
+    /// [`DecomposedSnowflake::decompose`] assumes millisecond ticks; use this when
+    /// the ID was generated with a different [`Builder::time_unit`](crate::Builder::time_unit).
+    #[must_use]
+    pub fn with_time_unit(mut self, time_unit: TimeUnit) -> Self {
+        self.time_unit = time_unit;
+        self
+    }
+
     /// Returns the underlying `SnowflakeId`.
     #[must_use]
     pub fn to_id(&self) -> SnowflakeId {
@@ -371,7 +802,7 @@ impl DecomposedSnowflake {
     /// Returns the elapsed time component as nanoseconds.
     #[must_use]
     pub fn nanos_time(&self) -> i64 {
-        (self.time as i64) * NANOS_PER_MILLI
+        (self.elapsed_millis() as i64) * NANOS_PER_MILLI
     }
 
     /// Returns the ID as a signed `i64`.
@@ -416,10 +847,13 @@ impl DecomposedSnowflake {
         self.id.base58()
     }
 
-    /// Returns the base64 encoded string of the raw 8-byte ID.
+    /// Returns the base64 encoded string of [`packed_bytes`](Self::packed_bytes).
+    ///
+    /// The same as [`SnowflakeId::base64`] for 57 to 63-bit layouts, and shorter
+    /// for narrower ones.
     #[must_use]
     pub fn base64(&self) -> String {
-        self.id.base64()
+        general_purpose::STANDARD.encode(self.packed_bytes())
     }
 
     /// Returns the decimal string representation as bytes.
@@ -434,10 +868,17 @@ impl DecomposedSnowflake {
         self.id.int_bytes()
     }
 
+    /// Returns the big-endian bytes of the ID, trimmed to the bytes its
+    /// [`width`](Self::width) needs.
+    #[must_use]
+    pub fn packed_bytes(&self) -> Vec<u8> {
+        self.id.int_bytes()[8 - usize::from(self.width.div_ceil(8))..].to_vec()
+    }
+
     /// Returns the elapsed time in milliseconds since the configured start time.
     #[must_use]
     pub fn elapsed_millis(&self) -> u64 {
-        self.time
+        self.time_unit.ticks_to_millis(self.time)
     }
 }
 
@@ -447,7 +888,11 @@ impl core::fmt::Display for DecomposedSnowflake {
             f,
             "id={}, time={}, data_center={}, machine={}, seq={}",
             self.id, self.time, self.data_center_id, self.machine_id, self.sequence
-        )
+        )?;
+        for (name, value) in &self.fields {
+            write!(f, ", {name}={value}")?;
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,653 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::builder::Builder;
+use crate::clock::{ClockDriftStrategy, ClockMode, SequenceStart, WaitPolicy};
+use crate::entropy::{EntropySource, SplitMix64};
+use crate::error::{BoxDynError, Error};
+use crate::id::{SnowflakeId, SnowflakeId128};
+use crate::persist::TimestampStore;
+use crate::snowflake::Snowflake;
+use crate::time::{TimeSource, TimeUnit};
+
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Maximum bit length of the node section, which holds a `u16` value.
+const MAX_NODE_BITS: u8 = 16;
+
+/// Bit allocation of a [`SnowflakeId128`].
+///
+/// From the most significant bit down, an ID packs
+/// `time | node | sequence | random`. The time and sequence sections together
+/// hold at most 63 bits, so the generator state stays a single atomic word; the
+/// random suffix fills the remaining bits with fresh entropy for every ID.
+///
+/// ```rust
+/// use snowflake_me::Layout128;
+///
+/// // 48 bits of milliseconds last almost 9000 years.
+/// const LAYOUT: Layout128 = Layout128::new(48, 14, 16, 50);
+///
+/// let id = LAYOUT.compose(1_000, 7, 3, 0xabc).unwrap();
+/// let parts = LAYOUT.decompose(id);
+/// assert_eq!((parts.time, parts.sequence, parts.node, parts.random), (1_000, 7, 3, 0xabc));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "RawLayout128", into = "RawLayout128")
+)]
+pub struct Layout128 {
+    bit_len_time: u8,
+    bit_len_sequence: u8,
+    bit_len_node: u8,
+    bit_len_random: u8,
+}
+
+impl Default for Layout128 {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Layout128 {
+    /// The default layout: 48 bits of time, 15 of sequence, 16 of node and 49 of random suffix.
+    pub const DEFAULT: Layout128 = Layout128::new(48, 15, 16, 49);
+
+    /// Create a layout from the bit length of each section.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout is invalid; see [`Layout128::try_new`]. In a `const`
+    /// context this is a compile-time error.
+    #[must_use]
+    pub const fn new(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_node: u8,
+        bit_len_random: u8,
+    ) -> Self {
+        assert!(
+            Self::is_valid(bit_len_time, bit_len_sequence, bit_len_node, bit_len_random),
+            "invalid layout: time must be non-empty, time and sequence must sum to at most 63 bits, node must be at most 16 bits and the total at most 128"
+        );
+        Self {
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_node,
+            bit_len_random,
+        }
+    }
+
+    /// Create a layout from the bit length of each section, checking that it is valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidLayout128`] if the time section is empty, the time
+    /// and sequence sections sum to more than 63 bits, the node section is wider than 16 bits, or all
+    /// sections together are wider than 128 bits.
+    pub const fn try_new(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_node: u8,
+        bit_len_random: u8,
+    ) -> Result<Self, Error> {
+        if !Self::is_valid(bit_len_time, bit_len_sequence, bit_len_node, bit_len_random) {
+            return Err(Error::InvalidLayout128(
+                bit_len_time,
+                bit_len_sequence,
+                bit_len_node,
+                bit_len_random,
+            ));
+        }
+        Ok(Self {
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_node,
+            bit_len_random,
+        })
+    }
+
+    const fn is_valid(
+        bit_len_time: u8,
+        bit_len_sequence: u8,
+        bit_len_node: u8,
+        bit_len_random: u8,
+    ) -> bool {
+        let state_bits = bit_len_time as u32 + bit_len_sequence as u32;
+        bit_len_time > 0
+            && state_bits <= 63
+            && bit_len_node <= MAX_NODE_BITS
+            && state_bits + bit_len_node as u32 + bit_len_random as u32 <= 128
+    }
+
+    /// Bit length of the time section.
+    #[must_use]
+    pub const fn bit_len_time(&self) -> u8 {
+        self.bit_len_time
+    }
+
+    /// Bit length of the sequence section.
+    #[must_use]
+    pub const fn bit_len_sequence(&self) -> u8 {
+        self.bit_len_sequence
+    }
+
+    /// Bit length of the node section.
+    #[must_use]
+    pub const fn bit_len_node(&self) -> u8 {
+        self.bit_len_node
+    }
+
+    /// Bit length of the random suffix.
+    #[must_use]
+    pub const fn bit_len_random(&self) -> u8 {
+        self.bit_len_random
+    }
+
+    /// Total bit length of an ID; the bits above it are always zero.
+    #[must_use]
+    pub const fn width(&self) -> u8 {
+        self.bit_len_time + self.bit_len_node + self.bit_len_sequence + self.bit_len_random
+    }
+
+    /// Position of the lowest bit of the time section.
+    #[must_use]
+    pub const fn time_shift(&self) -> u8 {
+        self.node_shift() + self.bit_len_node
+    }
+
+    /// Position of the lowest bit of the node section.
+    #[must_use]
+    pub const fn node_shift(&self) -> u8 {
+        self.sequence_shift() + self.bit_len_sequence
+    }
+
+    /// Position of the lowest bit of the sequence section.
+    #[must_use]
+    pub const fn sequence_shift(&self) -> u8 {
+        self.bit_len_random
+    }
+
+    /// Largest representable time, in ticks since the start time.
+    #[must_use]
+    pub const fn max_time(&self) -> u64 {
+        mask(self.bit_len_time) as u64
+    }
+
+    /// Largest representable sequence number.
+    #[must_use]
+    pub const fn max_sequence(&self) -> u64 {
+        mask(self.bit_len_sequence) as u64
+    }
+
+    /// Largest representable node ID.
+    #[must_use]
+    pub const fn max_node(&self) -> u64 {
+        mask(self.bit_len_node) as u64
+    }
+
+    /// Largest representable random suffix.
+    #[must_use]
+    pub const fn max_random(&self) -> u128 {
+        mask(self.bit_len_random)
+    }
+
+    /// Pack the components of an ID.
+    ///
+    /// Returns `None` if any component does not fit in its section.
+    #[must_use]
+    pub const fn compose(
+        &self,
+        time: u64,
+        sequence: u64,
+        node: u64,
+        random: u128,
+    ) -> Option<SnowflakeId128> {
+        if time > self.max_time()
+            || sequence > self.max_sequence()
+            || node > self.max_node()
+            || random > self.max_random()
+        {
+            return None;
+        }
+        Some(SnowflakeId128::new(
+            self.compose_unchecked(time, sequence, node, random),
+        ))
+    }
+
+    /// Pack components that are known to fit in their sections.
+    const fn compose_unchecked(&self, time: u64, sequence: u64, node: u64, random: u128) -> u128 {
+        ((time as u128) << self.time_shift())
+            | ((node as u128) << self.node_shift())
+            | ((sequence as u128) << self.sequence_shift())
+            | random
+    }
+
+    /// Decompose an ID into its components.
+    ///
+    /// The returned [`DecomposedSnowflake128`] assumes millisecond ticks; use
+    /// [`DecomposedSnowflake128::with_time_unit`] for other units.
+    #[must_use]
+    pub fn decompose(&self, id: SnowflakeId128) -> DecomposedSnowflake128 {
+        let raw = id.as_u128();
+        DecomposedSnowflake128 {
+            id,
+            time: ((raw >> self.time_shift()) & mask(self.bit_len_time)) as u64,
+            sequence: ((raw >> self.sequence_shift()) & mask(self.bit_len_sequence)) as u64,
+            node: ((raw >> self.node_shift()) & mask(self.bit_len_node)) as u64,
+            random: raw & mask(self.bit_len_random),
+            time_unit: TimeUnit::MILLISECOND,
+        }
+    }
+}
+
+/// All-ones mask of the given bit length.
+const fn mask(bits: u8) -> u128 {
+    if bits >= 128 {
+        u128::MAX
+    } else {
+        (1u128 << bits) - 1
+    }
+}
+
+/// Serialized form of a [`Layout128`], validated on deserialization.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawLayout128 {
+    bit_len_time: u8,
+    bit_len_sequence: u8,
+    bit_len_node: u8,
+    bit_len_random: u8,
+}
+
+#[cfg(feature = "serde")]
+impl From<Layout128> for RawLayout128 {
+    fn from(layout: Layout128) -> Self {
+        Self {
+            bit_len_time: layout.bit_len_time,
+            bit_len_sequence: layout.bit_len_sequence,
+            bit_len_node: layout.bit_len_node,
+            bit_len_random: layout.bit_len_random,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawLayout128> for Layout128 {
+    type Error = Error;
+
+    fn try_from(raw: RawLayout128) -> Result<Self, Error> {
+        Layout128::try_new(
+            raw.bit_len_time,
+            raw.bit_len_sequence,
+            raw.bit_len_node,
+            raw.bit_len_random,
+        )
+    }
+}
+
+/// Shared state of a [`Snowflake128`].
+struct Shared128 {
+    /// Generator of the time and sequence sections, with a 0-bit data center and machine ID.
+    inner: Snowflake,
+    layout: Layout128,
+    /// The node ID, in place.
+    node_bits: u128,
+    entropy: Arc<dyn EntropySource>,
+}
+
+/// A lock-free generator of 128-bit [`SnowflakeId128`]s.
+///
+/// Time and sequence are claimed exactly like [`Snowflake`] does, with the same
+/// [`ClockDriftStrategy`], [`WaitPolicy`], [`TimeSource`] and persistence
+/// options; each ID then gets the node ID and a fresh random suffix. IDs from
+/// one generator are strictly increasing.
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use snowflake_me::Snowflake128;
+///
+/// let sf = Snowflake128::builder().node_id(&|| Ok(7)).finalize()?;
+/// let id = sf.next_id()?;
+/// assert_eq!(sf.decompose(id).node, 7);
+/// assert_eq!(id.uuid().len(), 36);
+/// # }
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+///
+/// Instances can be safely cloned and shared across threads (cloning is a cheap `Arc` increment).
+#[derive(Clone)]
+pub struct Snowflake128(Arc<Shared128>);
+
+impl Snowflake128 {
+    /// Create a new [`Builder128`] to configure a `Snowflake128` generator.
+    #[must_use]
+    pub fn builder<'a>() -> Builder128<'a> {
+        Builder128::new()
+    }
+
+    /// Generate the next unique ID.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    pub fn next_id(&self) -> Result<SnowflakeId128, Error> {
+        self.0.inner.next_id().map(|id| self.widen(id))
+    }
+
+    /// Generate the next unique ID without ever waiting for the clock.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::try_next_id`].
+    pub fn try_next_id(&self) -> Result<SnowflakeId128, Error> {
+        self.0.inner.try_next_id().map(|id| self.widen(id))
+    }
+
+    /// Generate the next unique ID, sleeping instead of spinning when it has to wait.
+    ///
+    /// Requires the `tokio` feature; see [`Snowflake::next_id_async`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_id`].
+    #[cfg(feature = "tokio")]
+    pub async fn next_id_async(&self) -> Result<SnowflakeId128, Error> {
+        self.0.inner.next_id_async().await.map(|id| self.widen(id))
+    }
+
+    /// Generate multiple unique IDs in a single call.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::next_ids`].
+    pub fn next_ids(&self, count: usize) -> Result<Vec<SnowflakeId128>, Error> {
+        let mut ids = Vec::with_capacity(count);
+        while ids.len() < count {
+            let block = self.0.inner.reserve(count - ids.len())?;
+            ids.extend(block.iter().map(|id| self.widen(id)));
+        }
+        Ok(ids)
+    }
+
+    /// Record an ID received from another node, so that every ID issued afterwards is larger.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Snowflake::observe`].
+    pub fn observe(&self, id: SnowflakeId128) -> Result<(), Error> {
+        let layout = self.0.layout;
+        let time = (id.as_u128() >> layout.time_shift()) as u64 & layout.max_time();
+        self.0.inner.0.observe(time)
+    }
+
+    /// Decompose an ID into its constituent parts using the generator's configuration.
+    #[must_use]
+    pub fn decompose(&self, id: SnowflakeId128) -> DecomposedSnowflake128 {
+        self.0
+            .layout
+            .decompose(id)
+            .with_time_unit(self.0.inner.0.time_unit)
+    }
+
+    /// The generator's layout.
+    #[must_use]
+    pub fn layout(&self) -> Layout128 {
+        self.0.layout
+    }
+
+    /// Turn an ID of the inner generator (`time | sequence`) into a 128-bit ID.
+    fn widen(&self, id: SnowflakeId) -> SnowflakeId128 {
+        let shared = &self.0;
+        let raw = id.as_u64();
+        let time = raw >> shared.layout.bit_len_sequence;
+        let sequence = raw & shared.layout.max_sequence();
+        let random = match shared.layout.bit_len_random {
+            0 => 0,
+            1..=64 => u128::from(shared.entropy.next_u64()),
+            _ => {
+                (u128::from(shared.entropy.next_u64()) << 64)
+                    | u128::from(shared.entropy.next_u64())
+            }
+        } & shared.layout.max_random();
+        SnowflakeId128::new(
+            shared.layout.compose_unchecked(time, sequence, 0, random) | shared.node_bits,
+        )
+    }
+}
+
+/// A builder for constructing the [`Snowflake128`] generator.
+///
+/// Use [`Snowflake128::builder()`] to create an instance. The clock, wait and
+/// persistence options behave as on [`Builder`].
+pub struct Builder128<'a> {
+    inner: Builder<'a>,
+    layout: Layout128,
+    node_id: Option<&'a dyn Fn() -> Result<u16, BoxDynError>>,
+    entropy: Option<Arc<dyn EntropySource>>,
+}
+
+impl Default for Builder128<'_> {
+    fn default() -> Self {
+        Builder128::new()
+    }
+}
+
+impl<'a> Builder128<'a> {
+    /// Construct a new builder with default configuration.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            inner: Builder::new(),
+            layout: Layout128::DEFAULT,
+            node_id: None,
+            entropy: None,
+        }
+    }
+
+    /// Set the bit allocation of generated IDs. Defaults to [`Layout128::DEFAULT`].
+    #[must_use]
+    pub fn layout(mut self, layout: Layout128) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Set the node ID.
+    ///
+    /// Required; if the provided closure returns an error, [`Builder128::finalize`] will fail.
+    #[must_use]
+    pub fn node_id(mut self, node_id: &'a dyn Fn() -> Result<u16, BoxDynError>) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    /// Set the source of the random suffix.
+    ///
+    /// Defaults to a SplitMix64 generator seeded at [`Builder128::finalize`],
+    /// which is fast but not cryptographically secure.
+    #[must_use]
+    pub fn entropy_source<T: EntropySource + 'static>(mut self, entropy: T) -> Self {
+        self.entropy = Some(Arc::new(entropy));
+        self
+    }
+
+    /// See [`Builder::start_time`].
+    #[must_use]
+    pub fn start_time(mut self, start_time: i64) -> Self {
+        self.inner = self.inner.start_time(start_time);
+        self
+    }
+
+    /// See [`Builder::time_unit`].
+    #[must_use]
+    pub fn time_unit(mut self, time_unit: TimeUnit) -> Self {
+        self.inner = self.inner.time_unit(time_unit);
+        self
+    }
+
+    /// See [`Builder::clock_drift_strategy`].
+    #[must_use]
+    pub fn clock_drift_strategy(mut self, strategy: ClockDriftStrategy) -> Self {
+        self.inner = self.inner.clock_drift_strategy(strategy);
+        self
+    }
+
+    /// See [`Builder::max_clock_drift_ms`].
+    #[must_use]
+    pub fn max_clock_drift_ms(mut self, ms: i64) -> Self {
+        self.inner = self.inner.max_clock_drift_ms(ms);
+        self
+    }
+
+    /// See [`Builder::max_borrow_ahead_ms`].
+    #[must_use]
+    pub fn max_borrow_ahead_ms(mut self, ms: i64) -> Self {
+        self.inner = self.inner.max_borrow_ahead_ms(ms);
+        self
+    }
+
+    /// See [`Builder::wait_policy`].
+    #[must_use]
+    pub fn wait_policy(mut self, policy: WaitPolicy) -> Self {
+        self.inner = self.inner.wait_policy(policy);
+        self
+    }
+
+    /// See [`Builder::max_wait_ms`].
+    #[must_use]
+    pub fn max_wait_ms(mut self, ms: i64) -> Self {
+        self.inner = self.inner.max_wait_ms(ms);
+        self
+    }
+
+    /// See [`Builder::sequence_start`].
+    #[must_use]
+    pub fn sequence_start(mut self, start: SequenceStart) -> Self {
+        self.inner = self.inner.sequence_start(start);
+        self
+    }
+
+    /// See [`Builder::time_source`].
+    #[must_use]
+    pub fn time_source<T: TimeSource + 'static>(mut self, time_source: T) -> Self {
+        self.inner = self.inner.time_source(time_source);
+        self
+    }
+
+    /// See [`Builder::clock_mode`].
+    #[must_use]
+    pub fn clock_mode(mut self, mode: ClockMode) -> Self {
+        self.inner = self.inner.clock_mode(mode);
+        self
+    }
+
+    /// See [`Builder::timestamp_store`].
+    #[must_use]
+    pub fn timestamp_store<T: TimestampStore + 'static>(mut self, store: T) -> Self {
+        self.inner = self.inner.timestamp_store(store);
+        self
+    }
+
+    /// See [`Builder::persist_interval_ms`].
+    #[must_use]
+    pub fn persist_interval_ms(mut self, ms: i64) -> Self {
+        self.inner = self.inner.persist_interval_ms(ms);
+        self
+    }
+
+    /// Finish building and create a [`Snowflake128`] instance.
+    ///
+    /// Returns an error if validation fails or the node ID closure returns an error.
+    pub fn finalize(self) -> Result<Snowflake128, Error> {
+        let layout = self.layout;
+        let node_id = match self.node_id {
+            Some(node_id_fn) => node_id_fn().map_err(Error::MachineIdFailed)?,
+            None => return Err(Error::MachineIdFailed("Node ID not provided".into())),
+        };
+        if u64::from(node_id) > layout.max_node() {
+            return Err(Error::MachineIdFailed(
+                format!(
+                    "Node ID {node_id} is greater than the max allowed value {}",
+                    layout.max_node()
+                )
+                .into(),
+            ));
+        }
+
+        let inner = self
+            .inner
+            .bit_len_time(layout.bit_len_time)
+            .bit_len_sequence(layout.bit_len_sequence)
+            .bit_len_data_center_id(0)
+            .bit_len_machine_id(0)
+            .machine_id(&|| Ok(0))
+            .data_center_id(&|| Ok(0))
+            .finalize()?;
+        let entropy = match self.entropy {
+            Some(entropy) => entropy,
+            None => Arc::new(SplitMix64::seeded(inner.0.time_source.as_ref())),
+        };
+
+        Ok(Snowflake128(Arc::new(Shared128 {
+            inner,
+            layout,
+            node_bits: u128::from(node_id) << layout.node_shift(),
+            entropy,
+        })))
+    }
+}
+
+/// All components of a decomposed [`SnowflakeId128`].
+///
+/// Created by calling [`Snowflake128::decompose`] or [`Layout128::decompose`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecomposedSnowflake128 {
+    /// The Snowflake ID.
+    pub id: SnowflakeId128,
+    /// Elapsed ticks since the configured start time.
+    pub time: u64,
+    /// Sequence number within the same tick.
+    pub sequence: u64,
+    /// Node ID.
+    pub node: u64,
+    /// Random suffix.
+    pub random: u128,
+    /// Length of one tick of [`time`](Self::time).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_unit: TimeUnit,
+}
+
+impl DecomposedSnowflake128 {
+    /// Interpret [`time`](Self::time) in the given unit instead of milliseconds.
+    #[must_use]
+    pub fn with_time_unit(mut self, time_unit: TimeUnit) -> Self {
+        self.time_unit = time_unit;
+        self
+    }
+
+    /// Returns the elapsed time in milliseconds since the configured start time.
+    #[must_use]
+    pub fn elapsed_millis(&self) -> u64 {
+        self.time_unit.ticks_to_millis(self.time)
+    }
+}
+
+impl core::fmt::Display for DecomposedSnowflake128 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "id={}, time={}, node={}, seq={}, random={:x}",
+            self.id, self.time, self.node, self.sequence, self.random
+        )
+    }
+}
@@ -6,9 +6,12 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#[cfg(feature = "std")]
 use crate::ClockDriftStrategy;
-use crate::{SnowflakeId, error::*, snowflake::Snowflake};
+use crate::{
+    SnowflakeId,
+    error::*,
+    snowflake::{DecomposedSnowflake, Snowflake},
+};
 #[cfg(feature = "std")]
 use std::time::Duration;
 #[cfg(feature = "std")]
@@ -249,7 +252,7 @@ fn test_over_time_limit() -> Result<(), BoxDynError> {
 
     // Manually set the state to be over the time limit
     let time_max = 1u64 << bit_len_time;
-    let time_shift = sf.0.bit_len_sequence;
+    let time_shift = sf.0.layout.bit_len_sequence();
     let state_over_limit = time_max << time_shift;
     sf.0.state.store(state_over_limit, Ordering::Relaxed);
 
@@ -403,7 +406,7 @@ fn test_clock_drift_error_strategy() -> Result<(), BoxDynError> {
     let _id = sf.next_id()?;
 
     // Read current elapsed time and set state to a time far ahead of it
-    let time_shift = sf.0.bit_len_sequence;
+    let time_shift = sf.0.layout.bit_len_sequence();
     let current_elapsed = crate::time::current_millis() - sf.0.start_time;
     let future_time = (current_elapsed as u64) + 100_000; // 100 seconds in the future
     sf.0.state
@@ -429,7 +432,7 @@ fn test_clock_drift_last_timestamp_strategy() -> Result<(), BoxDynError> {
     let _id = sf.next_id()?;
 
     // Read current elapsed time and set state to a time far ahead of it
-    let time_shift = sf.0.bit_len_sequence;
+    let time_shift = sf.0.layout.bit_len_sequence();
     let current_elapsed = crate::time::current_millis() - sf.0.start_time;
     let future_time = (current_elapsed as u64) + 100_000;
     sf.0.state
@@ -461,7 +464,7 @@ fn test_clock_drift_exceeded() -> Result<(), BoxDynError> {
     let _id = sf.next_id()?;
 
     // Read current elapsed time and set state to a time far ahead (drift > 50ms)
-    let time_shift = sf.0.bit_len_sequence;
+    let time_shift = sf.0.layout.bit_len_sequence();
     let current_elapsed = crate::time::current_millis() - sf.0.start_time;
     let future_time = (current_elapsed as u64) + 100_000; // 100 seconds >> 50ms
     sf.0.state
@@ -594,6 +597,70 @@ fn test_next_ids_concurrent() -> Result<(), BoxDynError> {
     Ok(())
 }
 
+#[test]
+fn test_reserve_block_within_tick() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = manual_builder(&clock).finalize()?;
+
+    let first = sf.next_id()?;
+    let block = sf.reserve(100)?;
+    assert_eq!(block.len(), 100);
+    assert!(block.first().unwrap() > first);
+
+    let ids: Vec<_> = block.iter().collect();
+    assert_eq!(ids.len(), 100);
+    for (i, id) in ids.iter().enumerate() {
+        let parts = sf.decompose(*id);
+        assert_eq!(parts.time, 10);
+        assert_eq!(parts.sequence, i as u64 + 1);
+        assert_eq!(parts.machine_id, 1);
+        assert_eq!(parts.data_center_id, 1);
+    }
+    assert_eq!(block.last(), ids.last().copied());
+    assert_eq!(block.get(100), None);
+
+    // The block was claimed in one step: the next ID follows it directly.
+    assert_eq!(sf.decompose(sf.next_id()?).sequence, 101);
+    Ok(())
+}
+
+#[test]
+fn test_reserve_clamps_to_remaining_sequence() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = manual_builder(&clock)
+        .bit_len_time(49)
+        .bit_len_sequence(4)
+        .finalize()?;
+
+    let block = sf.reserve(100)?;
+    assert_eq!(block.len(), 16);
+    assert_eq!(sf.decompose(block.first().unwrap()).sequence, 0);
+    assert_eq!(sf.decompose(block.last().unwrap()).sequence, 15);
+
+    clock.advance(1);
+    let block = sf.reserve(3)?;
+    assert_eq!(block.len(), 3);
+    assert_eq!(sf.decompose(block.first().unwrap()).time, 11);
+
+    assert!(sf.reserve(0)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_next_ids_spans_ticks() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    clock.auto_advance(1);
+    let sf = manual_builder(&clock)
+        .bit_len_time(49)
+        .bit_len_sequence(4)
+        .finalize()?;
+
+    let ids = sf.next_ids(40)?;
+    assert_eq!(ids.len(), 40);
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    Ok(())
+}
+
 #[test]
 fn test_cache_line_alignment() {
     use crate::snowflake::SharedSnowflake;
@@ -611,7 +678,10 @@ fn test_cache_line_alignment() {
 #[test]
 #[ignore = "benchmark, run with `cargo test -- --ignored`"]
 fn bench_single_thread_performance() -> Result<(), BoxDynError> {
-    let sf = Snowflake::new()?;
+    let sf = Snowflake::builder()
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()?;
     let iterations = 1_000_000;
 
     let start = Instant::now();
@@ -636,7 +706,12 @@ fn bench_single_thread_performance() -> Result<(), BoxDynError> {
 #[test]
 #[ignore = "benchmark, run with `cargo test -- --ignored`"]
 fn bench_multi_thread_throughput() -> Result<(), BoxDynError> {
-    let sf = Arc::new(Snowflake::new()?);
+    let sf = Arc::new(
+        Snowflake::builder()
+            .machine_id(&|| Ok(1))
+            .data_center_id(&|| Ok(1))
+            .finalize()?,
+    );
     let num_threads = num_cpus::get().max(2); // Use available cores, at least 2
     let ids_per_thread = 1_000_000 / num_threads;
     let total_ids = num_threads * ids_per_thread;
@@ -671,6 +746,2516 @@ fn bench_multi_thread_throughput() -> Result<(), BoxDynError> {
     Ok(())
 }
 
+// --- Time source tests ---
+
+struct FixedClock(std::sync::atomic::AtomicI64);
+
+impl crate::TimeSource for FixedClock {
+    fn current_millis(&self) -> i64 {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_custom_time_source() -> Result<(), BoxDynError> {
+    let start_time = 1_640_995_200_000;
+    let sf = Snowflake::builder()
+        .time_source(FixedClock(start_time.into()))
+        .start_time(start_time - 1_000)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()?;
+
+    let first = sf.decompose(sf.next_id()?);
+    let second = sf.decompose(sf.next_id()?);
+    assert_eq!(first.time, 1_000);
+    assert_eq!(second.time, 1_000);
+    assert_eq!(second.sequence, first.sequence + 1);
+    Ok(())
+}
+
+#[test]
+fn test_time_source_per_generator() -> Result<(), BoxDynError> {
+    let start_time = 1_640_995_200_000;
+    let early = Snowflake::builder()
+        .time_source(FixedClock((start_time + 10).into()))
+        .start_time(start_time)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()?;
+    let late = Snowflake::builder()
+        .time_source(FixedClock((start_time + 20).into()))
+        .start_time(start_time)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()?;
+
+    assert_eq!(early.decompose(early.next_id()?).time, 10);
+    assert_eq!(late.decompose(late.next_id()?).time, 20);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_start_time_checked_against_time_source() {
+    let start_time = 1_640_995_200_000;
+    assert!(matches!(
+        Snowflake::builder()
+            .time_source(FixedClock(start_time.into()))
+            .start_time(start_time + 1)
+            .machine_id(&|| Ok(1))
+            .data_center_id(&|| Ok(1))
+            .finalize(),
+        Err(Error::StartTimeAheadOfCurrentTime(_))
+    ));
+}
+
+// --- ManualClock tests ---
+
+const TEST_EPOCH: i64 = 1_640_995_200_000;
+
+fn manual_builder<'a>(clock: &crate::time::ManualClock) -> crate::Builder<'a> {
+    Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+}
+
+#[test]
+fn test_manual_clock_controls() {
+    use crate::TimeSource;
+    let clock = crate::time::ManualClock::new(100);
+    assert_eq!(clock.current_millis(), 100);
+    clock.advance(5);
+    assert_eq!(clock.now(), 105);
+    clock.rewind(10);
+    assert_eq!(clock.now(), 95);
+    clock.set(1_000);
+    assert_eq!(clock.current_millis(), 1_000);
+
+    clock.auto_advance(2);
+    assert_eq!(clock.current_millis(), 1_000);
+    assert_eq!(clock.current_millis(), 1_002);
+    clock.freeze();
+    assert_eq!(clock.current_millis(), 1_004);
+    assert_eq!(clock.current_millis(), 1_004);
+}
+
+#[test]
+fn test_manual_clock_sequence_exhaustion() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = manual_builder(&clock)
+        .bit_len_time(51)
+        .bit_len_sequence(2)
+        .finalize()?;
+
+    for expected in 0..4 {
+        let parts = sf.decompose(sf.next_id()?);
+        assert_eq!(parts.time, 10);
+        assert_eq!(parts.sequence, expected);
+    }
+
+    // The sequence is exhausted; the generator waits for a later millisecond.
+    clock.auto_advance(1);
+    let parts = sf.decompose(sf.next_id()?);
+    assert!(parts.time > 10);
+    assert_eq!(parts.sequence, 0);
+    Ok(())
+}
+
+#[test]
+fn test_manual_clock_backward_jump() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = manual_builder(&clock)
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .finalize()?;
+    sf.next_id()?;
+
+    clock.rewind(30);
+    assert!(matches!(
+        sf.next_id(),
+        Err(Error::ClockDrift {
+            last_time: 100,
+            current_time: 70
+        })
+    ));
+
+    clock.advance(31);
+    assert_eq!(sf.decompose(sf.next_id()?).time, 101);
+    Ok(())
+}
+
+#[test]
+fn test_manual_clock_drift_exceeded() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = manual_builder(&clock).max_clock_drift_ms(20).finalize()?;
+    sf.next_id()?;
+
+    clock.rewind(21);
+    assert!(matches!(
+        sf.next_id(),
+        Err(Error::ClockDriftExceeded {
+            drift_ms: 21,
+            max_ms: 20
+        })
+    ));
+
+    // Within the limit, Wait blocks until the clock catches up.
+    clock.advance(1);
+    clock.auto_advance(1);
+    assert!(sf.decompose(sf.next_id()?).time > 100);
+    Ok(())
+}
+
+#[test]
+fn test_manual_clock_last_timestamp() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = manual_builder(&clock)
+        .clock_drift_strategy(ClockDriftStrategy::LastTimestamp)
+        .finalize()?;
+    let first = sf.next_id()?;
+
+    clock.rewind(50);
+    let second = sf.next_id()?;
+    assert!(second > first);
+    assert_eq!(sf.decompose(second).time, 100);
+    Ok(())
+}
+
+#[test]
+fn test_manual_clock_over_time_limit() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH);
+    let sf = manual_builder(&clock)
+        .bit_len_time(10)
+        .bit_len_sequence(43)
+        .finalize()?;
+
+    clock.advance(1023);
+    assert_eq!(sf.decompose(sf.next_id()?).time, 1023);
+    clock.advance(1);
+    assert!(matches!(sf.next_id(), Err(Error::OverTimeLimit)));
+    Ok(())
+}
+
+// --- Time unit tests ---
+
+#[test]
+fn test_time_unit_scales_time_field() -> Result<(), BoxDynError> {
+    use crate::TimeUnit;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_234);
+    let sf = manual_builder(&clock)
+        .time_unit(TimeUnit::CENTISECOND)
+        .finalize()?;
+
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!(parts.time, 123);
+    assert_eq!(parts.time_unit, TimeUnit::CENTISECOND);
+    assert_eq!(parts.elapsed_millis(), 1_230);
+    assert_eq!(parts.nanos_time(), 1_230_000_000);
+
+    // Still within the same 10ms tick: the sequence advances.
+    clock.advance(5);
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!(parts.time, 123);
+    assert_eq!(parts.sequence, 1);
+
+    clock.advance(1);
+    assert_eq!(sf.decompose(sf.next_id()?).time, 124);
+    Ok(())
+}
+
+#[test]
+fn test_time_unit_sequence_exhaustion_waits_for_next_tick() -> Result<(), BoxDynError> {
+    use crate::TimeUnit;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let sf = manual_builder(&clock)
+        .time_unit(TimeUnit::SECOND)
+        .bit_len_time(52)
+        .bit_len_sequence(1)
+        .finalize()?;
+
+    sf.next_id()?;
+    sf.next_id()?;
+    clock.auto_advance(100);
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!(parts.time, 2);
+    assert_eq!(parts.sequence, 0);
+    assert!(clock.now() >= TEST_EPOCH + 2_000);
+    Ok(())
+}
+
+#[test]
+fn test_time_unit_drift_threshold_in_millis() -> Result<(), BoxDynError> {
+    use crate::TimeUnit;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let sf = manual_builder(&clock)
+        .time_unit(TimeUnit::CENTISECOND)
+        .max_clock_drift_ms(20)
+        .finalize()?;
+    sf.next_id()?;
+
+    clock.rewind(25);
+    assert!(matches!(
+        sf.next_id(),
+        Err(Error::ClockDriftExceeded {
+            drift_ms: 30,
+            max_ms: 20
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "time unit must be")]
+fn test_time_unit_zero_panics() {
+    let _ = crate::TimeUnit::from_millis(0);
+}
+
+// --- Timestamp persistence tests ---
+
+#[derive(Default)]
+struct MemoryStore {
+    mark: std::sync::Mutex<Option<i64>>,
+    writes: std::sync::atomic::AtomicUsize,
+}
+
+impl crate::TimestampStore for MemoryStore {
+    fn load(&self) -> Result<Option<i64>, BoxDynError> {
+        Ok(*self.mark.lock().unwrap())
+    }
+
+    fn store(&self, millis: i64) -> Result<(), BoxDynError> {
+        self.writes
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        *self.mark.lock().unwrap() = Some(millis);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_persistence_writes_ahead_in_coarse_steps() -> Result<(), BoxDynError> {
+    let store = Arc::new(MemoryStore::default());
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let sf = manual_builder(&clock)
+        .timestamp_store(Arc::clone(&store))
+        .persist_interval_ms(500)
+        .finalize()?;
+
+    for _ in 0..100 {
+        sf.next_id()?;
+        clock.advance(1);
+    }
+    assert_eq!(*store.mark.lock().unwrap(), Some(TEST_EPOCH + 1_500));
+    assert_eq!(store.writes.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    clock.set(TEST_EPOCH + 1_500);
+    sf.next_id()?;
+    assert_eq!(*store.mark.lock().unwrap(), Some(TEST_EPOCH + 2_000));
+    assert_eq!(store.writes.load(std::sync::atomic::Ordering::Relaxed), 2);
+    Ok(())
+}
+
+#[test]
+fn test_persistence_survives_restart_with_rewound_clock() -> Result<(), BoxDynError> {
+    let store = Arc::new(MemoryStore::default());
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let first = manual_builder(&clock)
+        .timestamp_store(Arc::clone(&store))
+        .finalize()?;
+    let last_issued = first.next_id()?;
+    drop(first);
+
+    // Restart with the clock behind the persisted mark.
+    clock.set(TEST_EPOCH + 500);
+    let second = manual_builder(&clock)
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .timestamp_store(Arc::clone(&store))
+        .finalize()?;
+    assert!(matches!(second.next_id(), Err(Error::ClockDrift { .. })));
+
+    // Once the clock passes the mark, IDs resume above everything issued before.
+    clock.set(TEST_EPOCH + 2_000);
+    let id = second.next_id()?;
+    assert!(id > last_issued);
+    assert_eq!(second.decompose(id).time, 2_000);
+    Ok(())
+}
+
+#[test]
+fn test_persistence_restart_waits_past_mark() -> Result<(), BoxDynError> {
+    let store = Arc::new(MemoryStore::default());
+    store.mark.lock().unwrap().replace(TEST_EPOCH + 2_000);
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_990);
+    clock.auto_advance(1);
+    let sf = manual_builder(&clock)
+        .timestamp_store(Arc::clone(&store))
+        .finalize()?;
+
+    assert!(sf.decompose(sf.next_id()?).time >= 2_000);
+    Ok(())
+}
+
+#[test]
+fn test_persistence_store_failure() -> Result<(), BoxDynError> {
+    struct FailingStore;
+
+    impl crate::TimestampStore for FailingStore {
+        fn load(&self) -> Result<Option<i64>, BoxDynError> {
+            Ok(None)
+        }
+
+        fn store(&self, _millis: i64) -> Result<(), BoxDynError> {
+            Err("disk full".into())
+        }
+    }
+
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let sf = manual_builder(&clock)
+        .timestamp_store(FailingStore)
+        .finalize()?;
+    assert!(matches!(sf.next_id(), Err(Error::TimestampStoreFailed(_))));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_timestamp_store_roundtrip() -> Result<(), BoxDynError> {
+    use crate::{FileTimestampStore, TimestampStore};
+    let path = std::env::temp_dir().join(format!(
+        "snowflake-me-hwm-{}-{}",
+        std::process::id(),
+        crate::time::current_millis()
+    ));
+    let store = FileTimestampStore::new(&path);
+    assert_eq!(store.load()?, None);
+
+    store.store(1_234)?;
+    assert_eq!(store.load()?, Some(1_234));
+    store.store(5_678)?;
+    assert_eq!(FileTimestampStore::new(&path).load()?, Some(5_678));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+// --- Monotonic clock tests ---
+
+#[cfg(feature = "std")]
+#[test]
+fn test_monotonic_clock_ignores_backward_step() {
+    use crate::{MonotonicClock, TimeSource};
+    let wall = crate::time::ManualClock::new(TEST_EPOCH);
+    let clock = MonotonicClock::new(wall.clone());
+
+    wall.rewind(10_000);
+    let first = clock.current_millis();
+    assert!(first >= TEST_EPOCH);
+
+    thread::sleep(Duration::from_millis(5));
+    assert!(clock.current_millis() >= first + 5);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_monotonic_clock_slew() {
+    use crate::{MonotonicClock, TimeSource};
+    let wall = crate::time::ManualClock::new(TEST_EPOCH);
+    let fixed = MonotonicClock::new(wall.clone());
+    let slewed = MonotonicClock::with_slew(wall.clone(), 500_000);
+
+    wall.advance(1_000);
+    thread::sleep(Duration::from_millis(30));
+    let fixed_now = fixed.current_millis();
+    let slewed_now = slewed.current_millis();
+    assert!(
+        slewed_now > fixed_now,
+        "slew mode should move towards the wall clock"
+    );
+    assert!(slewed_now < TEST_EPOCH + 1_000, "slew mode must not step");
+
+    wall.rewind(2_000);
+    thread::sleep(Duration::from_millis(5));
+    assert!(
+        slewed.current_millis() >= slewed_now,
+        "slew mode must not go backwards"
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_clock_mode_monotonic_prevents_drift() -> Result<(), BoxDynError> {
+    use crate::ClockMode;
+    let wall = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = manual_builder(&wall)
+        .clock_mode(ClockMode::Monotonic)
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .finalize()?;
+    let first = sf.next_id()?;
+
+    wall.rewind(50);
+    let second = sf.next_id()?;
+    assert!(second > first);
+    assert!(sf.decompose(second).time >= 100);
+    Ok(())
+}
+
+// --- Wait policy tests ---
+
+#[test]
+fn test_wait_policy_custom_drives_exhaustion_wait() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let calls = Arc::new(core::sync::atomic::AtomicU32::new(0));
+    let policy = {
+        let clock = clock.clone();
+        let calls = calls.clone();
+        crate::WaitPolicy::custom(move |attempt| {
+            assert_eq!(
+                attempt,
+                calls.fetch_add(1, core::sync::atomic::Ordering::SeqCst)
+            );
+            clock.advance(1);
+        })
+    };
+    let sf = manual_builder(&clock)
+        .bit_len_time(51)
+        .bit_len_sequence(2)
+        .wait_policy(policy)
+        .finalize()?;
+
+    for _ in 0..4 {
+        sf.next_id()?;
+    }
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!(parts.time, 11);
+    assert_eq!(parts.sequence, 0);
+    assert_eq!(calls.load(core::sync::atomic::Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test]
+fn test_wait_policy_deadline_on_drift() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let policy = {
+        let clock = clock.clone();
+        crate::WaitPolicy::custom(move |_| clock.advance(1))
+    };
+    let sf = manual_builder(&clock)
+        .wait_policy(policy)
+        .max_wait_ms(10)
+        .finalize()?;
+    sf.next_id()?;
+
+    clock.rewind(50);
+    assert!(matches!(
+        sf.next_id(),
+        Err(Error::WaitTimeout { max_ms: 10 })
+    ));
+    assert_eq!(clock.now(), TEST_EPOCH + 60);
+
+    // A shorter backward jump is waited out within the deadline.
+    clock.set(TEST_EPOCH + 95);
+    assert_eq!(sf.decompose(sf.next_id()?).time, 101);
+    Ok(())
+}
+
+#[test]
+fn test_wait_policy_deadline_on_exhaustion() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = manual_builder(&clock)
+        .bit_len_time(51)
+        .bit_len_sequence(2)
+        .max_wait_ms(0)
+        .finalize()?;
+    let block = sf.reserve(4)?;
+    assert_eq!(block.len(), 4);
+    assert!(matches!(
+        sf.next_id(),
+        Err(Error::WaitTimeout { max_ms: 0 })
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_wait_policy_yield_and_sleep() -> Result<(), BoxDynError> {
+    for policy in [
+        crate::WaitPolicy::SpinYield { spins: 16 },
+        crate::WaitPolicy::SpinSleep {
+            spins: 16,
+            sleep: Duration::from_micros(100),
+        },
+    ] {
+        let sf = Snowflake::builder()
+            .bit_len_time(51)
+            .bit_len_sequence(2)
+            .machine_id(&|| Ok(1))
+            .data_center_id(&|| Ok(1))
+            .wait_policy(policy)
+            .finalize()?;
+        let ids = (0..50)
+            .map(|_| sf.next_id())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+    Ok(())
+}
+
+// --- Non-blocking generation tests ---
+
+#[test]
+fn test_try_next_id_sequence_exhausted() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = manual_builder(&clock)
+        .bit_len_time(51)
+        .bit_len_sequence(2)
+        .finalize()?;
+
+    for expected in 0..4 {
+        assert_eq!(sf.decompose(sf.try_next_id()?).sequence, expected);
+    }
+    assert!(matches!(
+        sf.try_next_id(),
+        Err(Error::WouldBlock { wait_ms: 1 })
+    ));
+
+    clock.advance(1);
+    let parts = sf.decompose(sf.try_next_id()?);
+    assert_eq!(parts.time, 11);
+    assert_eq!(parts.sequence, 0);
+    Ok(())
+}
+
+#[test]
+fn test_try_next_id_clock_behind() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = manual_builder(&clock)
+        .time_unit(crate::TimeUnit::CENTISECOND)
+        .finalize()?;
+    let first = sf.try_next_id()?;
+
+    // Back to 60ms: tick 10 only ends at 110ms.
+    clock.rewind(40);
+    assert!(matches!(
+        sf.try_next_id(),
+        Err(Error::WouldBlock { wait_ms: 50 })
+    ));
+
+    // Generation resumes once the clock reaches the next tick.
+    clock.set(TEST_EPOCH + 110);
+    let second = sf.try_next_id()?;
+    assert!(second > first);
+    assert_eq!(sf.decompose(second).time, 11);
+    Ok(())
+}
+
+#[test]
+fn test_try_next_id_keeps_drift_errors() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = manual_builder(&clock)
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .finalize()?;
+    sf.try_next_id()?;
+
+    clock.rewind(10);
+    assert!(matches!(sf.try_next_id(), Err(Error::ClockDrift { .. })));
+    Ok(())
+}
+
+// --- Layout tests ---
+
+#[test]
+fn test_layout_shifts_and_maxima() {
+    const LAYOUT: crate::Layout = crate::Layout::new(39, 8, 0, 16);
+    assert_eq!(LAYOUT.sequence_shift(), 0);
+    assert_eq!(LAYOUT.machine_id_shift(), 8);
+    assert_eq!(LAYOUT.data_center_id_shift(), 24);
+    assert_eq!(LAYOUT.time_shift(), 24);
+    assert_eq!(LAYOUT.max_time(), (1 << 39) - 1);
+    assert_eq!(LAYOUT.max_sequence(), 255);
+    assert_eq!(LAYOUT.max_data_center_id(), 0);
+    assert_eq!(LAYOUT.max_machine_id(), u64::from(u16::MAX));
+    assert_eq!(crate::Layout::default(), crate::Layout::new(41, 12, 5, 5));
+}
+
+#[test]
+fn test_layout_validation() {
+    assert!(crate::Layout::try_new(41, 12, 5, 5).is_ok());
+    assert!(matches!(
+        crate::Layout::try_new(41, 12, 5, 6),
+        Err(Error::InvalidBitLength(41, 12, 5, 6))
+    ));
+    assert!(matches!(
+        crate::Layout::try_new(30, 0, 16, 17),
+        Err(Error::InvalidBitLength(30, 0, 16, 17))
+    ));
+    assert!(matches!(
+        crate::Layout::try_new(255, 255, 0, 0),
+        Err(Error::InvalidBitLength(..))
+    ));
+}
+
+#[test]
+#[should_panic(expected = "invalid layout")]
+fn test_layout_new_panics_when_invalid() {
+    let _ = crate::Layout::new(41, 12, 5, 6);
+}
+
+#[test]
+fn test_layout_compose_checks_ranges() {
+    let layout = crate::Layout::DEFAULT;
+    let id = layout.compose(1, 2, 3, 4).unwrap();
+    assert_eq!(id.as_u64(), (1 << 22) | (3 << 17) | (4 << 12) | 2);
+    assert!(layout.compose(0, 4096, 0, 0).is_none());
+    assert!(layout.compose(0, 0, 32, 0).is_none());
+    assert!(layout.compose(0, 0, 0, 32).is_none());
+    assert!(layout.compose(1 << 41, 0, 0, 0).is_none());
+}
+
+#[test]
+fn test_layout_shared_between_builder_and_decompose() -> Result<(), BoxDynError> {
+    const LAYOUT: crate::Layout = crate::Layout::new(43, 10, 2, 8);
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1234);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(LAYOUT)
+        .machine_id(&|| Ok(200))
+        .data_center_id(&|| Ok(3))
+        .finalize()?;
+
+    let id = sf.next_id()?;
+    let parts = LAYOUT.decompose(id);
+    assert_eq!(parts.time, 1234);
+    assert_eq!(parts.machine_id, 200);
+    assert_eq!(parts.data_center_id, 3);
+    assert_eq!(parts.sequence, 0);
+    assert_eq!(
+        DecomposedSnowflake::decompose(id.as_u64(), 43, 10, 2, 8).machine_id,
+        200
+    );
+
+    assert!(matches!(
+        Snowflake::builder()
+            .time_source(clock)
+            .start_time(TEST_EPOCH)
+            .layout(LAYOUT)
+            .machine_id(&|| Ok(256))
+            .data_center_id(&|| Ok(0))
+            .finalize(),
+        Err(Error::MachineIdFailed(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_builder_accepts_16_bit_machine_id() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 5);
+    let sf = Snowflake::builder()
+        .time_source(clock)
+        .start_time(TEST_EPOCH)
+        .layout(crate::Layout::new(39, 8, 0, 16))
+        .machine_id(&|| Ok(u16::MAX))
+        .data_center_id(&|| Ok(0))
+        .finalize()?;
+    assert_eq!(sf.decompose(sf.next_id()?).machine_id, u64::from(u16::MAX));
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_layout_serde_validates() {
+    let layout = crate::Layout::new(41, 10, 4, 8);
+    let json = serde_json::to_string(&layout).unwrap();
+    assert_eq!(
+        json,
+        r#"{"bit_len_time":41,"bit_len_sequence":10,"bit_len_data_center_id":4,"bit_len_machine_id":8}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<crate::Layout>(&json).unwrap(),
+        layout
+    );
+    assert!(
+        serde_json::from_str::<crate::Layout>(
+            r#"{"bit_len_time":41,"bit_len_sequence":10,"bit_len_data_center_id":4,"bit_len_machine_id":9}"#
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_layout_field_order() -> Result<(), BoxDynError> {
+    use crate::Field;
+    const LAYOUT: crate::Layout = crate::Layout::new(41, 12, 5, 5).with_order(&[
+        Field::Time,
+        Field::Sequence,
+        Field::MachineId,
+        Field::DataCenterId,
+    ]);
+    assert_eq!(LAYOUT.time_shift(), 22);
+    assert_eq!(LAYOUT.sequence_shift(), 10);
+    assert_eq!(LAYOUT.machine_id_shift(), 5);
+    assert_eq!(LAYOUT.data_center_id_shift(), 0);
+
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 7);
+    let builder = |machine_id: &'static dyn Fn() -> Result<u16, BoxDynError>| {
+        Snowflake::builder()
+            .time_source(clock.clone())
+            .start_time(TEST_EPOCH)
+            .layout(LAYOUT)
+            .machine_id(machine_id)
+            .data_center_id(&|| Ok(2))
+    };
+    let a = builder(&|| Ok(1)).finalize()?;
+    let b = builder(&|| Ok(30)).finalize()?;
+
+    // With the sequence above the worker bits, IDs of one tick interleave across workers.
+    let a0 = a.next_id()?;
+    let b0 = b.next_id()?;
+    let a1 = a.next_id()?;
+    assert!(a0 < b0 && b0 < a1);
+    assert_eq!(a1.as_u64(), (7 << 22) | (1 << 10) | (1 << 5) | 2);
+
+    let parts = a.decompose(a1);
+    assert_eq!(
+        (
+            parts.time,
+            parts.sequence,
+            parts.machine_id,
+            parts.data_center_id
+        ),
+        (7, 1, 1, 2)
+    );
+    assert_eq!(
+        DecomposedSnowflake::decompose_with_layout(b0.as_u64(), &LAYOUT).machine_id,
+        30
+    );
+
+    // The bit-length setters keep the layout's field order.
+    let c = builder(&|| Ok(1))
+        .bit_len_time(40)
+        .bit_len_sequence(13)
+        .finalize()?;
+    assert!(c.0.layout.order().eq(LAYOUT.order()));
+    assert_eq!(c.0.layout.sequence_shift(), 10);
+    Ok(())
+}
+
+#[test]
+fn test_layout_time_below_other_fields() {
+    use crate::Field;
+    let layout = crate::Layout::new(41, 12, 5, 5).with_order(&[
+        Field::DataCenterId,
+        Field::MachineId,
+        Field::Time,
+        Field::Sequence,
+    ]);
+    let id = layout.compose(5, 6, 7, 8).unwrap();
+    assert_eq!(id.as_u64(), (7 << 58) | (8 << 53) | (5 << 12) | 6);
+    let parts = layout.decompose(id);
+    assert_eq!(
+        (
+            parts.time,
+            parts.sequence,
+            parts.data_center_id,
+            parts.machine_id
+        ),
+        (5, 6, 7, 8)
+    );
+}
+
+#[test]
+fn test_layout_invalid_field_order() {
+    use crate::Field;
+    let order = [
+        Field::Time,
+        Field::Sequence,
+        Field::Sequence,
+        Field::MachineId,
+    ];
+    assert!(matches!(
+        crate::Layout::DEFAULT.try_with_order(&order),
+        Err(Error::InvalidFieldOrder)
+    ));
+    assert!(matches!(
+        crate::Layout::DEFAULT.try_with_order(&order[..3]),
+        Err(Error::InvalidFieldOrder)
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_layout_serde_field_order() {
+    let layout = crate::Preset::SONYFLAKE.layout();
+    let json = serde_json::to_string(&layout).unwrap();
+    assert_eq!(
+        json,
+        r#"{"bit_len_time":39,"bit_len_sequence":8,"bit_len_data_center_id":0,"bit_len_machine_id":16,"order":["time","sequence","machine_id","data_center_id"]}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<crate::Layout>(&json).unwrap(),
+        layout
+    );
+    assert!(
+        serde_json::from_str::<crate::Layout>(
+            r#"{"bit_len_time":39,"bit_len_sequence":8,"bit_len_data_center_id":0,"bit_len_machine_id":16,"order":["time","time","machine_id","data_center_id"]}"#
+        )
+        .is_err()
+    );
+}
+
+// --- Preset tests ---
+
+#[test]
+fn test_preset_twitter_golden_vector() {
+    use crate::Preset;
+    // Tweet 1050118621198921728 from the Twitter API docs, created 2018-10-10T20:19:24Z.
+    let id = SnowflakeId::new(1_050_118_621_198_921_728);
+    let parts = Preset::TWITTER.decompose(id);
+    assert_eq!(Preset::TWITTER.timestamp_millis(id), 1_539_202_764_211);
+    assert_eq!(parts.data_center_id, 10);
+    assert_eq!(parts.machine_id, 27);
+    assert_eq!(parts.sequence, 0);
+}
+
+#[test]
+fn test_preset_discord_golden_vector() {
+    use crate::Preset;
+    // Example from the Discord API reference: 2016-04-30T11:18:25.796Z,
+    // internal worker 1, internal process 0, increment 7.
+    let id = SnowflakeId::new(175_928_847_299_117_063);
+    let parts = Preset::DISCORD.decompose(id);
+    assert_eq!(Preset::DISCORD.timestamp_millis(id), 1_462_015_105_796);
+    assert_eq!(parts.data_center_id, 1);
+    assert_eq!(parts.machine_id, 0);
+    assert_eq!(parts.sequence, 7);
+}
+
+#[test]
+fn test_preset_instagram_golden_vector() {
+    use crate::Preset;
+    // Media ID of instagram.com/p/BsOGulcndj-/ (the "world record egg"), posted
+    // 2019-01-04. Its 41-bit timestamp still fits in the preset's 40 bits.
+    let id = SnowflakeId::new(1_949_525_278_281_554_174);
+    let parts = Preset::INSTAGRAM.decompose(id);
+    assert_eq!(Preset::INSTAGRAM.timestamp_millis(id), 1_546_621_545_106);
+    assert_eq!(parts.machine_id, 1910);
+    assert_eq!(parts.sequence, 254);
+    assert_eq!(parts.data_center_id, 0);
+}
+
+#[test]
+fn test_preset_sonyflake_golden_vector() {
+    use crate::Preset;
+    // Sonyflake publishes no example IDs. This one is worked out by hand from its
+    // README: 2024-01-01T00:00:00Z is 29453760000 10ms ticks after the default
+    // epoch, sequence 5, and machine ID 0x0304, the lower 16 bits of 10.2.3.4.
+    let id = SnowflakeId::new(494_152_093_532_488_452);
+    let parts = Preset::SONYFLAKE.decompose(id);
+    assert_eq!(Preset::SONYFLAKE.timestamp_millis(id), 1_704_067_200_000);
+    assert_eq!(parts.sequence, 5);
+    assert_eq!(parts.machine_id, 0x0304);
+    assert_eq!(parts.data_center_id, 0);
+}
+
+#[test]
+fn test_preset_baidu_uid_golden_vector() {
+    use crate::{Layout, Preset};
+    // UidGenerator's README example: {"UID":"180363646902239241","parsed":{
+    // "timestamp":"2017-01-19 12:15:46","workerId":"4","sequence":"9"}}. It was
+    // generated with the README's configuration, timeBits 29, workerBits 21,
+    // seqBits 13 and epochStr 2016-09-20, rather than the defaults of BAIDU_UID.
+    let readme = Preset::new(
+        Layout::new(29, 13, 5, 16),
+        1_474_300_800_000,
+        Preset::BAIDU_UID.time_unit(),
+    );
+    assert!(
+        readme
+            .layout()
+            .order()
+            .eq(Preset::BAIDU_UID.layout().order())
+    );
+    let id = SnowflakeId::new(180_363_646_902_239_241);
+    let parts = readme.decompose(id);
+    // 2017-01-19T12:15:46+08:00.
+    assert_eq!(readme.timestamp_millis(id), 1_484_799_346_000);
+    assert_eq!((parts.data_center_id << 16) | parts.machine_id, 4);
+    assert_eq!(parts.sequence, 9);
+}
+
+#[test]
+fn test_builder_preset_generates_compatible_ids() -> Result<(), BoxDynError> {
+    use crate::Preset;
+    let epoch = Preset::SONYFLAKE.start_time();
+    let clock = crate::time::ManualClock::new(epoch + 1_234_567);
+    let sf = Snowflake::builder()
+        .time_source(clock)
+        .preset(Preset::SONYFLAKE)
+        .machine_id(&|| Ok(0xbeef))
+        .data_center_id(&|| Ok(0))
+        .finalize()?;
+
+    let block = sf.reserve(3)?;
+    for (sequence, id) in block.iter().enumerate() {
+        assert_eq!(id.as_u64() >> 24, 123_456);
+        assert_eq!((id.as_u64() >> 16) & 0xff, sequence as u64);
+        assert_eq!(id.as_u64() & 0xffff, 0xbeef);
+        let parts = Preset::SONYFLAKE.decompose(id);
+        assert_eq!(parts.machine_id, 0xbeef);
+        assert_eq!(parts.sequence, sequence as u64);
+        assert_eq!(sf.decompose(id).sequence, sequence as u64);
+    }
+    assert_eq!(
+        Preset::SONYFLAKE.timestamp_millis(block.first().unwrap()),
+        epoch + 1_234_560
+    );
+    Ok(())
+}
+
+// --- Custom field tests ---
+
+/// 41 bits of time, a 4-bit entity type, a 6-bit shard, 4 bits of machine ID and 8 of sequence.
+const FIELD_LAYOUT: crate::Layout =
+    crate::Layout::with_fields(41, 8, 0, 4, &[("entity_type", 4), ("shard", 6)]);
+
+#[test]
+fn test_layout_custom_fields() {
+    assert_eq!(FIELD_LAYOUT.time_shift(), 22);
+    assert_eq!(FIELD_LAYOUT.field_shift("entity_type"), Some(18));
+    assert_eq!(FIELD_LAYOUT.field_shift("shard"), Some(12));
+    assert_eq!(FIELD_LAYOUT.machine_id_shift(), 8);
+    assert_eq!(FIELD_LAYOUT.sequence_shift(), 0);
+    assert_eq!(FIELD_LAYOUT.max_field("entity_type"), Some(15));
+    assert_eq!(FIELD_LAYOUT.max_field("region"), None);
+    assert!(FIELD_LAYOUT.fields().eq([("entity_type", 4), ("shard", 6)]));
+
+    let id = SnowflakeId::new((9 << 22) | (5 << 18) | (17 << 12) | (3 << 8) | 2);
+    assert_eq!(FIELD_LAYOUT.field_of(id, "shard"), Some(17));
+    let parts = FIELD_LAYOUT.decompose(id);
+    assert_eq!((parts.time, parts.machine_id, parts.sequence), (9, 3, 2));
+    assert_eq!(parts.field("entity_type"), Some(5));
+    assert_eq!(parts.field("shard"), Some(17));
+    assert_eq!(parts.field("region"), None);
+    assert!(parts.to_string().ends_with(", entity_type=5, shard=17"));
+}
+
+#[test]
+fn test_layout_custom_field_order() {
+    use crate::Field;
+    let layout = FIELD_LAYOUT.with_order(&[
+        Field::Time,
+        Field::MachineId,
+        Field::Sequence,
+        Field::custom("shard"),
+        Field::DataCenterId,
+        Field::custom("entity_type"),
+    ]);
+    assert_eq!(layout.field_shift("entity_type"), Some(0));
+    assert_eq!(layout.field_shift("shard"), Some(4));
+    assert_eq!(layout.sequence_shift(), 10);
+    assert_eq!(layout.machine_id_shift(), 18);
+
+    // Every custom field must be listed, and only fields of the layout.
+    let builtins = [
+        Field::Time,
+        Field::DataCenterId,
+        Field::MachineId,
+        Field::Sequence,
+    ];
+    assert!(matches!(
+        FIELD_LAYOUT.try_with_order(&builtins),
+        Err(Error::InvalidFieldOrder)
+    ));
+    assert!(matches!(
+        crate::Layout::DEFAULT.try_with_order(&[
+            Field::Time,
+            Field::DataCenterId,
+            Field::MachineId,
+            Field::custom("shard"),
+        ]),
+        Err(Error::InvalidFieldOrder)
+    ));
+}
+
+#[test]
+fn test_layout_invalid_custom_fields() {
+    use crate::Layout;
+    assert!(matches!(
+        Layout::try_with_fields(41, 8, 0, 4, &[("shard", 11)]),
+        Err(Error::InvalidBitLength(41, 8, 0, 4))
+    ));
+    assert!(matches!(
+        Layout::try_with_fields(41, 8, 0, 4, &[("shard", 6), ("shard", 4)]),
+        Err(Error::InvalidField(_))
+    ));
+    assert!(matches!(
+        Layout::try_with_fields(41, 12, 0, 10, &[("", 0)]),
+        Err(Error::InvalidField(_))
+    ));
+    assert!(matches!(
+        Layout::try_with_fields(41, 12, 5, 5, &[("empty", 0)]),
+        Err(Error::InvalidField(_))
+    ));
+    assert!(matches!(
+        Layout::try_with_fields(41, 11, 5, 5, &[("a_name_longer_than_23_bytes", 1)]),
+        Err(Error::InvalidField(_))
+    ));
+}
+
+#[test]
+fn test_next_id_with_fields() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 9);
+    let sf = Snowflake::builder()
+        .time_source(clock)
+        .start_time(TEST_EPOCH)
+        .layout(FIELD_LAYOUT)
+        .machine_id(&|| Ok(3))
+        .data_center_id(&|| Ok(0))
+        .field("entity_type", 5)
+        .finalize()?;
+
+    let id = sf.next_id_with(&[("shard", 17)])?;
+    assert_eq!(id.as_u64(), (9 << 22) | (5 << 18) | (17 << 12) | (3 << 8));
+    let parts = sf.decompose(id);
+    assert_eq!(parts.field("entity_type"), Some(5));
+    assert_eq!(parts.field("shard"), Some(17));
+
+    // Per-call values override the builder's.
+    let id = sf.next_id_with(&[("shard", 63), ("entity_type", 1)])?;
+    let parts = sf.decompose(id);
+    assert_eq!(
+        (parts.field("entity_type"), parts.field("shard")),
+        (Some(1), Some(63))
+    );
+    assert_eq!(parts.sequence, 1);
+
+    // The shard has no builder value, so it is required on every call.
+    assert!(matches!(sf.next_id(), Err(Error::MissingField(name)) if name == "shard"));
+    assert!(matches!(sf.reserve(2), Err(Error::MissingField(_))));
+    assert!(matches!(
+        sf.next_id_with(&[("entity_type", 1)]),
+        Err(Error::MissingField(name)) if name == "shard"
+    ));
+    assert!(matches!(
+        sf.next_id_with(&[("shard", 64)]),
+        Err(Error::FieldOverflow {
+            value: 64,
+            max: 63,
+            ..
+        })
+    ));
+    assert!(matches!(
+        sf.next_id_with(&[("shard", 1), ("region", 1)]),
+        Err(Error::UnknownField(name)) if name == "region"
+    ));
+    // Rejected calls do not consume the sequence.
+    assert_eq!(sf.decompose(sf.next_id_with(&[("shard", 0)])?).sequence, 2);
+    Ok(())
+}
+
+#[test]
+fn test_builder_field_values() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 9);
+    let builder = || {
+        Snowflake::builder()
+            .time_source(clock.clone())
+            .start_time(TEST_EPOCH)
+            .layout(FIELD_LAYOUT)
+            .machine_id(&|| Ok(3))
+            .data_center_id(&|| Ok(0))
+    };
+
+    let sf = builder()
+        .field("entity_type", 15)
+        .field("shard", 42)
+        .finalize()?;
+    let ids = sf.next_ids(3)?;
+    for id in &ids {
+        assert_eq!(FIELD_LAYOUT.field_of(*id, "entity_type"), Some(15));
+        assert_eq!(FIELD_LAYOUT.field_of(*id, "shard"), Some(42));
+    }
+
+    assert!(matches!(
+        builder().field("entity_type", 16).finalize(),
+        Err(Error::FieldOverflow {
+            value: 16,
+            max: 15,
+            ..
+        })
+    ));
+    assert!(matches!(
+        builder().field("region", 1).finalize(),
+        Err(Error::UnknownField(_))
+    ));
+    assert!(matches!(
+        Snowflake::builder()
+            .time_source(clock.clone())
+            .start_time(TEST_EPOCH)
+            .machine_id(&|| Ok(3))
+            .data_center_id(&|| Ok(0))
+            .field("shard", 1)
+            .finalize(),
+        Err(Error::UnknownField(_))
+    ));
+
+    // The bit-length setters keep the layout's custom fields.
+    assert!(matches!(
+        builder().bit_len_time(42).finalize(),
+        Err(Error::InvalidBitLength(42, 8, 0, 4))
+    ));
+    let sf = builder()
+        .bit_len_time(40)
+        .bit_len_sequence(9)
+        .field("entity_type", 1)
+        .field("shard", 2)
+        .finalize()?;
+    assert_eq!(sf.0.layout.field_shift("shard"), Some(13));
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_layout_serde_custom_fields() {
+    let json = serde_json::to_string(&FIELD_LAYOUT).unwrap();
+    assert_eq!(
+        json,
+        r#"{"bit_len_time":41,"bit_len_sequence":8,"bit_len_data_center_id":0,"bit_len_machine_id":4,"fields":[["entity_type",4],["shard",6]]}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<crate::Layout>(&json).unwrap(),
+        FIELD_LAYOUT
+    );
+
+    let parts = FIELD_LAYOUT.decompose(SnowflakeId::new(17 << 12));
+    let json = serde_json::to_string(&parts).unwrap();
+    assert!(json.contains(r#""fields":[["entity_type",0],["shard",17]]"#));
+    let back: DecomposedSnowflake = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.field("shard"), Some(17));
+}
+
+// --- Layout width tests ---
+
+#[test]
+fn test_layout_narrow_width() {
+    use crate::Layout;
+    assert_eq!(Layout::DEFAULT.width(), 63);
+    assert_eq!(Layout::DEFAULT.byte_len(), 8);
+    assert_eq!(FIELD_LAYOUT.width(), 63);
+
+    let layout = Layout::try_new(41, 6, 0, 6).unwrap();
+    assert_eq!(layout.width(), 53);
+    assert_eq!(layout.byte_len(), 7);
+    assert_eq!(layout.time_shift(), 12);
+    assert_eq!(layout.max_id(), (1 << 53) - 1);
+    assert!(layout.contains(SnowflakeId::new(layout.max_id())));
+    assert!(!layout.contains(SnowflakeId::new(1 << 53)));
+
+    // Bits above the width are not part of any section.
+    let parts = layout.decompose(SnowflakeId::new((1 << 60) | (5 << 12) | (2 << 6) | 1));
+    assert_eq!((parts.time, parts.machine_id, parts.sequence), (5, 2, 1));
+    assert_eq!(parts.width, 53);
+
+    assert!(matches!(
+        Layout::try_new(41, 12, 5, 6),
+        Err(Error::InvalidBitLength(41, 12, 5, 6))
+    ));
+}
+
+#[test]
+fn test_layout_packed_bytes() {
+    use crate::Layout;
+    let layout = Layout::new(20, 6, 0, 6);
+    assert_eq!(layout.width(), 32);
+    let id = SnowflakeId::new(0xdead_beef);
+    let bytes = layout.packed_bytes(id);
+    assert_eq!(bytes, [0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(layout.from_packed_bytes(&bytes).unwrap(), id);
+    assert!(matches!(
+        layout.from_packed_bytes(&id.int_bytes()),
+        Err(Error::ParseIdFailed(_))
+    ));
+
+    let layout = Layout::new(41, 6, 0, 6);
+    assert_eq!(layout.packed_bytes(id).len(), 7);
+    assert!(matches!(
+        layout.from_packed_bytes(&[0x20, 0, 0, 0, 0, 0, 0]),
+        Err(Error::ParseIdFailed(_))
+    ));
+
+    // Decomposed IDs encode with the layout's width.
+    let parts = layout.decompose(id);
+    assert_eq!(parts.packed_bytes(), layout.packed_bytes(id));
+    assert_eq!(parts.base64(), "AAAA3q2+7w==");
+    let parts = Layout::DEFAULT.decompose(id);
+    assert_eq!(parts.packed_bytes(), id.int_bytes());
+    assert_eq!(parts.base64(), id.base64());
+}
+
+#[test]
+fn test_narrow_generator() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 6, 0, 6))
+        .machine_id(&|| Ok(63))
+        .data_center_id(&|| Ok(0))
+        .finalize()?;
+    for id in sf.next_ids(64)? {
+        assert!(id.as_u64() < 1 << 53);
+        assert_eq!(sf.decompose(id).machine_id, 63);
+    }
+
+    // 32-bit IDs from 1-second ticks, set through the bit-length setters.
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .time_unit(crate::TimeUnit::SECOND)
+        .bit_len_time(24)
+        .bit_len_sequence(4)
+        .bit_len_data_center_id(0)
+        .bit_len_machine_id(4)
+        .machine_id(&|| Ok(9))
+        .data_center_id(&|| Ok(0))
+        .finalize()?;
+    let id = sf.next_id()?;
+    assert_eq!(u32::try_from(id.as_u64())?, (1 << 8) | (9 << 4));
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_decomposed_width_serde() {
+    let parts = crate::Layout::new(41, 6, 0, 6).decompose(SnowflakeId::new(1));
+    let json = serde_json::to_string(&parts).unwrap();
+    assert!(json.ends_with(r#""width":53}"#));
+    let json = json.replace(r#","width":53"#, "");
+    let back: DecomposedSnowflake = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.width, 63);
+}
+
+// --- 128-bit generator tests ---
+
+#[test]
+fn test_snowflake_id128_encodings() {
+    use crate::SnowflakeId128;
+    let id = SnowflakeId128::new(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210);
+    assert_eq!(id.hex(), "123456789abcdeffedcba9876543210");
+    assert_eq!(id.uuid(), "01234567-89ab-cdef-fedc-ba9876543210");
+    assert_eq!(id.to_uuid_bytes()[..4], [0x01, 0x23, 0x45, 0x67]);
+    assert_eq!(SnowflakeId128::from_uuid_bytes(id.to_uuid_bytes()), id);
+    assert_eq!(id.int_bytes(), id.as_u128().to_be_bytes());
+
+    assert_eq!(id.string().parse::<SnowflakeId128>().unwrap(), id);
+    assert_eq!(
+        format!("0x{}", id.hex()).parse::<SnowflakeId128>().unwrap(),
+        id
+    );
+    assert_eq!(id.uuid().parse::<SnowflakeId128>().unwrap(), id);
+    assert!("0123-4567".parse::<SnowflakeId128>().is_err());
+    assert!("not an id".parse::<SnowflakeId128>().is_err());
+
+    // The 64-bit encodings are unchanged by the shared radix encoder.
+    let small = SnowflakeId::new(1_234_567_890);
+    assert_eq!(small.base58(), "2T6u2h");
+    assert_eq!(SnowflakeId128::new(1_234_567_890).base58(), small.base58());
+    assert_eq!(SnowflakeId128::new(0).base36(), "0");
+}
+
+#[test]
+fn test_id128_encodings_match_64_bit() {
+    use crate::SnowflakeId128;
+    for raw in [0, 1, 31, 32, 35, 36, 57, 58, 1_234_567_890, u64::MAX] {
+        let id = SnowflakeId::new(raw);
+        let id128 = SnowflakeId128::new(u128::from(raw));
+        assert_eq!(id128.base32(), id.base32(), "{raw}");
+        assert_eq!(id128.base36(), id.base36(), "{raw}");
+        assert_eq!(id128.base58(), id.base58(), "{raw}");
+    }
+    let max = SnowflakeId::new(u64::MAX);
+    assert_eq!(max.base32(), "x999999999999");
+    assert_eq!(max.base36(), "3w5e11264sgsf");
+    assert_eq!(max.base58(), "JPwcyDCgEup");
+}
+
+#[test]
+fn test_layout128() {
+    use crate::Layout128;
+    let layout = Layout128::DEFAULT;
+    assert_eq!(layout.width(), 128);
+    assert_eq!(
+        (
+            layout.time_shift(),
+            layout.node_shift(),
+            layout.sequence_shift()
+        ),
+        (80, 64, 49)
+    );
+
+    let layout = Layout128::new(48, 14, 16, 50);
+    let id = layout.compose(1_000, 7, 3, 0xabc).unwrap();
+    let parts = layout.decompose(id);
+    assert_eq!(
+        (parts.time, parts.sequence, parts.node, parts.random),
+        (1_000, 7, 3, 0xabc)
+    );
+    assert!(layout.compose(0, 0, 1 << 16, 0).is_none());
+    assert!(layout.compose(0, 0, 0, 1 << 50).is_none());
+
+    assert!(matches!(
+        Layout128::try_new(48, 16, 16, 48),
+        Err(Error::InvalidLayout128(48, 16, 16, 48))
+    ));
+    assert!(Layout128::try_new(40, 10, 17, 0).is_err());
+    assert!(Layout128::try_new(0, 10, 16, 0).is_err());
+    assert!(Layout128::try_new(48, 15, 16, 50).is_err());
+}
+
+#[test]
+fn test_snowflake128_next_id() -> Result<(), BoxDynError> {
+    use crate::{EntropySource, Layout128, Snowflake128};
+    struct Fixed;
+    impl EntropySource for Fixed {
+        fn next_u64(&self) -> u64 {
+            u64::MAX
+        }
+    }
+
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 42);
+    let sf = Snowflake128::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .node_id(&|| Ok(513))
+        .finalize()?;
+    let ids = sf.next_ids(100)?;
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    for (i, id) in ids.iter().enumerate() {
+        let parts = sf.decompose(*id);
+        assert_eq!(
+            (parts.time, parts.node, parts.sequence),
+            (42, 513, i as u64)
+        );
+        assert_eq!(parts.elapsed_millis(), 42);
+    }
+    assert_ne!(
+        sf.decompose(ids[0]).random,
+        sf.decompose(ids[1]).random,
+        "suffixes should differ"
+    );
+
+    // A suffix wider than 64 bits draws twice; a narrow one is masked.
+    let sf = Snowflake128::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout128::new(40, 8, 8, 72))
+        .node_id(&|| Ok(1))
+        .entropy_source(Fixed)
+        .finalize()?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!(parts.random, (1 << 72) - 1);
+    let sf = Snowflake128::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout128::new(40, 8, 8, 12))
+        .node_id(&|| Ok(1))
+        .entropy_source(Arc::new(Fixed))
+        .finalize()?;
+    let id = sf.next_id()?;
+    assert_eq!(sf.decompose(id).random, 0xfff);
+    assert_eq!(id.as_u128() >> 68, 0);
+
+    assert!(matches!(
+        Snowflake128::builder()
+            .time_source(clock.clone())
+            .start_time(TEST_EPOCH)
+            .finalize(),
+        Err(Error::MachineIdFailed(_))
+    ));
+    assert!(matches!(
+        Snowflake128::builder()
+            .time_source(clock)
+            .start_time(TEST_EPOCH)
+            .layout(Layout128::new(40, 8, 8, 12))
+            .node_id(&|| Ok(256))
+            .finalize(),
+        Err(Error::MachineIdFailed(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_snowflake128_clock_drift() -> Result<(), BoxDynError> {
+    use crate::Snowflake128;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = Snowflake128::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .node_id(&|| Ok(1))
+        .finalize()?;
+    sf.next_id()?;
+    clock.rewind(10);
+    assert!(matches!(sf.next_id(), Err(Error::ClockDrift { .. })));
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snowflake128_serde() {
+    use crate::{Layout128, SnowflakeId128};
+    let id = SnowflakeId128::new(u128::MAX - 1);
+    let json = serde_json::to_string(&id).unwrap();
+    assert_eq!(json, (u128::MAX - 1).to_string());
+    assert_eq!(serde_json::from_str::<SnowflakeId128>(&json).unwrap(), id);
+
+    let layout = Layout128::new(48, 14, 16, 50);
+    let json = serde_json::to_string(&layout).unwrap();
+    assert_eq!(serde_json::from_str::<Layout128>(&json).unwrap(), layout);
+    let invalid = json.replace("50", "51");
+    assert!(serde_json::from_str::<Layout128>(&invalid).is_err());
+
+    let parts = layout.decompose(layout.compose(1, 2, 3, 4).unwrap());
+    let json = serde_json::to_string(&parts).unwrap();
+    assert_eq!(
+        serde_json::from_str::<crate::DecomposedSnowflake128>(&json).unwrap(),
+        parts
+    );
+}
+
+// --- Sequence start tests ---
+
+#[test]
+fn test_sequence_start_step() -> Result<(), BoxDynError> {
+    use crate::{Layout, SequenceStart};
+    let clock = crate::time::ManualClock::new(TEST_EPOCH);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .sequence_start(SequenceStart::Step(5))
+        .finalize()?;
+
+    let mut starts = Vec::new();
+    let mut last = None;
+    for _ in 0..8 {
+        clock.advance(1);
+        let ids = sf.next_ids(2)?;
+        let parts = sf.decompose(ids[0]);
+        assert_eq!(parts.sequence, (parts.time * 5) & 7);
+        assert_eq!(sf.decompose(ids[1]).sequence, parts.sequence + 1);
+        assert!(last < Some(ids[0]) && ids[0] < ids[1]);
+        last = Some(ids[1]);
+        starts.push(parts.sequence);
+    }
+    // An odd step visits every start in the lower half.
+    starts.sort_unstable();
+    assert_eq!(starts, (0..8).collect::<Vec<_>>());
+
+    assert!(matches!(
+        Snowflake::builder()
+            .time_source(clock)
+            .start_time(TEST_EPOCH)
+            .machine_id(&|| Ok(1))
+            .data_center_id(&|| Ok(0))
+            .sequence_start(SequenceStart::Step(4))
+            .finalize(),
+        Err(Error::InvalidSequenceStep(4))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_sequence_start_random() -> Result<(), BoxDynError> {
+    use crate::{EntropySource, Layout, SequenceStart};
+    struct Fixed;
+    impl EntropySource for Fixed {
+        fn next_u64(&self) -> u64 {
+            u64::MAX
+        }
+    }
+
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 1);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .sequence_start(SequenceStart::random_with(Fixed))
+        .finalize()?;
+
+    // The start is masked to the lower half, leaving the upper half for the tick.
+    let ids = sf.next_ids(9)?;
+    let sequences: Vec<_> = ids.iter().map(|id| sf.decompose(*id).sequence).collect();
+    assert_eq!(sequences, (7..16).collect::<Vec<_>>());
+    assert!(matches!(sf.try_next_id(), Err(Error::WouldBlock { .. })));
+    clock.advance(1);
+    let id = sf.next_id()?;
+    assert_eq!(sf.decompose(id).sequence, 7);
+    assert!(id > ids[8]);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sequence_start_random_spread() -> Result<(), BoxDynError> {
+    use crate::SequenceStart;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .sequence_start(SequenceStart::random())
+        .finalize()?;
+    let mut starts = HashSet::new();
+    for _ in 0..64 {
+        clock.advance(1);
+        let sequence = sf.decompose(sf.next_id()?).sequence;
+        assert!(sequence < 1 << 11);
+        starts.insert(sequence);
+    }
+    assert!(starts.len() > 1, "random starts should vary between ticks");
+    Ok(())
+}
+
+// --- Hybrid logical clock tests ---
+
+#[test]
+fn test_hybrid_borrows_logical_ticks() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .clock_drift_strategy(ClockDriftStrategy::Hybrid)
+        .finalize()?;
+
+    // A frozen clock never blocks: exhausted ticks borrow the next one.
+    let ids: Vec<_> = (0..40)
+        .map(|_| sf.try_next_id())
+        .collect::<Result<_, _>>()?;
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    let times: Vec<_> = ids.iter().map(|id| sf.decompose(*id).time).collect();
+    assert_eq!((times[0], times[16], times[39]), (10, 11, 12));
+
+    // Nor does a clock that moved backward.
+    clock.rewind(5);
+    let id = sf.next_id()?;
+    assert!(id > ids[39]);
+    assert_eq!(sf.decompose(id).time, 12);
+
+    // Once the clock catches up, IDs follow it again.
+    clock.set(TEST_EPOCH + 20);
+    assert_eq!(sf.decompose(sf.next_id()?).time, 20);
+    Ok(())
+}
+
+#[test]
+fn test_hybrid_max_drift() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .clock_drift_strategy(ClockDriftStrategy::Hybrid)
+        .max_clock_drift_ms(1)
+        .finalize()?;
+    assert_eq!(sf.next_ids(32)?.len(), 32);
+    assert!(matches!(
+        sf.next_id(),
+        Err(Error::ClockDriftExceeded {
+            drift_ms: 2,
+            max_ms: 1
+        })
+    ));
+    clock.advance(1);
+    sf.next_id()?;
+    Ok(())
+}
+
+#[test]
+fn test_observe_remote_id() -> Result<(), BoxDynError> {
+    let remote_clock = crate::time::ManualClock::new(TEST_EPOCH + 1_000);
+    let remote = Snowflake::builder()
+        .time_source(remote_clock)
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(31))
+        .data_center_id(&|| Ok(31))
+        .finalize()?;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let local = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .clock_drift_strategy(ClockDriftStrategy::Hybrid)
+        .finalize()?;
+
+    let remote_id = remote.next_id()?;
+    assert!(local.next_id()? < remote_id);
+    local.observe(remote_id)?;
+    let id = local.next_id()?;
+    assert!(id > remote_id);
+    assert_eq!(local.decompose(id).time, 1_001);
+
+    // Observing an older ID changes nothing.
+    local.observe(SnowflakeId::new(0))?;
+    let next = local.next_id()?;
+    assert!(next > id);
+    assert_eq!(local.decompose(next).time, 1_001);
+
+    // The other strategies wait for the clock to pass the observed tick.
+    let waiting = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()?;
+    waiting.observe(remote_id)?;
+    assert!(matches!(
+        waiting.try_next_id(),
+        Err(Error::WouldBlock { .. })
+    ));
+    clock.set(TEST_EPOCH + 1_001);
+    assert!(waiting.try_next_id()? > remote_id);
+    Ok(())
+}
+
+#[test]
+fn test_observe_max_drift() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake::builder()
+        .time_source(clock)
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .clock_drift_strategy(ClockDriftStrategy::Hybrid)
+        .max_clock_drift_ms(100)
+        .finalize()?;
+    let far = SnowflakeId::new(1_000 << 22);
+    assert!(matches!(
+        sf.observe(far),
+        Err(Error::ClockDriftExceeded { drift_ms: 990, .. })
+    ));
+    assert_eq!(sf.decompose(sf.next_id()?).time, 10);
+    sf.observe(SnowflakeId::new(50 << 22))?;
+    assert_eq!(sf.decompose(sf.next_id()?).time, 51);
+    Ok(())
+}
+
+#[test]
+fn test_snowflake128_observe() -> Result<(), BoxDynError> {
+    use crate::Snowflake128;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake128::builder()
+        .time_source(clock)
+        .start_time(TEST_EPOCH)
+        .clock_drift_strategy(ClockDriftStrategy::Hybrid)
+        .node_id(&|| Ok(1))
+        .finalize()?;
+    let remote = sf.layout().compose(500, 3, 9, 0).unwrap();
+    sf.observe(remote)?;
+    let id = sf.next_id()?;
+    assert!(id > remote);
+    assert_eq!(sf.decompose(id).time, 501);
+    Ok(())
+}
+
+// --- Borrow-ahead tests ---
+
+#[test]
+fn test_borrow_ahead() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .max_borrow_ahead_ms(3)
+        .finalize()?;
+
+    // Ticks 10 to 13 are issued without waiting on a frozen clock.
+    let ids: Vec<_> = (0..64)
+        .map(|_| sf.try_next_id())
+        .collect::<Result<_, _>>()?;
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(sf.decompose(ids[63]).time, 13);
+
+    // At the cap it falls back to waiting, until the clock allows one more tick.
+    assert!(matches!(
+        sf.try_next_id(),
+        Err(Error::WouldBlock { wait_ms: 1 })
+    ));
+    clock.advance(1);
+    let id = sf.try_next_id()?;
+    assert_eq!(sf.decompose(id).time, 14);
+    Ok(())
+}
+
+#[test]
+fn test_borrow_ahead_absorbs_small_drift() -> Result<(), BoxDynError> {
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 100);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .max_borrow_ahead_ms(5)
+        .finalize()?;
+    let first = sf.next_id()?;
+    clock.rewind(5);
+    let id = sf.next_id()?;
+    assert!(id > first);
+    assert_eq!(sf.decompose(id).time, 100);
+    clock.rewind(1);
+    assert!(matches!(sf.next_id(), Err(Error::ClockDrift { .. })));
+    Ok(())
+}
+
+#[test]
+fn test_borrow_ahead_disabled_by_default() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake::builder()
+        .time_source(clock)
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .finalize()?;
+    assert_eq!(sf.reserve(16)?.len(), 16);
+    assert!(matches!(
+        sf.try_next_id(),
+        Err(Error::WouldBlock { wait_ms: 1 })
+    ));
+    Ok(())
+}
+
+// --- Sharded generator tests ---
+
+#[test]
+fn test_sharded_lanes() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    let clock = crate::time::ManualClock::new(TEST_EPOCH + 10);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(5))
+        .data_center_id(&|| Ok(0))
+        .finalize_sharded(2)?;
+    assert_eq!(sf.lanes(), 4);
+
+    let mut all = Vec::new();
+    for lane in 0..sf.lanes() {
+        let ids = sf.lane(lane).next_ids(4)?;
+        for id in &ids {
+            let parts = sf.decompose(*id);
+            assert_eq!((parts.time, parts.machine_id), (10, 5));
+            assert_eq!(parts.sequence >> 2, lane as u64);
+            assert_eq!(sf.lane_of(*id), lane);
+        }
+        // Each lane has its own quarter of the sequence range.
+        assert!(matches!(
+            sf.lane(lane).try_next_id(),
+            Err(Error::WouldBlock { .. })
+        ));
+        all.extend(ids);
+    }
+    all.sort_unstable();
+    all.dedup();
+    assert_eq!(all.len(), 16);
+
+    clock.advance(1);
+    let id = sf.next_id()?;
+    assert_eq!(sf.decompose(id).time, 11);
+    Ok(())
+}
+
+#[test]
+fn test_sharded_invalid_lane_bits() {
+    use crate::Layout;
+    let build = |lane_bits| {
+        Snowflake::builder()
+            .time_source(crate::time::ManualClock::new(TEST_EPOCH))
+            .start_time(TEST_EPOCH)
+            .layout(Layout::new(41, 12, 5, 5))
+            .machine_id(&|| Ok(1))
+            .data_center_id(&|| Ok(1))
+            .finalize_sharded(lane_bits)
+    };
+    assert!(build(0).is_ok());
+    assert_eq!(build(10).map(|sf| sf.lanes()).ok(), Some(1024));
+    assert!(matches!(build(11), Err(Error::InvalidLaneBits(11))));
+
+    let narrow = Snowflake::builder()
+        .time_source(crate::time::ManualClock::new(TEST_EPOCH))
+        .start_time(TEST_EPOCH)
+        .layout(Layout::new(41, 3, 0, 4))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(0))
+        .finalize_sharded(3);
+    assert!(matches!(narrow, Err(Error::InvalidLaneBits(3))));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sharded_concurrent_uniqueness() -> Result<(), BoxDynError> {
+    let sf = Snowflake::builder()
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize_sharded(3)?;
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let sf = sf.clone();
+            thread::spawn(move || {
+                (0..2_000)
+                    .map(|_| sf.next_id())
+                    .collect::<Result<Vec<_>, _>>()
+            })
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    for handle in handles {
+        let ids = handle.join().unwrap()?;
+        // A thread sticks to its lane, so its IDs are strictly increasing.
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        let lane = sf.lane_of(ids[0]);
+        assert!(ids.iter().all(|id| sf.lane_of(*id) == lane));
+        for id in ids {
+            assert!(seen.insert(id), "duplicate id {id}");
+        }
+    }
+    assert_eq!(seen.len(), 16_000);
+    Ok(())
+}
+
+// --- Owned builder tests ---
+
+#[test]
+fn test_owned_builder_is_send_static() {
+    fn assert_send_static<T: Send + 'static>() {}
+    assert_send_static::<crate::OwnedBuilder>();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_owned_builder_finalized_elsewhere() -> Result<(), BoxDynError> {
+    use crate::{Builder, OwnedBuilder};
+
+    fn configure(machine: u16, entity: String) -> OwnedBuilder {
+        Builder::owned()
+            .layout(crate::Layout::with_fields(39, 8, 0, 8, &[("entity", 8)]))
+            .machine_id(move || Ok(machine))
+            .data_center_id(|| Ok(0))
+            .check_machine_id(|id| id < 100)
+            .field(entity, 3)
+    }
+
+    let builder = configure(42, String::from("entity"));
+    let spare = builder.clone();
+    let sf = thread::spawn(move || builder.finalize()).join().unwrap()?;
+    let id = sf.next_id()?;
+    let parts = sf.decompose(id);
+    assert_eq!(parts.machine_id, 42);
+    assert_eq!(parts.field("entity"), Some(3));
+    // A clone can be finalized on its own.
+    assert_eq!(spare.finalize()?.decompose(sf.next_id()?).machine_id, 42);
+
+    let rejected = configure(200, String::from("entity")).finalize();
+    assert!(matches!(rejected, Err(Error::CheckMachineIdFailed)));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_owned_builder_propagates_id_errors() {
+    let result = crate::Builder::owned()
+        .machine_id(|| Err("no machine id".into()))
+        .data_center_id(|| Ok(1))
+        .finalize();
+    assert!(matches!(result, Err(Error::MachineIdFailed(_))));
+}
+
+// --- Worker ID resolver tests ---
+
+#[cfg(feature = "std")]
+#[test]
+fn test_statefulset_ordinal_parsing() -> Result<(), BoxDynError> {
+    use crate::worker::StatefulSetOrdinal;
+    assert_eq!(StatefulSetOrdinal::from_hostname("api-7").ordinal()?, 7);
+    assert_eq!(StatefulSetOrdinal::from_hostname("my-api-0").ordinal()?, 0);
+    assert_eq!(StatefulSetOrdinal::from_hostname("12\n").ordinal()?, 12);
+    for bad in ["api", "api-", "api-7a", "api-+7", ""] {
+        assert!(matches!(
+            StatefulSetOrdinal::from_hostname(bad).ordinal(),
+            Err(Error::OrdinalUnavailable { .. })
+        ));
+    }
+    let unset = StatefulSetOrdinal::from_env("SNOWFLAKE_ME_TEST_UNSET_ORDINAL").ordinal();
+    assert!(matches!(
+        unset,
+        Err(Error::OrdinalUnavailable { from, .. }) if from == "SNOWFLAKE_ME_TEST_UNSET_ORDINAL"
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_statefulset_ordinal_resolves_worker_id() -> Result<(), BoxDynError> {
+    use crate::Layout;
+    use crate::worker::{StatefulSetOrdinal, WorkerId, WorkerIdResolver};
+    let layout = Layout::new(41, 12, 2, 3);
+    let resolve = |pod: &str| StatefulSetOrdinal::from_hostname(pod).resolve(&layout);
+
+    // The ordinal fills the machine ID first, then the data center ID.
+    assert_eq!(
+        resolve("api-13")?,
+        WorkerId {
+            data_center_id: 1,
+            machine_id: 5
+        }
+    );
+    assert!(matches!(
+        resolve("api-32"),
+        Err(Error::OrdinalOutOfRange {
+            ordinal: 32,
+            max: 31
+        })
+    ));
+
+    // With a pinned data center ID, the ordinal must fit in the machine ID alone.
+    let pinned = StatefulSetOrdinal::from_hostname("api-7").data_center_id(2);
+    assert_eq!(
+        pinned.resolve(&layout)?,
+        WorkerId {
+            data_center_id: 2,
+            machine_id: 7
+        }
+    );
+    assert!(matches!(
+        pinned.clone().resolve(&Layout::new(41, 12, 2, 2)),
+        Err(Error::OrdinalOutOfRange { ordinal: 7, max: 3 })
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_builder_worker_id_resolver() -> Result<(), BoxDynError> {
+    use crate::worker::StatefulSetOrdinal;
+    let sf = Snowflake::builder()
+        .worker_id_resolver(StatefulSetOrdinal::from_hostname("api-3"))
+        .finalize()?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (0, 3));
+
+    // Closures take precedence over the resolver.
+    let sf = Snowflake::builder()
+        .worker_id_resolver(StatefulSetOrdinal::from_hostname("api-3"))
+        .data_center_id(&|| Ok(9))
+        .finalize()?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (9, 3));
+
+    // The resolver is not consulted when both IDs are set.
+    let sf = Snowflake::builder()
+        .worker_id_resolver(StatefulSetOrdinal::from_hostname("api"))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(2))
+        .finalize()?;
+    assert_eq!(sf.decompose(sf.next_id()?).machine_id, 1);
+
+    let result = Snowflake::builder()
+        .worker_id_resolver(StatefulSetOrdinal::from_hostname("api-1024"))
+        .finalize();
+    assert!(matches!(
+        result,
+        Err(Error::OrdinalOutOfRange { ordinal: 1024, .. })
+    ));
+    Ok(())
+}
+
+// --- Worker ID registry tests ---
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct TestRegistry {
+    acquired: std::sync::atomic::AtomicUsize,
+    healthy: Arc<std::sync::atomic::AtomicBool>,
+    renewals: Arc<std::sync::atomic::AtomicUsize>,
+    released: Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[cfg(feature = "std")]
+struct TestLease {
+    healthy: Arc<std::sync::atomic::AtomicBool>,
+    renewals: Arc<std::sync::atomic::AtomicUsize>,
+    released: Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[cfg(feature = "std")]
+impl crate::WorkerIdRegistry for TestRegistry {
+    fn acquire(&self, _layout: &crate::Layout) -> Result<Box<dyn crate::WorkerLease>, Error> {
+        self.acquired.fetch_add(1, Ordering::Relaxed);
+        self.healthy.store(true, Ordering::Relaxed);
+        Ok(Box::new(TestLease {
+            healthy: Arc::clone(&self.healthy),
+            renewals: Arc::clone(&self.renewals),
+            released: Arc::clone(&self.released),
+        }))
+    }
+}
+
+#[cfg(feature = "std")]
+impl crate::WorkerLease for TestLease {
+    fn worker_id(&self) -> crate::WorkerId {
+        crate::WorkerId {
+            data_center_id: 2,
+            machine_id: 9,
+        }
+    }
+
+    fn renew(&self) -> Result<(), BoxDynError> {
+        self.renewals.fetch_add(1, Ordering::Relaxed);
+        if self.healthy.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err("lease expired".into())
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for TestLease {
+    fn drop(&mut self) {
+        self.released.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_worker_lease_renewal_and_loss() -> Result<(), BoxDynError> {
+    let registry = Arc::new(TestRegistry::default());
+    let clock = crate::time::ManualClock::new(TEST_EPOCH);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .worker_id_registry(Arc::clone(&registry))
+        .lease_renew_interval_ms(100)
+        .finalize_sharded(1)?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (2, 9));
+
+    clock.advance(50);
+    sf.next_id()?;
+    assert_eq!(registry.renewals.load(Ordering::Relaxed), 0);
+    clock.advance(60);
+    sf.lane(1).next_id()?;
+    assert_eq!(registry.renewals.load(Ordering::Relaxed), 1);
+
+    // A failed renewal stops every lane for good.
+    registry.healthy.store(false, Ordering::Relaxed);
+    clock.advance(100);
+    assert!(matches!(sf.lane(0).next_id(), Err(Error::LeaseLost)));
+    registry.healthy.store(true, Ordering::Relaxed);
+    assert!(matches!(sf.lane(1).try_next_id(), Err(Error::LeaseLost)));
+    assert!(matches!(sf.reserve(4), Err(Error::LeaseLost)));
+    assert_eq!(registry.renewals.load(Ordering::Relaxed), 2);
+
+    assert!(!registry.released.load(Ordering::Relaxed));
+    drop(sf);
+    assert!(registry.released.load(Ordering::Relaxed));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_worker_id_registry_precedence() -> Result<(), BoxDynError> {
+    use crate::worker::StatefulSetOrdinal;
+    let registry = Arc::new(TestRegistry::default());
+
+    // A closure could issue IDs outside the lease, so it is rejected before
+    // the registry is asked for one.
+    let machine_id: &dyn Fn() -> Result<u16, BoxDynError> = &|| Ok(4);
+    let data_center_id: &dyn Fn() -> Result<u16, BoxDynError> = &|| Ok(3);
+    for (machine_id, data_center_id) in [
+        (Some(machine_id), None),
+        (None, Some(data_center_id)),
+        (Some(machine_id), Some(data_center_id)),
+    ] {
+        let mut builder = Snowflake::builder().worker_id_registry(Arc::clone(&registry));
+        if let Some(machine_id) = machine_id {
+            builder = builder.machine_id(machine_id);
+        }
+        if let Some(data_center_id) = data_center_id {
+            builder = builder.data_center_id(data_center_id);
+        }
+        assert!(matches!(
+            builder.finalize(),
+            Err(Error::RegistryWithWorkerIdClosure)
+        ));
+    }
+    assert_eq!(registry.acquired.load(Ordering::Relaxed), 0);
+
+    // The registry wins over a resolver.
+    let sf = Snowflake::builder()
+        .worker_id_resolver(StatefulSetOrdinal::from_hostname("api-3"))
+        .worker_id_registry(Arc::clone(&registry))
+        .finalize()?;
+    assert_eq!(registry.acquired.load(Ordering::Relaxed), 1);
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (2, 9));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_worker_registry() -> Result<(), BoxDynError> {
+    use crate::worker::FileWorkerRegistry;
+    use crate::{Layout, WorkerId, WorkerIdRegistry};
+    let dir = std::env::temp_dir().join(format!(
+        "snowflake-me-workers-{}-{}",
+        std::process::id(),
+        crate::time::current_millis()
+    ));
+    let registry = FileWorkerRegistry::new(&dir);
+    let layout = Layout::new(41, 12, 1, 1);
+
+    let leases: Vec<_> = (0..4)
+        .map(|_| registry.acquire(&layout))
+        .collect::<Result<_, _>>()?;
+    let ids: Vec<_> = leases.iter().map(|lease| lease.worker_id()).collect();
+    assert_eq!(
+        ids[3],
+        WorkerId {
+            data_center_id: 1,
+            machine_id: 1
+        }
+    );
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 4);
+    assert!(matches!(
+        registry.acquire(&layout),
+        Err(Error::NoFreeWorkerId { capacity: 4 })
+    ));
+    assert!(leases.iter().all(|lease| lease.renew().is_ok()));
+
+    // Dropping a lease frees its worker ID.
+    let mut leases = leases;
+    leases.remove(2);
+    assert_eq!(registry.acquire(&layout)?.worker_id(), ids[2]);
+
+    // With a pinned data center ID, only machine IDs are handed out.
+    let pinned = registry.clone().data_center_id(7);
+    assert_eq!(
+        pinned.acquire(&layout)?.worker_id(),
+        WorkerId {
+            data_center_id: 7,
+            machine_id: 0
+        }
+    );
+    drop(leases);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_worker_lease_lost_when_lock_file_removed() -> Result<(), BoxDynError> {
+    use crate::worker::FileWorkerRegistry;
+    let dir = std::env::temp_dir().join(format!(
+        "snowflake-me-workers-lost-{}-{}",
+        std::process::id(),
+        crate::time::current_millis()
+    ));
+    let clock = crate::time::ManualClock::new(TEST_EPOCH);
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(TEST_EPOCH)
+        .worker_id_registry(FileWorkerRegistry::new(&dir).data_center_id(1))
+        .finalize()?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (1, 0));
+
+    std::fs::remove_dir_all(&dir)?;
+    clock.advance(1_000);
+    assert!(matches!(sf.next_id(), Err(Error::LeaseLost)));
+    Ok(())
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn test_redis_reply_limits() {
+    use crate::worker::redis::read_reply;
+    let read = |bytes: &[u8]| read_reply(&mut std::io::BufReader::new(bytes), 0);
+
+    assert!(read(b"+OK\r\n").is_ok_and(|reply| !reply.is_nil()));
+    assert!(read(b"$-1\r\n").is_ok_and(|reply| reply.is_nil()));
+    assert!(read(b"*2\r\n$1\r\na\r\n*1\r\n:1\r\n").is_ok());
+
+    let rejected = |bytes: &[u8], reason: &str| match read(bytes) {
+        Err(err) => assert!(err.to_string().contains(reason), "{err}"),
+        Ok(reply) => panic!("accepted {reply:?}"),
+    };
+    // A header line that never ends.
+    rejected(&[b'+'; 1 << 20], "line exceeds");
+    rejected(b"$4294967296\r\n", "bulk reply");
+    rejected(b"*9223372036854775807\r\n", "array reply");
+    rejected(&b"*1\r\n".repeat(100_000), "nested deeper");
+    assert!(read(b"+OK").is_err());
+}
+
+// --- worker_node table tests ---
+
+#[cfg(feature = "std")]
+#[test]
+fn test_disposable_worker_id_wraps_keys() -> Result<(), BoxDynError> {
+    use crate::worker::{DisposableWorkerId, WorkerNode};
+    use crate::{Layout, WorkerId, WorkerIdResolver};
+    let rows = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&rows);
+    let table = move |node: &WorkerNode| -> Result<u64, BoxDynError> {
+        let mut rows = recorded.lock().unwrap();
+        rows.push(node.clone());
+        Ok(rows.len() as u64 + 14)
+    };
+    let resolver = DisposableWorkerId::new(table)
+        .host_name("db-host")
+        .port("9000");
+    let layout = Layout::new(41, 12, 1, 3);
+
+    // Keys 15, 16 and 17 of a 16-ID layout.
+    let ids: Vec<_> = (0..3)
+        .map(|_| resolver.resolve(&layout))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        ids,
+        [
+            WorkerId {
+                data_center_id: 1,
+                machine_id: 7
+            },
+            WorkerId {
+                data_center_id: 0,
+                machine_id: 0
+            },
+            WorkerId {
+                data_center_id: 0,
+                machine_id: 1
+            },
+        ]
+    );
+    let rows = rows.lock().unwrap();
+    assert_eq!(
+        (rows[0].host_name.as_str(), rows[0].port.as_str()),
+        ("db-host", "9000")
+    );
+    assert!(rows[0].launch_time >= TEST_EPOCH);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_disposable_worker_id_in_builder() -> Result<(), BoxDynError> {
+    use crate::worker::{DisposableWorkerId, WorkerNode};
+    let table = |_: &WorkerNode| -> Result<u64, BoxDynError> { Ok(70) };
+    let sf = Snowflake::builder()
+        .worker_id_resolver(DisposableWorkerId::new(table).data_center_id(4))
+        .finalize()?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (4, 70 % 32));
+
+    // With both closures set the resolver is not consulted, so no row is recorded.
+    let unused = |_: &WorkerNode| -> Result<u64, BoxDynError> { panic!("row inserted") };
+    Snowflake::builder()
+        .worker_id_resolver(DisposableWorkerId::new(unused))
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()?;
+
+    // The row records the system clock, not the generator's time source.
+    let launch_times = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&launch_times);
+    let table = move |node: &WorkerNode| -> Result<u64, BoxDynError> {
+        recorded.lock().unwrap().push(node.launch_time);
+        Ok(1)
+    };
+    let before = crate::time::current_millis();
+    Snowflake::builder()
+        .time_source(crate::time::ManualClock::new(TEST_EPOCH))
+        .start_time(TEST_EPOCH)
+        .worker_id_resolver(DisposableWorkerId::new(table))
+        .finalize()?;
+    let launch_time = launch_times.lock().unwrap()[0];
+    assert!((before..=crate::time::current_millis()).contains(&launch_time));
+
+    let failing = |_: &WorkerNode| -> Result<u64, BoxDynError> { Err("database is down".into()) };
+    let result = Snowflake::builder()
+        .worker_id_resolver(DisposableWorkerId::new(failing))
+        .finalize();
+    assert!(matches!(result, Err(Error::WorkerIdRegistryFailed(_))));
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_worker_node_table() -> Result<(), BoxDynError> {
+    use crate::worker::{DisposableWorkerId, SqliteWorkerNodeTable};
+    let path = std::env::temp_dir().join(format!(
+        "snowflake-me-worker-node-{}-{}.db",
+        std::process::id(),
+        crate::time::current_millis()
+    ));
+    let mut machine_ids = Vec::new();
+    for _ in 0..3 {
+        // A new connection per start, like separate processes sharing the database.
+        let table = SqliteWorkerNodeTable::open(&path)?;
+        let sf = Snowflake::builder()
+            .worker_id_resolver(DisposableWorkerId::new(table).port("8080"))
+            .finalize()?;
+        machine_ids.push(sf.decompose(sf.next_id()?).machine_id);
+    }
+    assert_eq!(machine_ids, [1, 2, 3]);
+
+    let conn = rusqlite::Connection::open(&path)?;
+    let (count, port): (i64, String) =
+        conn.query_row("SELECT COUNT(*), MAX(port) FROM worker_node", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_eq!((count, port.as_str()), (3, "8080"));
+    drop(conn);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+// --- IP fallback tests ---
+
+#[cfg(feature = "ip-fallback")]
+#[test]
+fn test_ip_cidr_parse_and_contains() -> Result<(), Error> {
+    use crate::worker::IpCidr;
+    use std::net::IpAddr;
+    let cidr: IpCidr = "10.1.0.0/22".parse()?;
+    assert_eq!(cidr.prefix(), 22);
+    assert_eq!(cidr.to_string(), "10.1.0.0/22");
+    assert!(cidr.contains("10.1.3.255".parse::<IpAddr>().unwrap()));
+    assert!(!cidr.contains("10.1.4.0".parse::<IpAddr>().unwrap()));
+    assert!(!cidr.contains("fd00::1".parse::<IpAddr>().unwrap()));
+
+    let all: IpCidr = "fd00::/0".parse()?;
+    assert!(all.contains("2001:db8::1".parse::<IpAddr>().unwrap()));
+
+    for invalid in ["10.0.0.0/33", "abc/8", "10.0.0.0", "fd00::/129"] {
+        assert!(
+            matches!(invalid.parse::<IpCidr>(), Err(Error::InvalidCidr(s)) if s == invalid),
+            "{invalid}"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ip-fallback")]
+#[test]
+fn test_ip_fallback_octets_fail_instead_of_masking() -> Result<(), Error> {
+    use crate::Layout;
+    use crate::worker::IpFallback;
+    let fallback = IpFallback::new();
+    let layout = Layout::default();
+
+    let worker_id = fallback.derive("10.0.1.5".parse().unwrap(), 16, &layout)?;
+    assert_eq!((worker_id.data_center_id, worker_id.machine_id), (1, 5));
+    let worker_id = fallback.derive("fd00::3:7".parse().unwrap(), 64, &layout)?;
+    assert_eq!((worker_id.data_center_id, worker_id.machine_id), (3, 7));
+
+    // 33 needs 6 bits; the old fallback would have used 33 & 31 = 1.
+    let addr = "10.0.33.5".parse().unwrap();
+    assert!(matches!(
+        fallback.derive(addr, 16, &layout),
+        Err(Error::IpAddressOverflow { addr: a, needed: 6, available: 5 }) if a == addr
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "ip-fallback")]
+#[test]
+fn test_ip_fallback_low_bits() -> Result<(), Error> {
+    use crate::Layout;
+    use crate::worker::{IpDerivation, IpFallback};
+    let fallback = IpFallback::new().derivation(IpDerivation::LowBits);
+    let layout = Layout::default();
+
+    // 10.1.2.37 in a /22: host bits 0b10_0010_0101, split 5 / 5.
+    let worker_id = fallback.derive("10.1.2.37".parse().unwrap(), 22, &layout)?;
+    assert_eq!((worker_id.data_center_id, worker_id.machine_id), (17, 5));
+
+    // A /16 has 16 host bits, more than the 10 the layout holds.
+    assert!(matches!(
+        fallback.derive("10.1.2.37".parse().unwrap(), 16, &layout),
+        Err(Error::IpAddressOverflow {
+            needed: 16,
+            available: 10,
+            ..
+        })
+    ));
+
+    // A wider layout takes the whole host part.
+    let wide = Layout::new(41, 6, 8, 8);
+    let worker_id = fallback.derive("10.1.2.37".parse().unwrap(), 16, &wide)?;
+    assert_eq!((worker_id.data_center_id, worker_id.machine_id), (2, 37));
+    Ok(())
+}
+
+#[cfg(feature = "ip-fallback")]
+#[test]
+fn test_ip_fallback_hash_is_stable_and_in_range() -> Result<(), Error> {
+    use crate::Layout;
+    use crate::worker::{IpDerivation, IpFallback};
+    let fallback = IpFallback::new().derivation(IpDerivation::Hash);
+    let layout = Layout::new(41, 12, 2, 3);
+    let mut seen = std::collections::HashSet::new();
+    for host in 0..=255u8 {
+        let addr = std::net::IpAddr::from([10, 200, 77, host]);
+        let worker_id = fallback.derive(addr, 8, &layout)?;
+        assert!(worker_id.data_center_id <= 3 && worker_id.machine_id <= 7);
+        assert_eq!(fallback.derive(addr, 8, &layout)?, worker_id);
+        seen.insert(worker_id);
+    }
+    // 256 addresses spread over all 32 worker IDs.
+    assert_eq!(seen.len(), 32);
+    Ok(())
+}
+
+#[cfg(feature = "ip-fallback")]
+#[test]
+fn test_ip_fallback_filters_without_match() {
+    use crate::worker::IpFallback;
+    let no_interface = IpFallback::new().interface("no-such-interface0");
+    let result = Snowflake::builder()
+        .worker_id_resolver(no_interface)
+        .finalize();
+    assert!(matches!(result, Err(Error::NoPrivateIP)));
+
+    let no_network = IpFallback::new().cidr("203.0.113.0/24".parse().unwrap());
+    let result = Snowflake::builder()
+        .worker_id_resolver(no_network)
+        .finalize();
+    assert!(matches!(result, Err(Error::NoPrivateIP)));
+}
+
 // --- no_std verification ---
 //
 // The crate must compile with `--no-default-features` (verified by CI).
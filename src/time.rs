@@ -7,47 +7,345 @@
 // except according to those terms.
 
 //! Time source abstraction supporting both `std` and `no_std` environments.
+//!
+//! Every [`Snowflake`] reads the clock through its own
+//! [`TimeSource`], configured with [`Builder::time_source`](crate::Builder::time_source).
+//! With the `std` feature the default is [`SystemClock`]; `no_std` builds must
+//! always supply one (for example a wrapper around an RTC driver).
 
 #[cfg(feature = "std")]
 use jiff::Timestamp;
 
-/// Get current time in milliseconds since Unix epoch (std version).
-#[cfg(feature = "std")]
-pub(crate) fn current_millis() -> i64 {
-    Timestamp::now().as_millisecond()
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+#[cfg(any(feature = "std", feature = "testkit", test))]
+use core::sync::atomic::{AtomicI64, Ordering};
+
+/// A source of wall-clock time for the generator.
+///
+/// Implementations must be cheap to call: [`Snowflake::next_id`](crate::Snowflake::next_id)
+/// reads the clock at least once per generated ID.
+pub trait TimeSource: Send + Sync {
+    /// Returns the current time in milliseconds since the Unix epoch.
+    fn current_millis(&self) -> i64;
 }
 
-// --- no_std time source ---
+impl<T: TimeSource + ?Sized> TimeSource for Arc<T> {
+    fn current_millis(&self) -> i64 {
+        (**self).current_millis()
+    }
+}
 
-#[cfg(not(feature = "std"))]
-use core::sync::atomic::{AtomicI64, Ordering};
+impl<T: TimeSource + ?Sized> TimeSource for Box<T> {
+    fn current_millis(&self) -> i64 {
+        (**self).current_millis()
+    }
+}
 
-/// Global time source for no_std environments.
+impl<T: TimeSource + ?Sized> TimeSource for &T {
+    fn current_millis(&self) -> i64 {
+        (**self).current_millis()
+    }
+}
+
+/// The length of one tick of the timestamp field.
+///
+/// The generator divides the time elapsed since the start time by this unit, so
+/// coarser units make a time field of a given bit length last longer at the cost
+/// of fewer IDs per second. Defaults to [`TimeUnit::MILLISECOND`].
+///
+/// # Example
 ///
-/// Must be set via [`set_time_source`] before generating IDs.
-#[cfg(not(feature = "std"))]
-static NO_STD_TIME_SOURCE: AtomicI64 = AtomicI64::new(0);
+/// ```rust
+/// use snowflake_me::TimeUnit;
+///
+/// const FIVE_MS: TimeUnit = TimeUnit::from_millis(5);
+/// assert_eq!(FIVE_MS.as_millis(), 5);
+/// assert_eq!(TimeUnit::CENTISECOND.as_millis(), 10);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeUnit(u64);
+
+impl TimeUnit {
+    /// One millisecond per tick (the classic Snowflake layout).
+    pub const MILLISECOND: Self = Self(1);
+    /// Ten milliseconds per tick (the Sonyflake layout).
+    pub const CENTISECOND: Self = Self(10);
+    /// One second per tick.
+    pub const SECOND: Self = Self(1_000);
+
+    /// Create a custom unit of `millis` milliseconds per tick.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `millis` is zero or larger than `i64::MAX`.
+    #[must_use]
+    pub const fn from_millis(millis: u64) -> Self {
+        assert!(
+            millis > 0 && millis <= i64::MAX as u64,
+            "time unit must be between 1 and i64::MAX milliseconds"
+        );
+        Self(millis)
+    }
 
-/// Get current time in milliseconds (no_std version).
+    /// Returns the length of one tick in milliseconds.
+    #[must_use]
+    pub const fn as_millis(self) -> u64 {
+        self.0
+    }
+
+    /// Convert a duration in ticks to milliseconds, saturating on overflow.
+    pub(crate) const fn ticks_to_millis(self, ticks: u64) -> u64 {
+        ticks.saturating_mul(self.0)
+    }
+}
+
+impl Default for TimeUnit {
+    fn default() -> Self {
+        Self::MILLISECOND
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TimeUnit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TimeUnit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let millis = u64::deserialize(deserializer)?;
+        if millis == 0 || millis > i64::MAX as u64 {
+            return Err(serde::de::Error::custom("time unit out of range"));
+        }
+        Ok(TimeUnit(millis))
+    }
+}
+
+/// The system wall clock, read via `jiff::Timestamp::now()`.
 ///
-/// Returns the value last set via [`set_time_source`].
-#[cfg(not(feature = "std"))]
+/// This is the default time source when the `std` feature is enabled.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl TimeSource for SystemClock {
+    fn current_millis(&self) -> i64 {
+        current_millis()
+    }
+}
+
+/// A clock anchored to the wall clock once and then driven by `std::time::Instant`.
+///
+/// The wall time is read when the clock is created; afterwards, time advances
+/// with the monotonic clock, so NTP steps or manual `date` changes cannot make it
+/// go backwards. With [`MonotonicClock::with_slew`] it also re-synchronizes with
+/// the wall clock at a bounded rate.
+///
+/// Usually created through [`Builder::clock_mode`](crate::Builder::clock_mode).
+#[cfg(feature = "std")]
+pub struct MonotonicClock {
+    wall: Arc<dyn TimeSource>,
+    anchor_millis: i64,
+    anchor: std::time::Instant,
+    max_slew_ppm: Option<u32>,
+    /// Correction applied on top of the monotonic elapsed time, in microseconds.
+    offset_micros: AtomicI64,
+    /// Monotonic elapsed time of the last wall-clock sync, in microseconds.
+    last_sync_micros: AtomicI64,
+    /// Largest value returned so far.
+    last_millis: AtomicI64,
+}
+
+/// Minimum monotonic time between two wall-clock reads in slew mode.
+#[cfg(feature = "std")]
+const SLEW_SYNC_INTERVAL_MICROS: i64 = 1_000;
+
+/// Upper bound on the slew rate, which keeps the corrected clock strictly advancing.
+#[cfg(feature = "std")]
+const MAX_SLEW_PPM: u32 = 500_000;
+
+#[cfg(feature = "std")]
+impl MonotonicClock {
+    /// Anchor a new clock to the current reading of `wall`.
+    #[must_use]
+    pub fn new<T: TimeSource + 'static>(wall: T) -> Self {
+        Self::anchored(Arc::new(wall), None)
+    }
+
+    /// Anchor a new clock to `wall` and re-synchronize with it at no more than
+    /// `max_ppm` parts per million (clamped to `500_000`).
+    #[must_use]
+    pub fn with_slew<T: TimeSource + 'static>(wall: T, max_ppm: u32) -> Self {
+        Self::anchored(Arc::new(wall), Some(max_ppm))
+    }
+
+    pub(crate) fn anchored(wall: Arc<dyn TimeSource>, max_slew_ppm: Option<u32>) -> Self {
+        let anchor_millis = wall.current_millis();
+        Self {
+            wall,
+            anchor_millis,
+            anchor: std::time::Instant::now(),
+            max_slew_ppm: max_slew_ppm.map(|ppm| ppm.min(MAX_SLEW_PPM)),
+            offset_micros: AtomicI64::new(0),
+            last_sync_micros: AtomicI64::new(0),
+            last_millis: AtomicI64::new(anchor_millis),
+        }
+    }
+
+    /// Move the offset towards the wall clock by at most the allowed slew.
+    fn slew(&self, elapsed_micros: i64, max_ppm: u32) -> i64 {
+        let offset = self.offset_micros.load(Ordering::Acquire);
+        let last_sync = self.last_sync_micros.load(Ordering::Relaxed);
+        let since_sync = elapsed_micros - last_sync;
+        if since_sync < SLEW_SYNC_INTERVAL_MICROS
+            || self
+                .last_sync_micros
+                .compare_exchange(
+                    last_sync,
+                    elapsed_micros,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+        {
+            return offset;
+        }
+
+        let wall_micros = (self.wall.current_millis() - self.anchor_millis) * 1_000;
+        let target = wall_micros - elapsed_micros;
+        let max_step = since_sync * i64::from(max_ppm) / 1_000_000;
+        let offset = offset + (target - offset).clamp(-max_step, max_step);
+        self.offset_micros.store(offset, Ordering::Release);
+        offset
+    }
+}
+
+#[cfg(feature = "std")]
+impl TimeSource for MonotonicClock {
+    fn current_millis(&self) -> i64 {
+        let elapsed_micros = self.anchor.elapsed().as_micros() as i64;
+        let offset = match self.max_slew_ppm {
+            Some(max_ppm) => self.slew(elapsed_micros, max_ppm),
+            None => 0,
+        };
+        let millis = self.anchor_millis + (elapsed_micros + offset) / 1_000;
+        self.last_millis
+            .fetch_max(millis, Ordering::AcqRel)
+            .max(millis)
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for MonotonicClock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MonotonicClock")
+            .field("anchor_millis", &self.anchor_millis)
+            .field("max_slew_ppm", &self.max_slew_ppm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Get current time in milliseconds since Unix epoch (std version).
+#[cfg(feature = "std")]
 pub(crate) fn current_millis() -> i64 {
-    NO_STD_TIME_SOURCE.load(Ordering::Relaxed)
+    Timestamp::now().as_millisecond()
 }
 
-/// Set the time source for no_std environments.
+/// A deterministic clock for tests, advanced by hand.
+///
+/// Clones share the same underlying time, so a test can keep one handle while
+/// the generator owns another. The clock starts frozen; [`ManualClock::auto_advance`]
+/// makes every read move it forward, which lets sequence exhaustion and
+/// [`ClockDriftStrategy::Wait`](crate::ClockDriftStrategy::Wait) make progress
+/// without a second thread.
 ///
-/// Must be called periodically (e.g., from a timer interrupt or RTC read)
-/// with the current time in milliseconds since Unix epoch.
+/// Only available with the `testkit` feature.
 ///
 /// # Example
 ///
-/// ```rust,ignore
-/// // In a timer interrupt handler or main loop:
-/// snowflake_me::set_time_source(get_rtc_millis());
+/// ```rust
+/// use snowflake_me::{ClockDriftStrategy, Error, ManualClock, Snowflake};
+///
+/// let clock = ManualClock::new(1_640_995_200_000);
+/// let sf = Snowflake::builder()
+///     .time_source(clock.clone())
+///     .start_time(1_640_995_200_000)
+///     .clock_drift_strategy(ClockDriftStrategy::Error)
+///     .machine_id(&|| Ok(1))
+///     .data_center_id(&|| Ok(1))
+///     .finalize()
+///     .unwrap();
+///
+/// clock.advance(10);
+/// assert_eq!(sf.decompose(sf.next_id().unwrap()).time, 10);
+///
+/// clock.rewind(5);
+/// assert!(matches!(sf.next_id(), Err(Error::ClockDrift { .. })));
 /// ```
-#[cfg(not(feature = "std"))]
-pub fn set_time_source(millis: i64) {
-    NO_STD_TIME_SOURCE.store(millis, Ordering::Relaxed);
+#[cfg(any(test, feature = "testkit"))]
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    state: Arc<ManualClockState>,
+}
+
+#[cfg(any(test, feature = "testkit"))]
+#[derive(Debug, Default)]
+struct ManualClockState {
+    now: AtomicI64,
+    step: AtomicI64,
+}
+
+#[cfg(any(test, feature = "testkit"))]
+impl ManualClock {
+    /// Create a frozen clock reading `millis` milliseconds since the Unix epoch.
+    #[must_use]
+    pub fn new(millis: i64) -> Self {
+        let clock = Self::default();
+        clock.set(millis);
+        clock
+    }
+
+    /// Returns the current time without advancing the clock.
+    #[must_use]
+    pub fn now(&self) -> i64 {
+        self.state.now.load(Ordering::SeqCst)
+    }
+
+    /// Jump to an absolute time in milliseconds since the Unix epoch.
+    pub fn set(&self, millis: i64) {
+        self.state.now.store(millis, Ordering::SeqCst);
+    }
+
+    /// Move the clock forward by `millis` milliseconds.
+    pub fn advance(&self, millis: i64) {
+        self.state.now.fetch_add(millis, Ordering::SeqCst);
+    }
+
+    /// Move the clock backward by `millis` milliseconds.
+    pub fn rewind(&self, millis: i64) {
+        self.state.now.fetch_sub(millis, Ordering::SeqCst);
+    }
+
+    /// Advance the clock by `step` milliseconds after every read.
+    pub fn auto_advance(&self, step: i64) {
+        self.state.step.store(step, Ordering::SeqCst);
+    }
+
+    /// Stop advancing on reads. The clock then only moves when told to.
+    pub fn freeze(&self) {
+        self.auto_advance(0);
+    }
+}
+
+#[cfg(any(test, feature = "testkit"))]
+impl TimeSource for ManualClock {
+    fn current_millis(&self) -> i64 {
+        let step = self.state.step.load(Ordering::SeqCst);
+        self.state.now.fetch_add(step, Ordering::SeqCst)
+    }
 }
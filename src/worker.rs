@@ -0,0 +1,452 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resolving the data center ID and machine ID of a generator from its environment.
+//!
+//! A [`WorkerIdResolver`] set with [`Builder::worker_id_resolver`](crate::Builder::worker_id_resolver)
+//! is asked for the IDs at [`Builder::finalize`](crate::Builder::finalize), after the
+//! layout is known. Explicit `machine_id` / `data_center_id` closures still take
+//! precedence over it, and it takes precedence over `ip-fallback`, which is an
+//! `IpFallback` resolver with the default configuration.
+//!
+//! A [`WorkerIdRegistry`] set with [`Builder::worker_id_registry`](crate::Builder::worker_id_registry)
+//! goes further and hands out a [`WorkerLease`], so that no two running generators
+//! share a worker ID. It replaces the closures rather than yielding to them, so
+//! that no ID is issued outside the lease. The generator renews the lease as it issues IDs, stops with
+//! [`Error::LeaseLost`] once a renewal fails, and releases the lease when dropped.
+
+use crate::error::{BoxDynError, Error};
+use crate::layout::Layout;
+use core::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use alloc::{borrow::Cow, string::String};
+
+#[cfg(feature = "ip-fallback")]
+mod ip;
+#[cfg(feature = "redis")]
+pub(crate) mod redis;
+#[cfg(feature = "std")]
+mod sql;
+#[cfg(feature = "ip-fallback")]
+pub use ip::{IpCidr, IpDerivation, IpFallback};
+#[cfg(feature = "redis")]
+pub use redis::RedisWorkerRegistry;
+#[cfg(feature = "sqlite")]
+pub use sql::SqliteWorkerNodeTable;
+#[cfg(feature = "std")]
+pub use sql::{DisposableWorkerId, WorkerNode, WorkerNodeTable};
+
+/// The data center ID and machine ID of a generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorkerId {
+    /// The data center ID.
+    pub data_center_id: u16,
+    /// The machine ID.
+    pub machine_id: u16,
+}
+
+#[cfg(feature = "std")]
+impl WorkerId {
+    /// The worker ID at `index`, filling the machine ID section first and the
+    /// data center ID section with the bits above it, or only the machine ID
+    /// section if `data_center_id` is pinned.
+    pub(crate) fn from_index(index: u64, layout: &Layout, data_center_id: Option<u16>) -> Self {
+        Self {
+            data_center_id: match data_center_id {
+                Some(data_center_id) => data_center_id,
+                None => (index >> layout.bit_len_machine_id()) as u16,
+            },
+            machine_id: (index & layout.max_machine_id()) as u16,
+        }
+    }
+
+    /// The largest index [`WorkerId::from_index`] accepts.
+    pub(crate) fn max_index(layout: &Layout, data_center_id: Option<u16>) -> u64 {
+        match data_center_id {
+            Some(_) => layout.max_machine_id(),
+            None => {
+                (layout.max_data_center_id() << layout.bit_len_machine_id())
+                    | layout.max_machine_id()
+            }
+        }
+    }
+}
+
+/// A source of the [`WorkerId`] of a generator.
+pub trait WorkerIdResolver: Send + Sync {
+    /// Resolve the IDs for a generator using `layout`.
+    ///
+    /// Both IDs must fit in the layout's data center ID and machine ID sections.
+    fn resolve(&self, layout: &Layout) -> Result<WorkerId, Error>;
+}
+
+impl<T: WorkerIdResolver + ?Sized> WorkerIdResolver for Arc<T> {
+    fn resolve(&self, layout: &Layout) -> Result<WorkerId, Error> {
+        (**self).resolve(layout)
+    }
+}
+
+/// Derives the worker ID from the ordinal of a Kubernetes StatefulSet pod.
+///
+/// Pods of a StatefulSet are named `<name>-<ordinal>` and keep their ordinal
+/// across restarts and rescheduling, unlike their IP address. The ordinal is
+/// read from the `HOSTNAME` environment variable by default, or from another
+/// variable holding either a pod name or a bare number (such as one set from the
+/// `apps.kubernetes.io/pod-index` label).
+///
+/// The ordinal fills the machine ID section first and the data center ID section
+/// with the bits above it, or only the machine ID section when the data center ID
+/// is pinned with [`StatefulSetOrdinal::data_center_id`]. An ordinal that does
+/// not fit returns [`Error::OrdinalOutOfRange`] rather than wrapping around onto
+/// another pod's ID.
+///
+/// ```rust
+/// use snowflake_me::Snowflake;
+/// use snowflake_me::worker::StatefulSetOrdinal;
+///
+/// let sf = Snowflake::builder()
+///     .worker_id_resolver(StatefulSetOrdinal::from_hostname("api-37"))
+///     .finalize()?;
+///
+/// // 37 = 1 << 5 | 5 with the default 5-bit machine ID section.
+/// let parts = sf.decompose(sf.next_id()?);
+/// assert_eq!((parts.data_center_id, parts.machine_id), (1, 5));
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct StatefulSetOrdinal {
+    source: OrdinalSource,
+    data_center_id: Option<u16>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+enum OrdinalSource {
+    Env(Cow<'static, str>),
+    Hostname(String),
+}
+
+#[cfg(feature = "std")]
+impl Default for StatefulSetOrdinal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl StatefulSetOrdinal {
+    /// Read the ordinal from the `HOSTNAME` environment variable.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::from_env("HOSTNAME")
+    }
+
+    /// Read the ordinal from the environment variable `var`.
+    ///
+    /// The variable may hold a pod name (`api-7`) or a bare ordinal (`7`).
+    #[must_use]
+    pub fn from_env(var: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            source: OrdinalSource::Env(var.into()),
+            data_center_id: None,
+        }
+    }
+
+    /// Use the ordinal of the given pod name instead of reading the environment.
+    #[must_use]
+    pub fn from_hostname(hostname: impl Into<String>) -> Self {
+        Self {
+            source: OrdinalSource::Hostname(hostname.into()),
+            data_center_id: None,
+        }
+    }
+
+    /// Pin the data center ID, so that the ordinal only fills the machine ID section.
+    #[must_use]
+    pub fn data_center_id(mut self, data_center_id: u16) -> Self {
+        self.data_center_id = Some(data_center_id);
+        self
+    }
+
+    /// Read and parse the ordinal.
+    ///
+    /// Returns [`Error::OrdinalUnavailable`] if the variable is unset or does not
+    /// end in a number.
+    pub fn ordinal(&self) -> Result<u64, Error> {
+        let (from, value) = match &self.source {
+            OrdinalSource::Env(var) => {
+                let value = std::env::var(var.as_ref()).map_err(|e| Error::OrdinalUnavailable {
+                    from: String::from(var.as_ref()),
+                    reason: e.to_string(),
+                })?;
+                (var.as_ref(), value)
+            }
+            OrdinalSource::Hostname(hostname) => ("hostname", hostname.clone()),
+        };
+        parse_ordinal(&value).ok_or_else(|| Error::OrdinalUnavailable {
+            from: String::from(from),
+            reason: format!("`{value}` does not end in `-<ordinal>`"),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl WorkerIdResolver for StatefulSetOrdinal {
+    fn resolve(&self, layout: &Layout) -> Result<WorkerId, Error> {
+        let ordinal = self.ordinal()?;
+        let max = WorkerId::max_index(layout, self.data_center_id);
+        if ordinal > max {
+            return Err(Error::OrdinalOutOfRange { ordinal, max });
+        }
+        Ok(WorkerId::from_index(ordinal, layout, self.data_center_id))
+    }
+}
+
+/// The number after the last `-` of a pod name, or the whole value if it is a number.
+#[cfg(feature = "std")]
+fn parse_ordinal(value: &str) -> Option<u64> {
+    let digits = value.trim().rsplit('-').next()?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Hands out exclusive leases on worker IDs.
+pub trait WorkerIdRegistry: Send + Sync {
+    /// Acquire a lease on a worker ID that fits `layout` and no other holder has.
+    ///
+    /// Returns [`Error::NoFreeWorkerId`] if every worker ID is taken.
+    fn acquire(&self, layout: &Layout) -> Result<Box<dyn WorkerLease>, Error>;
+}
+
+impl<T: WorkerIdRegistry + ?Sized> WorkerIdRegistry for Arc<T> {
+    fn acquire(&self, layout: &Layout) -> Result<Box<dyn WorkerLease>, Error> {
+        (**self).acquire(layout)
+    }
+}
+
+/// An exclusive lease on a worker ID, released when dropped.
+pub trait WorkerLease: Send + Sync {
+    /// The leased worker ID.
+    fn worker_id(&self) -> WorkerId;
+
+    /// Confirm the lease is still held, extending it if it expires.
+    ///
+    /// Called by the generator at most once per
+    /// [`Builder::lease_renew_interval_ms`](crate::Builder::lease_renew_interval_ms).
+    /// An error means the lease is lost, and the generator stops issuing IDs.
+    fn renew(&self) -> Result<(), BoxDynError>;
+}
+
+/// Renewal state for a generator holding a [`WorkerLease`].
+pub(crate) struct LeaseKeeper {
+    lease: Box<dyn WorkerLease>,
+    /// Wall-clock time (ms) from which the lease is due for renewal.
+    renew_at: AtomicI64,
+    /// Set while a thread is renewing the lease.
+    renewing: AtomicBool,
+    /// Set once a renewal failed; the lease is never used again.
+    lost: AtomicBool,
+    interval_ms: i64,
+}
+
+impl LeaseKeeper {
+    pub(crate) fn new(lease: Box<dyn WorkerLease>, now: i64, interval_ms: i64) -> Self {
+        let interval_ms = interval_ms.max(1);
+        Self {
+            lease,
+            renew_at: AtomicI64::new(now.saturating_add(interval_ms)),
+            renewing: AtomicBool::new(false),
+            lost: AtomicBool::new(false),
+            interval_ms,
+        }
+    }
+
+    /// Fail if the lease is lost, renewing it first if it is due at `now` (ms
+    /// since the Unix epoch). Other threads keep going while one renews.
+    pub(crate) fn check(&self, now: i64) -> Result<(), Error> {
+        if self.lost.load(Ordering::Acquire) {
+            return Err(Error::LeaseLost);
+        }
+        if now >= self.renew_at.load(Ordering::Acquire)
+            && self
+                .renewing
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            if now >= self.renew_at.load(Ordering::Acquire) {
+                match self.lease.renew() {
+                    Ok(()) => self
+                        .renew_at
+                        .store(now.saturating_add(self.interval_ms), Ordering::Release),
+                    Err(_err) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::error!(
+                            worker_id = ?self.lease.worker_id(),
+                            error = %_err,
+                            "worker ID lease lost, no more IDs will be issued"
+                        );
+                        self.lost.store(true, Ordering::Release);
+                    }
+                }
+            }
+            self.renewing.store(false, Ordering::Release);
+        }
+        if self.lost.load(Ordering::Acquire) {
+            return Err(Error::LeaseLost);
+        }
+        Ok(())
+    }
+}
+
+/// A [`WorkerIdRegistry`] for several processes on one host, backed by lock files.
+///
+/// Each worker ID has a file `worker-<data_center_id>-<machine_id>.lock` in the
+/// directory, and a process holds the ID while it holds an exclusive lock on
+/// that file. Locks are released by the OS when the process exits, even after
+/// a crash, so a restarted process can take its ID back. The files themselves
+/// are left in place. Renewal fails if the lock file is deleted or replaced.
+///
+/// Worker IDs are taken in order like [`StatefulSetOrdinal`] ordinals, or only
+/// machine IDs when the data center ID is pinned with
+/// [`FileWorkerRegistry::data_center_id`].
+///
+/// ```rust
+/// use snowflake_me::Snowflake;
+/// use snowflake_me::worker::FileWorkerRegistry;
+///
+/// let dir = std::env::temp_dir().join(format!("snowflake-me-doc-{}", std::process::id()));
+/// let registry = FileWorkerRegistry::new(&dir).data_center_id(3);
+/// let first = Snowflake::builder().worker_id_registry(registry.clone()).finalize()?;
+/// let second = Snowflake::builder().worker_id_registry(registry).finalize()?;
+///
+/// let first = first.decompose(first.next_id()?);
+/// let second = second.decompose(second.next_id()?);
+/// assert_eq!((first.data_center_id, first.machine_id), (3, 0));
+/// assert_eq!((second.data_center_id, second.machine_id), (3, 1));
+/// # drop(std::fs::remove_dir_all(&dir));
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct FileWorkerRegistry {
+    dir: std::path::PathBuf,
+    data_center_id: Option<u16>,
+}
+
+#[cfg(feature = "std")]
+impl FileWorkerRegistry {
+    /// Create a registry keeping its lock files in `dir`.
+    ///
+    /// The directory is created on the first [`acquire`](WorkerIdRegistry::acquire).
+    #[must_use]
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            data_center_id: None,
+        }
+    }
+
+    /// Pin the data center ID, so that only machine IDs are handed out.
+    #[must_use]
+    pub fn data_center_id(mut self, data_center_id: u16) -> Self {
+        self.data_center_id = Some(data_center_id);
+        self
+    }
+
+    /// Returns the directory of the lock files.
+    #[must_use]
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+}
+
+#[cfg(feature = "std")]
+impl WorkerIdRegistry for FileWorkerRegistry {
+    fn acquire(&self, layout: &Layout) -> Result<Box<dyn WorkerLease>, Error> {
+        use std::io::Write;
+
+        let failed = |err: std::io::Error| Error::WorkerIdRegistryFailed(err.into());
+        std::fs::create_dir_all(&self.dir).map_err(failed)?;
+        let max = WorkerId::max_index(layout, self.data_center_id);
+        for index in 0..=max {
+            let worker_id = WorkerId::from_index(index, layout, self.data_center_id);
+            let path = self.dir.join(format!(
+                "worker-{}-{}.lock",
+                worker_id.data_center_id, worker_id.machine_id
+            ));
+            let mut file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .map_err(failed)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(std::fs::TryLockError::WouldBlock) => continue,
+                Err(std::fs::TryLockError::Error(err)) => return Err(failed(err)),
+            }
+            // Record the holder for operators; the lock is what counts.
+            file.set_len(0).map_err(failed)?;
+            writeln!(file, "{}", std::process::id()).map_err(failed)?;
+            #[cfg(feature = "tracing")]
+            tracing::info!(?worker_id, path = %path.display(), "worker ID lease acquired");
+            return Ok(Box::new(FileLease {
+                file,
+                path,
+                worker_id,
+            }));
+        }
+        Err(Error::NoFreeWorkerId { capacity: max + 1 })
+    }
+}
+
+/// A lease of a [`FileWorkerRegistry`]: the locked file.
+#[cfg(feature = "std")]
+struct FileLease {
+    file: std::fs::File,
+    path: std::path::PathBuf,
+    worker_id: WorkerId,
+}
+
+#[cfg(feature = "std")]
+impl WorkerLease for FileLease {
+    fn worker_id(&self) -> WorkerId {
+        self.worker_id
+    }
+
+    fn renew(&self) -> Result<(), BoxDynError> {
+        let on_disk = std::fs::metadata(&self.path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let held = self.file.metadata()?;
+            if (on_disk.dev(), on_disk.ino()) != (held.dev(), held.ino()) {
+                return Err(format!("lock file {} was replaced", self.path.display()).into());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = on_disk;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for FileLease {
+    fn drop(&mut self) {
+        // Closing the file releases the lock too; unlock first to be explicit.
+        let _ = self.file.unlock();
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{WorkerId, WorkerIdResolver};
+use crate::error::Error;
+use crate::layout::Layout;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// How an [`IpFallback`] turns an address into a [`WorkerId`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpDerivation {
+    /// The third and fourth octets of an IPv4 address, or the last two segments
+    /// of an IPv6 address, as the data center ID and machine ID.
+    ///
+    /// Fails if either does not fit its section. This is the default, and gives
+    /// the same IDs as earlier versions whenever they fit.
+    #[default]
+    Octets,
+    /// The low bits of the address fill the machine ID section, then the data
+    /// center ID section.
+    ///
+    /// Fails if the host part of the address (below the prefix of the matching
+    /// CIDR, or of the interface's network without CIDR filters) is wider than
+    /// the two sections, since addresses in the same network could then collide.
+    LowBits,
+    /// A 64-bit FNV-1a hash of the full address, reduced to the two sections.
+    ///
+    /// Any address fits, but distinct addresses may share a worker ID.
+    Hash,
+}
+
+/// An IP network in CIDR notation, such as `10.1.0.0/22` or `fd00::/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// Create a network from an address and a prefix length.
+    ///
+    /// Returns [`Error::InvalidCidr`] if the prefix is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, Error> {
+        if prefix > address_bits(addr) {
+            return Err(Error::InvalidCidr(format!("{addr}/{prefix}")));
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    /// The address the network was created with.
+    #[must_use]
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length in bits.
+    #[must_use]
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether `ip` is in the network.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let host_bits = address_bits(self.addr) - self.prefix;
+        match (self.addr, ip) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                (address_value(self.addr) ^ address_value(ip))
+                    .checked_shr(u32::from(host_bits))
+                    .unwrap_or(0)
+                    == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+impl core::str::FromStr for IpCidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidCidr(String::from(s));
+        let (addr, prefix) = s.split_once('/').ok_or_else(invalid)?;
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        Self::new(addr, prefix)
+    }
+}
+
+impl core::fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Derives the worker ID from an address of a local network interface.
+///
+/// This is what the `ip-fallback` feature uses when a generator has no other
+/// source for its IDs, with the default configuration: the first private IPv4
+/// address of any interface that is up and not a loopback (a private IPv6
+/// address if there is none), and [`IpDerivation::Octets`]. Pass a configured
+/// one to [`Builder::worker_id_resolver`](crate::Builder::worker_id_resolver) to
+/// pick the interface by name, only accept addresses in given CIDRs (instead of
+/// private ones) and choose the [`IpDerivation`].
+///
+/// Fails with [`Error::NoPrivateIP`] if no address matches, and with
+/// [`Error::IpAddressOverflow`] if the derivation would lose bits.
+///
+/// ```rust,no_run
+/// use snowflake_me::Snowflake;
+/// use snowflake_me::worker::{IpDerivation, IpFallback};
+///
+/// // Pods in 10.1.0.0/22 have 10 host bits: 5 for the data center ID, 5 for the machine ID.
+/// let sf = Snowflake::builder()
+///     .worker_id_resolver(
+///         IpFallback::new()
+///             .interface("eth0")
+///             .cidr("10.1.0.0/22".parse()?)
+///             .derivation(IpDerivation::LowBits),
+///     )
+///     .finalize()?;
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IpFallback {
+    interface: Option<String>,
+    cidrs: Vec<IpCidr>,
+    derivation: IpDerivation,
+}
+
+impl IpFallback {
+    /// Use the first private address of any interface, with [`IpDerivation::Octets`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only use addresses of the interface with this name, e.g. `eth0`.
+    #[must_use]
+    pub fn interface(mut self, name: impl Into<String>) -> Self {
+        self.interface = Some(name.into());
+        self
+    }
+
+    /// Only use addresses in `cidr`, private or not.
+    ///
+    /// May be called several times; earlier networks are preferred.
+    #[must_use]
+    pub fn cidr(mut self, cidr: IpCidr) -> Self {
+        self.cidrs.push(cidr);
+        self
+    }
+
+    /// Set how the address is turned into the worker ID.
+    #[must_use]
+    pub fn derivation(mut self, derivation: IpDerivation) -> Self {
+        self.derivation = derivation;
+        self
+    }
+
+    /// The matching address and the prefix length of its network.
+    fn find(&self) -> Option<(IpAddr, u8)> {
+        let interfaces = pnet_datalink::interfaces();
+        let networks: Vec<_> = interfaces
+            .iter()
+            .filter(|iface| iface.is_up() && !iface.is_loopback())
+            .filter(|iface| {
+                self.interface
+                    .as_ref()
+                    .is_none_or(|name| iface.name == *name)
+            })
+            .flat_map(|iface| iface.ips.iter())
+            .map(|network| (network.ip(), network.prefix()))
+            .collect();
+        if self.cidrs.is_empty() {
+            let private_v4 = networks.iter().find(|(ip, _)| match ip {
+                IpAddr::V4(ipv4) => is_private_ipv4(ipv4),
+                IpAddr::V6(_) => false,
+            });
+            let private_v6 = || {
+                networks.iter().find(|(ip, _)| match ip {
+                    IpAddr::V4(_) => false,
+                    IpAddr::V6(ipv6) => is_private_ipv6(ipv6),
+                })
+            };
+            return private_v4.or_else(private_v6).copied();
+        }
+        self.cidrs.iter().find_map(|cidr| {
+            networks
+                .iter()
+                .find(|(ip, _)| cidr.contains(*ip))
+                .map(|(ip, _)| (*ip, cidr.prefix()))
+        })
+    }
+
+    /// Derive the worker ID from `ip` in a network with the given prefix length.
+    pub(crate) fn derive(
+        &self,
+        ip: IpAddr,
+        prefix: u8,
+        layout: &Layout,
+    ) -> Result<WorkerId, Error> {
+        let data_center_bits = layout.bit_len_data_center_id();
+        let machine_bits = layout.bit_len_machine_id();
+        let worker_id = match self.derivation {
+            IpDerivation::Octets => {
+                let (data_center_id, machine_id) = match ip {
+                    IpAddr::V4(ipv4) => {
+                        let octets = ipv4.octets();
+                        (u16::from(octets[2]), u16::from(octets[3]))
+                    }
+                    IpAddr::V6(ipv6) => {
+                        let segments = ipv6.segments();
+                        (segments[6], segments[7])
+                    }
+                };
+                for (value, available) in [
+                    (data_center_id, data_center_bits),
+                    (machine_id, machine_bits),
+                ] {
+                    let needed = (u16::BITS - value.leading_zeros()) as u8;
+                    if needed > available {
+                        return Err(Error::IpAddressOverflow {
+                            addr: ip,
+                            needed,
+                            available,
+                        });
+                    }
+                }
+                WorkerId {
+                    data_center_id,
+                    machine_id,
+                }
+            }
+            IpDerivation::LowBits => {
+                let available = data_center_bits + machine_bits;
+                let needed = address_bits(ip) - prefix.min(address_bits(ip));
+                if needed > available {
+                    return Err(Error::IpAddressOverflow {
+                        addr: ip,
+                        needed,
+                        available,
+                    });
+                }
+                let index = address_value(ip) & ((1u128 << available) - 1);
+                WorkerId::from_index(index as u64, layout, None)
+            }
+            IpDerivation::Hash => {
+                let bytes = match ip {
+                    IpAddr::V4(ipv4) => ipv4.octets().to_vec(),
+                    IpAddr::V6(ipv6) => ipv6.octets().to_vec(),
+                };
+                let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                    (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+                });
+                let capacity = WorkerId::max_index(layout, None) + 1;
+                WorkerId::from_index(hash % capacity, layout, None)
+            }
+        };
+        #[cfg(feature = "tracing")]
+        tracing::info!(%ip, ?worker_id, derivation = ?self.derivation, "worker ID derived from IP address");
+        Ok(worker_id)
+    }
+}
+
+impl WorkerIdResolver for IpFallback {
+    fn resolve(&self, layout: &Layout) -> Result<WorkerId, Error> {
+        let (ip, prefix) = self.find().ok_or(Error::NoPrivateIP)?;
+        self.derive(ip, prefix, layout)
+    }
+}
+
+fn address_bits(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn address_value(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ipv4) => u128::from(u32::from(ipv4)),
+        IpAddr::V6(ipv6) => u128::from(ipv6),
+    }
+}
+
+fn is_private_ipv4(ip: &Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    matches!(octets[0], 10)
+        || (octets[0] == 172 && (16..=31).contains(&octets[1]))
+        || (octets[0] == 192 && octets[1] == 168)
+}
+
+fn is_private_ipv6(ip: &Ipv6Addr) -> bool {
+    // fc00::/7 (Unique Local Address)
+    // fe80::/10 (Link-Local Address)
+    (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80
+}
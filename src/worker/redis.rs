@@ -0,0 +1,409 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{WorkerId, WorkerIdRegistry, WorkerLease};
+use crate::error::{BoxDynError, Error};
+use crate::layout::Layout;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Extends the lease if the slot is still ours, or takes it back if it expired
+/// and nobody else claimed it in the meantime.
+const RENEW_SCRIPT: &str = "local v = redis.call('get', KEYS[1]) \
+    if v == ARGV[1] or not v then return redis.call('set', KEYS[1], ARGV[1], 'px', ARGV[2]) end \
+    return false";
+
+/// Deletes the slot only if it is still ours.
+const RELEASE_SCRIPT: &str = "if redis.call('get', KEYS[1]) == ARGV[1] then \
+    return redis.call('del', KEYS[1]) end return 0";
+
+/// Distinguishes the leases taken by one process.
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// The longest bulk string accepted from the server. Replies to the registry's
+/// commands are a few bytes; anything longer is a broken or hostile server.
+const MAX_BULK_LEN: usize = 4096;
+
+/// The longest reply header line accepted, including the trailing `\r\n`.
+const MAX_LINE_LEN: usize = 1024;
+
+/// The most elements accepted in an array reply.
+const MAX_ARRAY_LEN: i64 = 16;
+
+/// The deepest array nesting accepted; the registry's commands never return
+/// nested arrays.
+const MAX_DEPTH: u8 = 2;
+
+/// A [`WorkerIdRegistry`] that claims worker IDs as keys on a Redis-compatible server.
+///
+/// Each worker ID is a key `<prefix>:<data_center_id>:<machine_id>`, claimed with
+/// `SET key token NX PX ttl` by scanning the data center ID × machine ID space
+/// (or only the machine IDs when the data center ID is pinned) for a free one.
+/// Any server speaking RESP with `SET` and `EVAL` works. Renewals extend the key
+/// if it still holds this lease's token, and take it back if it expired without
+/// being claimed by another generator; otherwise the lease is lost. Besides the
+/// renewals made while the generator issues IDs, a background thread renews the
+/// lease every third of its TTL, so an idle generator keeps its worker ID.
+/// Dropping the lease stops the thread and deletes the key if it is still ours.
+///
+/// Requires the `redis` feature. The server is reached over plain TCP.
+///
+/// ```rust,no_run
+/// use snowflake_me::Snowflake;
+/// use snowflake_me::worker::RedisWorkerRegistry;
+///
+/// let sf = Snowflake::builder()
+///     .worker_id_registry(
+///         RedisWorkerRegistry::new("127.0.0.1:6379")
+///             .key_prefix("orders:snowflake")
+///             .ttl_ms(30_000),
+///     )
+///     .lease_renew_interval_ms(5_000)
+///     .finalize()?;
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct RedisWorkerRegistry {
+    addr: String,
+    password: Option<String>,
+    key_prefix: String,
+    ttl_ms: u64,
+    timeout: Duration,
+    data_center_id: Option<u16>,
+}
+
+impl RedisWorkerRegistry {
+    /// Create a registry on the server at `addr` (`host:port`).
+    ///
+    /// Defaults to the key prefix `snowflake:worker`, a 10s lease TTL and a 1s
+    /// network timeout.
+    #[must_use]
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            password: None,
+            key_prefix: String::from("snowflake:worker"),
+            ttl_ms: 10_000,
+            timeout: Duration::from_secs(1),
+            data_center_id: None,
+        }
+    }
+
+    /// Authenticate with `AUTH <password>` on every connection.
+    #[must_use]
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Set the prefix of the slot keys, e.g. one per service.
+    #[must_use]
+    pub fn key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.key_prefix = prefix.into();
+        self
+    }
+
+    /// Set how long a lease lasts without renewal, in milliseconds.
+    ///
+    /// The lease is renewed in the background every third of this. Must be well
+    /// above [`Builder::lease_renew_interval_ms`](crate::Builder::lease_renew_interval_ms)
+    /// and the network timeout.
+    #[must_use]
+    pub fn ttl_ms(mut self, ttl_ms: u64) -> Self {
+        self.ttl_ms = ttl_ms.max(1);
+        self
+    }
+
+    /// Set the connect, read and write timeout of each request.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pin the data center ID, so that only machine IDs are handed out.
+    #[must_use]
+    pub fn data_center_id(mut self, data_center_id: u16) -> Self {
+        self.data_center_id = Some(data_center_id);
+        self
+    }
+
+    fn key(&self, worker_id: WorkerId) -> String {
+        format!(
+            "{}:{}:{}",
+            self.key_prefix, worker_id.data_center_id, worker_id.machine_id
+        )
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let mut last_err = None;
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    stream.set_nodelay(true)?;
+                    let mut conn = Connection(BufReader::new(stream));
+                    if let Some(password) = &self.password {
+                        conn.command(&["AUTH", password])?;
+                    }
+                    return Ok(conn);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address for {}", self.addr),
+            )
+        }))
+    }
+}
+
+impl WorkerIdRegistry for RedisWorkerRegistry {
+    fn acquire(&self, layout: &Layout) -> Result<Box<dyn WorkerLease>, Error> {
+        let failed = |err: io::Error| Error::WorkerIdRegistryFailed(err.into());
+        let mut conn = self.connect().map_err(failed)?;
+        let token = format!(
+            "{}-{}-{}",
+            std::process::id(),
+            crate::time::current_millis(),
+            NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+        );
+        let ttl = self.ttl_ms.to_string();
+        let max = WorkerId::max_index(layout, self.data_center_id);
+        for index in 0..=max {
+            let worker_id = WorkerId::from_index(index, layout, self.data_center_id);
+            let key = self.key(worker_id);
+            let reply = conn
+                .command(&["SET", &key, &token, "NX", "PX", &ttl])
+                .map_err(failed)?;
+            if reply.is_nil() {
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::info!(?worker_id, key, "worker ID lease acquired");
+            let slot = Arc::new(Slot {
+                registry: self.clone(),
+                conn: Mutex::new(Some(conn)),
+                key,
+                token,
+                worker_id,
+                lost: AtomicBool::new(false),
+            });
+            let stop = Arc::new((Mutex::new(false), Condvar::new()));
+            let renewer = std::thread::Builder::new()
+                .name(String::from("snowflake-lease-renewal"))
+                .spawn({
+                    let slot = Arc::clone(&slot);
+                    let stop = Arc::clone(&stop);
+                    move || slot.renew_until_stopped(&stop)
+                })
+                .map_err(failed)?;
+            return Ok(Box::new(RedisLease {
+                slot,
+                stop,
+                renewer: Some(renewer),
+            }));
+        }
+        Err(Error::NoFreeWorkerId { capacity: max + 1 })
+    }
+}
+
+/// A lease of a [`RedisWorkerRegistry`]: a slot key holding our token, renewed
+/// by a background thread until dropped.
+struct RedisLease {
+    slot: Arc<Slot>,
+    /// Set to stop the renewal thread.
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    renewer: Option<JoinHandle<()>>,
+}
+
+/// The slot key of a lease, shared with its renewal thread.
+struct Slot {
+    registry: RedisWorkerRegistry,
+    /// Reconnected on the next request after an I/O error.
+    conn: Mutex<Option<Connection>>,
+    key: String,
+    token: String,
+    worker_id: WorkerId,
+    /// Set once another generator claimed the key; it is never taken back.
+    lost: AtomicBool,
+}
+
+impl Slot {
+    /// Run a script on the slot key, reconnecting once if the connection broke.
+    fn eval(&self, script: &str, ttl: &str) -> io::Result<Reply> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let args = ["EVAL", script, "1", &self.key, &self.token, ttl];
+        if let Some(open) = conn.as_mut()
+            && let Ok(reply) = open.command(&args)
+        {
+            return Ok(reply);
+        }
+        *conn = None;
+        let mut fresh = self.registry.connect()?;
+        let reply = fresh.command(&args)?;
+        *conn = Some(fresh);
+        Ok(reply)
+    }
+
+    fn renew(&self) -> Result<(), BoxDynError> {
+        if self.lost.load(Ordering::Acquire) {
+            return Err(format!("slot {} was claimed by another generator", self.key).into());
+        }
+        if self
+            .eval(RENEW_SCRIPT, &self.registry.ttl_ms.to_string())?
+            .is_nil()
+        {
+            self.lost.store(true, Ordering::Release);
+            return Err(format!("slot {} was claimed by another generator", self.key).into());
+        }
+        Ok(())
+    }
+
+    /// Renew every third of the TTL until `stop` is set or the slot is lost.
+    /// I/O errors are retried on the next round, while the key has not expired.
+    fn renew_until_stopped(&self, stop: &(Mutex<bool>, Condvar)) {
+        let interval = Duration::from_millis((self.registry.ttl_ms / 3).max(1));
+        let (stopped, wake) = stop;
+        let mut stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            stopped = wake
+                .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            if *stopped {
+                return;
+            }
+            if let Err(_err) = self.renew() {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(worker_id = ?self.worker_id, error = %_err, "background lease renewal failed");
+                if self.lost.load(Ordering::Acquire) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl WorkerLease for RedisLease {
+    fn worker_id(&self) -> WorkerId {
+        self.slot.worker_id
+    }
+
+    fn renew(&self) -> Result<(), BoxDynError> {
+        self.slot.renew()
+    }
+}
+
+impl Drop for RedisLease {
+    fn drop(&mut self) {
+        let (stopped, wake) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wake.notify_one();
+        if let Some(renewer) = self.renewer.take() {
+            let _ = renewer.join();
+        }
+        let _ = self.slot.eval(RELEASE_SCRIPT, "0");
+    }
+}
+
+/// A minimal RESP client connection.
+struct Connection(BufReader<TcpStream>);
+
+/// A RESP reply; only what the registry needs to tell apart.
+#[derive(Debug)]
+pub(crate) enum Reply {
+    Nil,
+    Value,
+}
+
+impl Reply {
+    pub(crate) fn is_nil(&self) -> bool {
+        matches!(self, Reply::Nil)
+    }
+}
+
+impl Connection {
+    fn command(&mut self, args: &[&str]) -> io::Result<Reply> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg.as_bytes());
+            request.extend_from_slice(b"\r\n");
+        }
+        self.0.get_mut().write_all(&request)?;
+        read_reply(&mut self.0, 0)
+    }
+}
+
+/// Read one reply, rejecting oversized or too deeply nested ones before
+/// allocating for them. `depth` is 0 for a top-level reply.
+pub(crate) fn read_reply(reader: &mut impl BufRead, depth: u8) -> io::Result<Reply> {
+    let too_large = |what: String| io::Error::new(io::ErrorKind::InvalidData, what);
+    if depth > MAX_DEPTH {
+        return Err(too_large(format!(
+            "reply nested deeper than {MAX_DEPTH} levels"
+        )));
+    }
+    let mut line = Vec::new();
+    io::Read::take(&mut *reader, MAX_LINE_LEN as u64).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        if line.len() == MAX_LINE_LEN {
+            return Err(too_large(format!(
+                "reply line exceeds {MAX_LINE_LEN} bytes"
+            )));
+        }
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let line = String::from_utf8_lossy(&line);
+    let line = line.trim_end_matches(['\r', '\n']);
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("bad reply `{line}`"));
+    let (kind, rest) = line.split_at_checked(1).ok_or_else(invalid)?;
+    match kind {
+        "+" | ":" => Ok(Reply::Value),
+        "_" => Ok(Reply::Nil),
+        "-" => Err(io::Error::other(format!("server error: {rest}"))),
+        "$" => {
+            let len: i64 = rest.parse().map_err(|_| invalid())?;
+            if len < 0 {
+                return Ok(Reply::Nil);
+            }
+            if len > MAX_BULK_LEN as i64 {
+                return Err(too_large(format!(
+                    "bulk reply of {len} bytes exceeds {MAX_BULK_LEN}"
+                )));
+            }
+            let mut data = vec![0; len as usize + 2];
+            reader.read_exact(&mut data)?;
+            Ok(Reply::Value)
+        }
+        "*" => {
+            let len: i64 = rest.parse().map_err(|_| invalid())?;
+            if len < 0 {
+                return Ok(Reply::Nil);
+            }
+            if len > MAX_ARRAY_LEN {
+                return Err(too_large(format!(
+                    "array reply of {len} elements exceeds {MAX_ARRAY_LEN}"
+                )));
+            }
+            for _ in 0..len {
+                read_reply(reader, depth + 1)?;
+            }
+            Ok(Reply::Value)
+        }
+        _ => Err(invalid()),
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{WorkerId, WorkerIdResolver};
+use crate::error::{BoxDynError, Error};
+use crate::layout::Layout;
+
+/// A row of the `worker_node` table, recorded once per generator start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerNode {
+    /// Host name of the process.
+    pub host_name: String,
+    /// Port (or other discriminator) of the process on its host.
+    pub port: String,
+    /// Wall-clock time the row was inserted, in milliseconds since the Unix epoch.
+    ///
+    /// Always read from the system clock, not from the generator's
+    /// [`TimeSource`](crate::TimeSource): the row records when the process
+    /// started for operators, and resolvers run before the generator exists.
+    pub launch_time: i64,
+}
+
+/// A `worker_node` table with an auto-increment key.
+///
+/// Implement it with the database client the service already uses; closures
+/// `Fn(&WorkerNode) -> Result<u64, BoxDynError>` implement it too. For Postgres:
+///
+/// ```sql
+/// CREATE TABLE IF NOT EXISTS worker_node (
+///     id          BIGSERIAL PRIMARY KEY,
+///     host_name   VARCHAR(64) NOT NULL,
+///     port        VARCHAR(64) NOT NULL,
+///     launch_time BIGINT      NOT NULL
+/// );
+/// INSERT INTO worker_node (host_name, port, launch_time) VALUES ($1, $2, $3) RETURNING id;
+/// ```
+pub trait WorkerNodeTable: Send + Sync {
+    /// Insert a row for `node` and return its auto-increment key.
+    fn insert(&self, node: &WorkerNode) -> Result<u64, BoxDynError>;
+}
+
+impl<F> WorkerNodeTable for F
+where
+    F: Fn(&WorkerNode) -> Result<u64, BoxDynError> + Send + Sync,
+{
+    fn insert(&self, node: &WorkerNode) -> Result<u64, BoxDynError> {
+        self(node)
+    }
+}
+
+/// Assigns a fresh worker ID on every start from a `worker_node` table, like
+/// the `DisposableWorkerIdAssigner` of Baidu's UidGenerator.
+///
+/// Each [`Builder::finalize`](crate::Builder::finalize) inserts a row and takes
+/// the new auto-increment key modulo the number of worker IDs the layout holds,
+/// filling the machine ID section first and the data center ID section with the
+/// bits above it (or only the machine ID section with a pinned data center ID).
+/// Keys are never reused, so consecutive starts get distinct worker IDs until
+/// the key wraps around the capacity; size the layout so that a wrapped ID's
+/// previous holder is long gone.
+///
+/// The builder only consults the resolver when at least one of the
+/// `machine_id` / `data_center_id` closures is missing, and every such
+/// `finalize` records a row, even if a closure supplies the other ID. The key
+/// then still spans both sections; with a `data_center_id` closure, pin the same
+/// data center ID here so that the whole key goes to the machine ID.
+///
+/// ```rust
+/// use snowflake_me::Snowflake;
+/// use snowflake_me::worker::{DisposableWorkerId, WorkerNode};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// // Stands in for `INSERT ... RETURNING id`.
+/// let next_key = AtomicU64::new(41);
+/// let table = move |_: &WorkerNode| {
+///     Ok::<_, Box<dyn std::error::Error + Send + Sync>>(next_key.fetch_add(1, Ordering::Relaxed))
+/// };
+///
+/// let sf = Snowflake::builder()
+///     .worker_id_resolver(DisposableWorkerId::new(table).port("8080"))
+///     .finalize()?;
+///
+/// // Key 41 with the default 5-bit machine ID section: 41 = 1 << 5 | 9.
+/// let parts = sf.decompose(sf.next_id()?);
+/// assert_eq!((parts.data_center_id, parts.machine_id), (1, 9));
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct DisposableWorkerId<T> {
+    table: T,
+    host_name: Option<String>,
+    port: Option<String>,
+    data_center_id: Option<u16>,
+}
+
+impl<T: WorkerNodeTable> DisposableWorkerId<T> {
+    /// Assign worker IDs from `table`.
+    ///
+    /// The row's host name defaults to the `HOSTNAME` environment variable and
+    /// its port to the process ID.
+    #[must_use]
+    pub fn new(table: T) -> Self {
+        Self {
+            table,
+            host_name: None,
+            port: None,
+            data_center_id: None,
+        }
+    }
+
+    /// Set the host name recorded in the row.
+    #[must_use]
+    pub fn host_name(mut self, host_name: impl Into<String>) -> Self {
+        self.host_name = Some(host_name.into());
+        self
+    }
+
+    /// Set the port recorded in the row.
+    #[must_use]
+    pub fn port(mut self, port: impl Into<String>) -> Self {
+        self.port = Some(port.into());
+        self
+    }
+
+    /// Pin the data center ID, so that the key only fills the machine ID section.
+    #[must_use]
+    pub fn data_center_id(mut self, data_center_id: u16) -> Self {
+        self.data_center_id = Some(data_center_id);
+        self
+    }
+}
+
+impl<T: WorkerNodeTable> WorkerIdResolver for DisposableWorkerId<T> {
+    fn resolve(&self, layout: &Layout) -> Result<WorkerId, Error> {
+        let node = WorkerNode {
+            host_name: self.host_name.clone().unwrap_or_else(|| {
+                std::env::var("HOSTNAME").unwrap_or_else(|_| String::from("localhost"))
+            }),
+            port: self
+                .port
+                .clone()
+                .unwrap_or_else(|| std::process::id().to_string()),
+            // Deliberately the system clock, see `WorkerNode::launch_time`.
+            launch_time: crate::time::current_millis(),
+        };
+        let key = self
+            .table
+            .insert(&node)
+            .map_err(Error::WorkerIdRegistryFailed)?;
+        let capacity = WorkerId::max_index(layout, self.data_center_id) + 1;
+        let worker_id = WorkerId::from_index(key % capacity, layout, self.data_center_id);
+        #[cfg(feature = "tracing")]
+        tracing::info!(key, ?worker_id, "worker ID assigned from worker_node table");
+        Ok(worker_id)
+    }
+}
+
+/// A [`WorkerNodeTable`] in an SQLite database.
+///
+/// Creates the `worker_node` table if it does not exist. Requires the `sqlite`
+/// feature, which bundles SQLite.
+///
+/// ```rust
+/// use snowflake_me::Snowflake;
+/// use snowflake_me::worker::{DisposableWorkerId, SqliteWorkerNodeTable};
+///
+/// let table = SqliteWorkerNodeTable::open_in_memory()?;
+/// let sf = Snowflake::builder()
+///     .worker_id_resolver(DisposableWorkerId::new(table))
+///     .finalize()?;
+/// assert_eq!(sf.decompose(sf.next_id()?).machine_id, 1);
+/// # Ok::<(), snowflake_me::Error>(())
+/// ```
+#[cfg(feature = "sqlite")]
+pub struct SqliteWorkerNodeTable(std::sync::Mutex<rusqlite::Connection>);
+
+#[cfg(feature = "sqlite")]
+impl SqliteWorkerNodeTable {
+    /// Open (or create) the database file at `path`.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let conn = rusqlite::Connection::open(path)
+            .map_err(|err| Error::WorkerIdRegistryFailed(err.into()))?;
+        Self::new(conn)
+    }
+
+    /// Open a private in-memory database, e.g. for tests.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        let conn = rusqlite::Connection::open_in_memory()
+            .map_err(|err| Error::WorkerIdRegistryFailed(err.into()))?;
+        Self::new(conn)
+    }
+
+    /// Use an open connection.
+    pub fn new(conn: rusqlite::Connection) -> Result<Self, Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS worker_node (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                host_name   TEXT    NOT NULL,
+                port        TEXT    NOT NULL,
+                launch_time INTEGER NOT NULL
+            )",
+        )
+        .map_err(|err| Error::WorkerIdRegistryFailed(err.into()))?;
+        Ok(Self(std::sync::Mutex::new(conn)))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl WorkerNodeTable for SqliteWorkerNodeTable {
+    fn insert(&self, node: &WorkerNode) -> Result<u64, BoxDynError> {
+        let conn = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "INSERT INTO worker_node (host_name, port, launch_time) VALUES (?1, ?2, ?3)",
+            (&node.host_name, &node.port, node.launch_time),
+        )?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl core::fmt::Debug for SqliteWorkerNodeTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SqliteWorkerNodeTable")
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(missing_docs)]
+#![cfg(feature = "tokio")]
+
+use snowflake_me::{ClockDriftStrategy, Error, Snowflake, TimeSource};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+const EPOCH: i64 = 1_640_995_200_000;
+
+struct StepClock(AtomicI64);
+
+impl TimeSource for StepClock {
+    fn current_millis(&self) -> i64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn test_next_id_async_matches_next_id() {
+    let sf = Snowflake::builder()
+        .machine_id(&|| Ok(3))
+        .data_center_id(&|| Ok(4))
+        .finalize()
+        .unwrap();
+
+    let sync_id = sf.next_id().unwrap();
+    let async_id = sf.next_id_async().await.unwrap();
+    assert!(async_id > sync_id);
+
+    let parts = sf.decompose(async_id);
+    assert_eq!(parts.machine_id, 3);
+    assert_eq!(parts.data_center_id, 4);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_next_id_async_sequence_exhaustion() {
+    // Four IDs per millisecond forces most calls through the sleep path.
+    let sf = Snowflake::builder()
+        .bit_len_time(51)
+        .bit_len_sequence(2)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()
+        .unwrap();
+
+    let mut handles = Vec::new();
+    for _ in 0..4 {
+        let sf = sf.clone();
+        handles.push(tokio::spawn(async move {
+            let mut ids = Vec::with_capacity(25);
+            for _ in 0..25 {
+                ids.push(sf.next_id_async().await.unwrap());
+            }
+            ids
+        }));
+    }
+
+    let mut seen = HashSet::new();
+    for handle in handles {
+        let ids = handle.await.unwrap();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        for id in ids {
+            assert!(seen.insert(id), "duplicate ID: {id}");
+        }
+    }
+    assert_eq!(seen.len(), 100);
+}
+
+#[tokio::test]
+async fn test_next_id_async_drift_wait_yields_to_runtime() {
+    let clock = Arc::new(StepClock(AtomicI64::new(EPOCH + 100)));
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(EPOCH)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()
+        .unwrap();
+    let first = sf.next_id().unwrap();
+
+    // On a single-threaded runtime the clock only recovers if the waiting
+    // generator hands the thread back instead of spinning.
+    clock.0.store(EPOCH + 90, Ordering::SeqCst);
+    let recover = {
+        let clock = clock.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            clock.0.store(EPOCH + 101, Ordering::SeqCst);
+        })
+    };
+
+    let next = sf.next_id_async().await.unwrap();
+    recover.await.unwrap();
+    assert!(next > first);
+    assert_eq!(sf.decompose(next).time, 101);
+}
+
+#[tokio::test]
+async fn test_next_id_async_reports_drift_error() {
+    let clock = Arc::new(StepClock(AtomicI64::new(EPOCH + 100)));
+    let sf = Snowflake::builder()
+        .time_source(clock.clone())
+        .start_time(EPOCH)
+        .clock_drift_strategy(ClockDriftStrategy::Error)
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()
+        .unwrap();
+    sf.next_id_async().await.unwrap();
+
+    clock.0.store(EPOCH + 50, Ordering::SeqCst);
+    assert!(matches!(
+        sf.next_id_async().await,
+        Err(Error::ClockDrift {
+            last_time: 100,
+            current_time: 50
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_reserve_async() {
+    let sf = Snowflake::builder()
+        .machine_id(&|| Ok(1))
+        .data_center_id(&|| Ok(1))
+        .finalize()
+        .unwrap();
+
+    assert!(sf.reserve_async(0).await.unwrap().is_empty());
+    let block = sf.reserve_async(10).await.unwrap();
+    assert!(!block.is_empty());
+    assert!(block.len() <= 10);
+    assert!(
+        block
+            .iter()
+            .collect::<Vec<_>>()
+            .windows(2)
+            .all(|p| p[0] < p[1])
+    );
+}
@@ -118,3 +118,27 @@ fn test_metrics_full() {
         mt_generated
     );
 }
+
+#[cfg(feature = "testkit")]
+#[test]
+fn test_metrics_borrow_ahead() {
+    get_snapshotter();
+    let clock = snowflake_me::ManualClock::new(1_700_000_000_000);
+    let sf = Snowflake::builder()
+        .time_source(clock)
+        .start_time(1_600_000_000_000)
+        .layout(snowflake_me::Layout::new(41, 4, 0, 4))
+        .machine_id(&|| Ok(3))
+        .data_center_id(&|| Ok(0))
+        .max_borrow_ahead_ms(10)
+        .finalize()
+        .unwrap();
+
+    // Three ticks' worth of IDs on a frozen clock borrow two ticks.
+    for _ in 0..48 {
+        sf.try_next_id().unwrap();
+    }
+    let lead =
+        gauge_value("snowflake_borrow_ahead_ms").expect("snowflake_borrow_ahead_ms not found");
+    assert_eq!(lead, 2.0);
+}
@@ -1,7 +1,7 @@
 #![allow(missing_docs)]
 
 use proptest::prelude::*;
-use snowflake_me::DecomposedSnowflake;
+use snowflake_me::{DecomposedSnowflake, Layout, SnowflakeId};
 
 proptest! {
     #[test]
@@ -13,6 +13,33 @@ proptest! {
             | decomposed.sequence;
         prop_assert_eq!(id, reconstructed);
     }
+
+    #[test]
+    fn layout_roundtrip(
+        id in 0u64..(1u64 << 63),
+        bit_len_sequence in 0u8..=16,
+        bit_len_data_center_id in 0u8..=16,
+        bit_len_machine_id in 0u8..=16,
+        unused_bits in 0u8..=15,
+    ) {
+        let bit_len_time =
+            63 - unused_bits - bit_len_sequence - bit_len_data_center_id - bit_len_machine_id;
+        let layout = Layout::try_new(
+            bit_len_time,
+            bit_len_sequence,
+            bit_len_data_center_id,
+            bit_len_machine_id,
+        )
+        .unwrap();
+        prop_assert_eq!(layout.width(), 63 - unused_bits);
+        let id = SnowflakeId::new(id & layout.max_id());
+        let parts = layout.decompose(id);
+        prop_assert_eq!(
+            layout.compose(parts.time, parts.sequence, parts.data_center_id, parts.machine_id),
+            Some(id)
+        );
+        prop_assert_eq!(layout.from_packed_bytes(&layout.packed_bytes(id)).unwrap(), id);
+    }
 }
 
 #[cfg(feature = "std")]
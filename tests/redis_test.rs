@@ -0,0 +1,287 @@
+// Copyright 2022 houseme
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(missing_docs)]
+#![cfg(feature = "redis")]
+
+use snowflake_me::worker::RedisWorkerRegistry;
+use snowflake_me::{Error, Layout, Snowflake};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Keys = Arc<Mutex<HashMap<String, (String, Instant)>>>;
+
+/// An in-process stand-in for a Redis server, handling the commands the
+/// registry sends: `AUTH`, `SET NX PX` and the renew / release scripts.
+struct StandIn {
+    addr: SocketAddr,
+    keys: Keys,
+}
+
+impl StandIn {
+    fn start(password: Option<&'static str>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let keys = Keys::default();
+        let shared = Arc::clone(&keys);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let keys = Arc::clone(&shared);
+                thread::spawn(move || serve(stream.unwrap(), &keys, password));
+            }
+        });
+        Self { addr, keys }
+    }
+
+    fn registry(&self) -> RedisWorkerRegistry {
+        RedisWorkerRegistry::new(self.addr.to_string()).key_prefix("test")
+    }
+
+    fn holder(&self, key: &str) -> Option<String> {
+        let keys = self.keys.lock().unwrap();
+        keys.get(key)
+            .filter(|(_, expiry)| *expiry > Instant::now())
+            .map(|(token, _)| token.clone())
+    }
+}
+
+fn serve(stream: TcpStream, keys: &Keys, password: Option<&str>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut authenticated = password.is_none();
+    while let Some(args) = read_command(&mut reader) {
+        let reply = match args[0].as_str() {
+            "AUTH" if Some(args[1].as_str()) == password => {
+                authenticated = true;
+                "+OK\r\n".to_string()
+            }
+            "AUTH" => "-WRONGPASS invalid password\r\n".to_string(),
+            _ if !authenticated => "-NOAUTH Authentication required.\r\n".to_string(),
+            "SET" => {
+                let mut keys = keys.lock().unwrap();
+                let now = Instant::now();
+                let taken = keys.get(&args[1]).is_some_and(|(_, expiry)| *expiry > now);
+                if taken {
+                    "$-1\r\n".to_string()
+                } else {
+                    let ttl = Duration::from_millis(args[5].parse().unwrap());
+                    keys.insert(args[1].clone(), (args[2].clone(), now + ttl));
+                    "+OK\r\n".to_string()
+                }
+            }
+            "EVAL" => {
+                let (script, key, token) = (&args[1], &args[3], &args[4]);
+                let mut keys = keys.lock().unwrap();
+                let now = Instant::now();
+                let current = keys
+                    .get(key)
+                    .filter(|(_, expiry)| *expiry > now)
+                    .map(|(value, _)| value.clone());
+                if script.contains("'del'") {
+                    if current.as_ref() == Some(token) {
+                        keys.remove(key);
+                        ":1\r\n".to_string()
+                    } else {
+                        ":0\r\n".to_string()
+                    }
+                } else if current.is_none() || current.as_ref() == Some(token) {
+                    let ttl = Duration::from_millis(args[5].parse().unwrap());
+                    keys.insert(key.clone(), (token.clone(), now + ttl));
+                    "+OK\r\n".to_string()
+                } else {
+                    "$-1\r\n".to_string()
+                }
+            }
+            other => format!("-ERR unknown command '{other}'\r\n"),
+        };
+        if writer.write_all(reply.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok().filter(|n| *n > 0)?;
+    let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+    (0..count)
+        .map(|_| {
+            let mut line = String::new();
+            reader.read_line(&mut line).ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut data = vec![0; len + 2];
+            reader.read_exact(&mut data).ok()?;
+            data.truncate(len);
+            String::from_utf8(data).ok()
+        })
+        .collect()
+}
+
+#[test]
+fn test_redis_registry_scans_for_free_slots() -> Result<(), Error> {
+    let server = StandIn::start(None);
+    let layout = Layout::new(41, 12, 1, 1);
+    let generators = (0..4)
+        .map(|_| {
+            Snowflake::builder()
+                .layout(layout)
+                .worker_id_registry(server.registry())
+                .finalize()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ids = Vec::new();
+    for sf in &generators {
+        let parts = sf.decompose(sf.next_id()?);
+        ids.push((parts.data_center_id, parts.machine_id));
+    }
+    assert_eq!(ids, [(0, 0), (0, 1), (1, 0), (1, 1)]);
+    assert!(server.holder("test:1:0").is_some());
+
+    // Every slot is taken.
+    let full = Snowflake::builder()
+        .layout(layout)
+        .worker_id_registry(server.registry())
+        .finalize();
+    assert!(matches!(full, Err(Error::NoFreeWorkerId { capacity: 4 })));
+
+    // Dropping a generator releases its slot for the next one.
+    let mut generators = generators;
+    generators.remove(1);
+    assert_eq!(server.holder("test:0:1"), None);
+    let sf = Snowflake::builder()
+        .layout(layout)
+        .worker_id_registry(server.registry())
+        .finalize()?;
+    assert_eq!(sf.decompose(sf.next_id()?).machine_id, 1);
+    Ok(())
+}
+
+#[test]
+fn test_redis_registry_pinned_data_center() -> Result<(), Error> {
+    let server = StandIn::start(None);
+    let sf = Snowflake::builder()
+        .worker_id_registry(server.registry().data_center_id(6))
+        .finalize()?;
+    let parts = sf.decompose(sf.next_id()?);
+    assert_eq!((parts.data_center_id, parts.machine_id), (6, 0));
+    assert!(server.holder("test:6:0").is_some());
+    Ok(())
+}
+
+#[test]
+fn test_redis_lease_lost_to_another_generator() -> Result<(), Error> {
+    let server = StandIn::start(None);
+    let sf = Snowflake::builder()
+        .worker_id_registry(server.registry())
+        .lease_renew_interval_ms(1)
+        .finalize()?;
+    sf.next_id()?;
+
+    server.keys.lock().unwrap().insert(
+        "test:0:0".to_string(),
+        (
+            "someone-else".to_string(),
+            Instant::now() + Duration::from_secs(60),
+        ),
+    );
+    thread::sleep(Duration::from_millis(5));
+    assert!(matches!(sf.next_id(), Err(Error::LeaseLost)));
+
+    // The other holder's slot is left alone on drop.
+    drop(sf);
+    assert_eq!(server.holder("test:0:0").as_deref(), Some("someone-else"));
+    Ok(())
+}
+
+#[test]
+fn test_redis_idle_generator_keeps_its_lease() -> Result<(), Error> {
+    let server = StandIn::start(None);
+    let sf = Snowflake::builder()
+        .worker_id_registry(server.registry().ttl_ms(60))
+        .finalize()?;
+    let token = server.holder("test:0:0").unwrap();
+
+    // No IDs are issued for several TTLs; the background renewal keeps the key.
+    for _ in 0..5 {
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(server.holder("test:0:0").as_ref(), Some(&token));
+    }
+    let other = Snowflake::builder()
+        .worker_id_registry(server.registry().ttl_ms(60))
+        .finalize()?;
+    assert_eq!(other.decompose(other.next_id()?).machine_id, 1);
+
+    // A key that expired unclaimed, e.g. during a network partition, is taken back.
+    server.keys.lock().unwrap().remove("test:0:0");
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(server.holder("test:0:0"), Some(token));
+    sf.next_id()?;
+
+    // Dropping the generator stops the renewal, so the key stays released.
+    drop(sf);
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(server.holder("test:0:0"), None);
+    Ok(())
+}
+
+#[test]
+fn test_redis_registry_rejects_oversized_replies() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            if read_command(&mut reader).is_some() {
+                // Claims a 4 GiB bulk string that never arrives.
+                let _ = stream.write_all(b"$4294967296\r\n");
+            }
+        }
+    });
+
+    let result = Snowflake::builder()
+        .worker_id_registry(RedisWorkerRegistry::new(addr.to_string()))
+        .finalize();
+    assert!(matches!(
+        result,
+        Err(Error::WorkerIdRegistryFailed(err)) if err.to_string().contains("exceeds")
+    ));
+}
+
+#[test]
+fn test_redis_registry_auth_and_connection_errors() -> Result<(), Error> {
+    let server = StandIn::start(Some("secret"));
+    let sf = Snowflake::builder()
+        .worker_id_registry(server.registry().password("secret"))
+        .finalize()?;
+    sf.next_id()?;
+
+    let wrong = Snowflake::builder()
+        .worker_id_registry(server.registry().password("wrong"))
+        .finalize();
+    assert!(matches!(wrong, Err(Error::WorkerIdRegistryFailed(_))));
+    let missing = Snowflake::builder()
+        .worker_id_registry(server.registry())
+        .finalize();
+    assert!(matches!(missing, Err(Error::WorkerIdRegistryFailed(_))));
+
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let unreachable = Snowflake::builder()
+        .worker_id_registry(RedisWorkerRegistry::new(closed.to_string()))
+        .finalize();
+    assert!(matches!(unreachable, Err(Error::WorkerIdRegistryFailed(_))));
+    Ok(())
+}